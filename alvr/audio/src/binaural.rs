@@ -0,0 +1,201 @@
+use std::f32::consts::PI;
+
+// Spherical head model from Brown and Duda, "A structural model for binaural sound synthesis"
+// (1998). It provides the interaural time and level differences, which give the lateral cues. It
+// has no pinna model, so the front/back separation is limited.
+const HEAD_RADIUS_M: f32 = 0.0875;
+const SPEED_OF_SOUND_MPS: f32 = 343.0;
+const MIN_SHADOW_ALPHA: f32 = 0.1;
+const MIN_SHADOW_ANGLE: f32 = 5.0 * PI / 6.0;
+
+// Azimuth of the virtual speaker of each channel in degrees, positive to the right. None for LFE,
+// which is dropped like in the stereo downmix. Layouts follow the WAVEFORMATEXTENSIBLE order.
+fn speaker_azimuths(channels_count: usize) -> Option<Vec<Option<f32>>> {
+    let azimuths = match channels_count {
+        // FL FR FC
+        3 => vec![Some(-30.0), Some(30.0), Some(0.0)],
+        // FL FR BL BR
+        4 => vec![Some(-45.0), Some(45.0), Some(-135.0), Some(135.0)],
+        // FL FR FC LFE BL BR
+        6 => vec![
+            Some(-30.0),
+            Some(30.0),
+            Some(0.0),
+            None,
+            Some(-110.0),
+            Some(110.0),
+        ],
+        // FL FR FC LFE BL BR SL SR
+        8 => vec![
+            Some(-30.0),
+            Some(30.0),
+            Some(0.0),
+            None,
+            Some(-150.0),
+            Some(150.0),
+            Some(-90.0),
+            Some(90.0),
+        ],
+        _ => return None,
+    };
+
+    Some(azimuths)
+}
+
+// Path from a virtual speaker to one ear: a delay followed by the head shadow filter
+struct EarPath {
+    delay_samples: usize,
+    // One-pole one-zero filter, discretized with the bilinear transform
+    b0: f32,
+    b1: f32,
+    a1: f32,
+    last_input: f32,
+    last_output: f32,
+}
+
+impl EarPath {
+    // incidence_angle is the angle between the source and the ear direction, in radians
+    fn new(incidence_angle: f32, sample_rate: f32) -> Self {
+        let head_time = HEAD_RADIUS_M / SPEED_OF_SOUND_MPS;
+
+        // Delay relative to a source right in front of the ear
+        let delay_s = if incidence_angle < PI / 2.0 {
+            head_time * (1.0 - incidence_angle.cos())
+        } else {
+            head_time * (1.0 + incidence_angle - PI / 2.0)
+        };
+
+        // High frequencies are boosted up to 6dB in front of the ear and attenuated behind it
+        let alpha = (1.0 + MIN_SHADOW_ALPHA / 2.0)
+            + (1.0 - MIN_SHADOW_ALPHA / 2.0) * (incidence_angle / MIN_SHADOW_ANGLE * PI).cos();
+        let two_omega0 = 2.0 / head_time;
+        let k = 2.0 * sample_rate;
+        let norm = two_omega0 + k;
+
+        Self {
+            delay_samples: (delay_s * sample_rate).round() as usize,
+            b0: (two_omega0 + alpha * k) / norm,
+            b1: (two_omega0 - alpha * k) / norm,
+            a1: (two_omega0 - k) / norm,
+            last_input: 0.0,
+            last_output: 0.0,
+        }
+    }
+
+    fn filter(&mut self, input: f32) -> f32 {
+        let output = self.b0 * input + self.b1 * self.last_input - self.a1 * self.last_output;
+        self.last_input = input;
+        self.last_output = output;
+
+        output
+    }
+}
+
+// Angle between the source and the ear direction, in [0, PI]
+fn incidence_angle(source_azimuth_deg: f32, ear_azimuth_deg: f32) -> f32 {
+    let difference = (source_azimuth_deg - ear_azimuth_deg).rem_euclid(360.0);
+
+    f32::min(difference, 360.0 - difference).to_radians()
+}
+
+// Renders surround channels as virtual speakers for headphones
+pub struct BinauralRenderer {
+    input_channels_count: usize,
+    // For each input channel, paths to the left and right ear
+    paths: Vec<Option<[EarPath; 2]>>,
+    // Past input frames, long enough for the longest delay
+    history: Vec<Vec<f32>>,
+    history_cursor: usize,
+    gain: f32,
+}
+
+impl BinauralRenderer {
+    // Returns None if the channel layout is not known
+    pub fn new(input_channels_count: usize, sample_rate: u32) -> Option<Self> {
+        let azimuths = speaker_azimuths(input_channels_count)?;
+
+        let paths = azimuths
+            .iter()
+            .map(|azimuth| {
+                azimuth.map(|azimuth| {
+                    [-90.0, 90.0].map(|ear_azimuth| {
+                        EarPath::new(incidence_angle(azimuth, ear_azimuth), sample_rate as _)
+                    })
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let history_size = paths
+            .iter()
+            .flatten()
+            .flat_map(|ear_paths| ear_paths.iter().map(|path| path.delay_samples))
+            .max()
+            .unwrap_or(0)
+            + 1;
+
+        // Same loudness as the stereo downmix for a signal coming from all speakers
+        let speakers_count = azimuths.iter().flatten().count();
+
+        Some(Self {
+            input_channels_count,
+            paths,
+            history: vec![vec![0.0; input_channels_count]; history_size],
+            history_cursor: 0,
+            gain: 2.0 / speakers_count as f32,
+        })
+    }
+
+    // Takes interleaved samples, returns interleaved stereo samples
+    pub fn process(&mut self, samples: &[f32]) -> Vec<f32> {
+        let history_size = self.history.len();
+
+        let mut output = Vec::with_capacity(samples.len() / self.input_channels_count * 2);
+        for frame in samples.chunks_exact(self.input_channels_count) {
+            self.history[self.history_cursor].copy_from_slice(frame);
+
+            let mut ears = [0.0; 2];
+            for (channel, paths) in self.paths.iter_mut().enumerate() {
+                if let Some(paths) = paths {
+                    for (ear, path) in ears.iter_mut().zip(paths) {
+                        let index = (self.history_cursor + history_size - path.delay_samples)
+                            % history_size;
+                        *ear += path.filter(self.history[index][channel]);
+                    }
+                }
+            }
+            output.extend(ears.map(|ear| ear * self.gain));
+
+            self.history_cursor = (self.history_cursor + 1) % history_size;
+        }
+
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn side_speaker_is_louder_and_earlier_on_near_ear() {
+        let mut renderer = BinauralRenderer::new(8, 48000).unwrap();
+
+        // Impulse on the side left channel
+        let mut samples = vec![0.0; 8 * 100];
+        samples[6] = 1.0;
+        let output = renderer.process(&samples);
+
+        let left = output.iter().step_by(2).copied().collect::<Vec<_>>();
+        let right = output
+            .iter()
+            .skip(1)
+            .step_by(2)
+            .copied()
+            .collect::<Vec<_>>();
+        let first_sound = |ear: &[f32]| ear.iter().position(|s| s.abs() > 1e-6).unwrap();
+        let energy = |ear: &[f32]| ear.iter().map(|s| s * s).sum::<f32>();
+
+        assert!(first_sound(&left) < first_sound(&right));
+        assert!(energy(&left) > energy(&right));
+    }
+}
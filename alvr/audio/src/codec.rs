@@ -0,0 +1,234 @@
+use alvr_common::prelude::*;
+use alvr_packets::AudioStreamFormat;
+use alvr_session::{AudioCodecConfig, SurroundAudioMode};
+use cpal::Sample;
+use opus::{Application, Bitrate, Channels, Decoder, Encoder};
+use std::time::{Duration, Instant};
+
+const OPUS_SAMPLE_RATES: [u32; 5] = [8000, 12000, 16000, 24000, 48000];
+const OPUS_DEFAULT_SAMPLE_RATE: u32 = 48000;
+
+// 10ms frames. Shorter frames increase the packet overhead and reduce the compression efficiency
+const OPUS_FRAMES_PER_SECOND: usize = 100;
+
+// Recommended by the Opus documentation
+const MAX_OPUS_PACKET_SIZE: usize = 4000;
+
+// The encoder adds in-band FEC data only if it expects some packet loss
+const FEC_EXPECTED_PACKET_LOSS_PERCENT: i32 = 10;
+
+// The streamer chooses the format and sends it to the client with the stream config. The device
+// sample rate is kept when possible, to avoid resampling. Opus does not support arbitrary sample
+// rates, in that case the audio is resampled to 48 kHz.
+pub fn negotiate_stream_format(
+    codec: &AudioCodecConfig,
+    device_sample_rate: u32,
+    channels_count: u16,
+) -> AudioStreamFormat {
+    let sample_rate = match codec {
+        AudioCodecConfig::Opus { .. } if !OPUS_SAMPLE_RATES.contains(&device_sample_rate) => {
+            OPUS_DEFAULT_SAMPLE_RATE
+        }
+        _ => device_sample_rate,
+    };
+
+    AudioStreamFormat {
+        sample_rate,
+        channels_count,
+    }
+}
+
+// Surround audio is sent as is only if the client can play it and the codec supports it. The
+// layout is implied by the channels count. Otherwise the streamer downmixes it to stereo.
+pub fn negotiate_game_audio_channels(
+    mode: &SurroundAudioMode,
+    codec: &AudioCodecConfig,
+    device_channels_count: u16,
+    client_channels_count: u16,
+) -> u16 {
+    if device_channels_count <= 2 {
+        return 2;
+    }
+
+    if matches!(mode, SurroundAudioMode::Passthrough) {
+        if !matches!(codec, AudioCodecConfig::Pcm) {
+            warn!("Surround audio passthrough requires the PCM codec. Using binaural downmix");
+        } else if client_channels_count < device_channels_count {
+            warn!("The client cannot play {device_channels_count} audio channels. Using binaural downmix");
+        } else {
+            return device_channels_count;
+        }
+    }
+
+    2
+}
+
+fn opus_channels(channels_count: u16) -> StrResult<Channels> {
+    match channels_count {
+        1 => Ok(Channels::Mono),
+        2 => Ok(Channels::Stereo),
+        _ => fmt_e!("Opus supports only mono and stereo audio"),
+    }
+}
+
+fn opus_frame_samples_count(format: AudioStreamFormat) -> usize {
+    format.sample_rate as usize / OPUS_FRAMES_PER_SECOND * format.channels_count as usize
+}
+
+pub enum AudioEncoder {
+    Pcm,
+    Opus {
+        encoder: Encoder,
+        frame_samples_count: usize,
+        pending_samples: Vec<f32>,
+        // Capture time of the first pending sample
+        pending_instant: Instant,
+    },
+}
+
+impl AudioEncoder {
+    pub fn new(codec: &AudioCodecConfig, format: AudioStreamFormat) -> StrResult<Self> {
+        match codec {
+            AudioCodecConfig::Pcm => Ok(Self::Pcm),
+            AudioCodecConfig::Opus {
+                bitrate_kbps,
+                in_band_fec,
+            } => {
+                // LowDelay would use only the CELT layer, which does not support FEC
+                let mut encoder = Encoder::new(
+                    format.sample_rate,
+                    opus_channels(format.channels_count)?,
+                    Application::Audio,
+                )
+                .map_err(err!())?;
+                encoder
+                    .set_bitrate(Bitrate::Bits(*bitrate_kbps as i32 * 1000))
+                    .map_err(err!())?;
+                encoder.set_inband_fec(*in_band_fec).map_err(err!())?;
+                if *in_band_fec {
+                    encoder
+                        .set_packet_loss_perc(FEC_EXPECTED_PACKET_LOSS_PERCENT)
+                        .map_err(err!())?;
+                }
+
+                Ok(Self::Opus {
+                    encoder,
+                    frame_samples_count: opus_frame_samples_count(format),
+                    pending_samples: vec![],
+                    pending_instant: Instant::now(),
+                })
+            }
+        }
+    }
+
+    // Takes interleaved samples in chunks of any size, with the capture time of the first one. PCM
+    // is sent as i16 in native endianness. Opus samples are buffered until a whole frame is
+    // available, so zero or more packets are returned, each with the capture time of its first
+    // sample.
+    pub fn encode(
+        &mut self,
+        samples: &[f32],
+        capture_instant: Instant,
+    ) -> StrResult<Vec<(Instant, Vec<u8>)>> {
+        match self {
+            Self::Pcm => Ok(vec![(
+                capture_instant,
+                samples
+                    .iter()
+                    .flat_map(|sample| sample.to_sample::<i16>().to_ne_bytes())
+                    .collect(),
+            )]),
+            Self::Opus {
+                encoder,
+                frame_samples_count,
+                pending_samples,
+                pending_instant,
+            } => {
+                if pending_samples.is_empty() {
+                    *pending_instant = capture_instant;
+                }
+                pending_samples.extend(samples);
+
+                let frame_duration = Duration::from_secs(1) / OPUS_FRAMES_PER_SECOND as u32;
+
+                let mut packets = vec![];
+                while pending_samples.len() >= *frame_samples_count {
+                    packets.push((
+                        *pending_instant,
+                        encoder
+                            .encode_vec_float(
+                                &pending_samples[..*frame_samples_count],
+                                MAX_OPUS_PACKET_SIZE,
+                            )
+                            .map_err(err!())?,
+                    ));
+                    pending_samples.drain(..*frame_samples_count);
+                    *pending_instant += frame_duration;
+                }
+
+                Ok(packets)
+            }
+        }
+    }
+}
+
+pub enum AudioDecoder {
+    Pcm,
+    Opus {
+        decoder: Decoder,
+        channels_count: usize,
+        frame_samples_count: usize,
+    },
+}
+
+impl AudioDecoder {
+    pub fn new(codec: &AudioCodecConfig, format: AudioStreamFormat) -> StrResult<Self> {
+        match codec {
+            AudioCodecConfig::Pcm => Ok(Self::Pcm),
+            AudioCodecConfig::Opus { .. } => Ok(Self::Opus {
+                decoder: Decoder::new(format.sample_rate, opus_channels(format.channels_count)?)
+                    .map_err(err!())?,
+                channels_count: format.channels_count as _,
+                frame_samples_count: opus_frame_samples_count(format),
+            }),
+        }
+    }
+
+    // If true, the output has no discontinuities even in case of packet loss
+    pub fn conceals_packet_loss(&self) -> bool {
+        matches!(self, Self::Opus { .. })
+    }
+
+    // Returns interleaved samples. After a packet loss, Opus first reconstructs the last lost frame
+    // using the FEC data of the current packet, or with its packet loss concealment if there is none
+    pub fn decode(&mut self, packet: &[u8], had_packet_loss: bool) -> StrResult<Vec<f32>> {
+        match self {
+            Self::Pcm => Ok(packet
+                .chunks_exact(2)
+                .map(|c| i16::from_ne_bytes([c[0], c[1]]).to_sample::<f32>())
+                .collect()),
+            Self::Opus {
+                decoder,
+                channels_count,
+                frame_samples_count,
+            } => {
+                let mut samples = vec![0.0; *frame_samples_count * (1 + had_packet_loss as usize)];
+
+                let mut decoded_samples_count = 0;
+                if had_packet_loss {
+                    let frames_count = decoder
+                        .decode_float(packet, &mut samples[..*frame_samples_count], true)
+                        .map_err(err!())?;
+                    decoded_samples_count += frames_count * *channels_count;
+                }
+
+                let frames_count = decoder
+                    .decode_float(packet, &mut samples[decoded_samples_count..], false)
+                    .map_err(err!())?;
+                samples.truncate(decoded_samples_count + frames_count * *channels_count);
+
+                Ok(samples)
+            }
+        }
+    }
+}
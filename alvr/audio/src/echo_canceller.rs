@@ -0,0 +1,166 @@
+use realfft::{num_complex::Complex, ComplexToReal, RealFftPlanner, RealToComplex};
+use std::{collections::VecDeque, sync::Arc};
+
+// Normalized step size. Smaller values adapt slower but diverge less during double talk
+const STEP_SIZE: f32 = 0.3;
+
+// Smoothing of the reference power used to normalize the step size
+const POWER_SMOOTHING_FACTOR: f32 = 0.7;
+
+// Keeps the step size bounded when the reference is silent (about -60 dBFS)
+const MIN_REFERENCE_POWER: f32 = 1e-6;
+
+// Partitioned block frequency domain adaptive filter (overlap-save, constrained). The echo path is
+// modeled by a filter of block_size * partitions_count samples, which must cover the delay between
+// the reference and the echo in the microphone signal.
+pub struct EchoCanceller {
+    block_size: usize,
+    forward_fft: Arc<dyn RealToComplex<f32>>,
+    inverse_fft: Arc<dyn ComplexToReal<f32>>,
+    previous_reference: Vec<f32>,
+    // Spectra of the last reference blocks, the newest first
+    reference_spectra: VecDeque<Vec<Complex<f32>>>,
+    // One filter partition for each reference spectrum
+    weights: Vec<Vec<Complex<f32>>>,
+    reference_power: Vec<f32>,
+}
+
+impl EchoCanceller {
+    pub fn new(block_size: usize, partitions_count: usize) -> Self {
+        let mut planner = RealFftPlanner::new();
+        let forward_fft = planner.plan_fft_forward(2 * block_size);
+        let inverse_fft = planner.plan_fft_inverse(2 * block_size);
+        let bins_count = block_size + 1;
+
+        Self {
+            block_size,
+            forward_fft,
+            inverse_fft,
+            previous_reference: vec![0.0; block_size],
+            reference_spectra: vec![vec![Complex::default(); bins_count]; partitions_count].into(),
+            weights: vec![vec![Complex::default(); bins_count]; partitions_count],
+            reference_power: vec![0.0; bins_count],
+        }
+    }
+
+    fn spectrum(&self, mut signal: Vec<f32>) -> Vec<Complex<f32>> {
+        let mut spectrum = self.forward_fft.make_output_vec();
+        self.forward_fft.process(&mut signal, &mut spectrum).ok();
+
+        spectrum
+    }
+
+    // The result is normalized, unlike the spectra
+    fn signal(&self, mut spectrum: Vec<Complex<f32>>) -> Vec<f32> {
+        // The imaginary parts of DC and Nyquist must be zero for a real signal
+        spectrum[0].im = 0.0;
+        spectrum[self.block_size].im = 0.0;
+
+        let mut signal = self.inverse_fft.make_output_vec();
+        self.inverse_fft.process(&mut spectrum, &mut signal).ok();
+
+        let scale = 1.0 / (2 * self.block_size) as f32;
+        signal.iter_mut().for_each(|sample| *sample *= scale);
+
+        signal
+    }
+
+    // Both slices contain block_size mono samples. Returns the microphone block without the echo
+    pub fn process(&mut self, reference: &[f32], microphone: &[f32]) -> Vec<f32> {
+        let block_size = self.block_size;
+
+        let reference_spectrum = self.spectrum([&self.previous_reference[..], reference].concat());
+        self.previous_reference.copy_from_slice(reference);
+        self.reference_spectra.pop_back();
+        self.reference_spectra.push_front(reference_spectrum);
+
+        let mut echo_spectrum = vec![Complex::default(); block_size + 1];
+        for (weights, reference) in self.weights.iter().zip(&self.reference_spectra) {
+            for ((echo, weight), reference) in echo_spectrum.iter_mut().zip(weights).zip(reference)
+            {
+                *echo += weight * reference;
+            }
+        }
+        let echo = self.signal(echo_spectrum);
+
+        // Only the second half of the circular convolution is valid
+        let error = microphone
+            .iter()
+            .zip(&echo[block_size..])
+            .map(|(sample, echo)| sample - echo)
+            .collect::<Vec<_>>();
+
+        let error_spectrum = self.spectrum([&vec![0.0; block_size][..], &error[..]].concat());
+
+        let min_power = MIN_REFERENCE_POWER * (2 * block_size) as f32;
+        for (bin, power) in self.reference_power.iter_mut().enumerate() {
+            let block_power = self
+                .reference_spectra
+                .iter()
+                .map(|spectrum| spectrum[bin].norm_sqr())
+                .sum::<f32>();
+            *power = POWER_SMOOTHING_FACTOR * *power + (1.0 - POWER_SMOOTHING_FACTOR) * block_power;
+        }
+
+        for partition in 0..self.weights.len() {
+            let gradient = self.reference_spectra[partition]
+                .iter()
+                .zip(&error_spectrum)
+                .zip(&self.reference_power)
+                .map(|((reference, error), power)| {
+                    reference.conj() * error / f32::max(*power, min_power)
+                })
+                .collect::<Vec<_>>();
+
+            // Constrain the gradient to a causal filter of block_size samples, otherwise the
+            // circular convolution wraps around
+            let mut gradient = self.signal(gradient);
+            gradient[block_size..].fill(0.0);
+            let gradient = self.spectrum(gradient);
+
+            for (weight, gradient) in self.weights[partition].iter_mut().zip(gradient) {
+                *weight += gradient * STEP_SIZE;
+            }
+        }
+
+        error
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn removes_delayed_echo() {
+        const BLOCK_SIZE: usize = 64;
+
+        // Deterministic white noise
+        let mut seed = 1_u32;
+        let mut noise = || {
+            seed = seed.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+            (seed >> 8) as f32 / (1 << 24) as f32 - 0.5
+        };
+
+        let reference = (0..BLOCK_SIZE * 1000).map(|_| noise()).collect::<Vec<_>>();
+        let echo = (0..reference.len())
+            .map(|i| {
+                let tap = |delay: usize| i.checked_sub(delay).map(|i| reference[i]).unwrap_or(0.0);
+                0.5 * tap(100) - 0.2 * tap(150)
+            })
+            .collect::<Vec<_>>();
+
+        let mut canceller = EchoCanceller::new(BLOCK_SIZE, 4);
+        let output = reference
+            .chunks_exact(BLOCK_SIZE)
+            .zip(echo.chunks_exact(BLOCK_SIZE))
+            .flat_map(|(reference, echo)| canceller.process(reference, echo))
+            .collect::<Vec<_>>();
+
+        let energy = |signal: &[f32]| signal.iter().map(|s| s * s).sum::<f32>();
+        let tail = echo.len() - BLOCK_SIZE * 100;
+
+        // At least 20 dB of attenuation after convergence
+        assert!(energy(&output[tail..]) < energy(&echo[tail..]) * 0.01);
+    }
+}
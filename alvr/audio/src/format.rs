@@ -0,0 +1,317 @@
+use crate::binaural::BinauralRenderer;
+use alvr_common::prelude::*;
+use alvr_packets::AudioStreamFormat;
+use cpal::{Data, FromSample, Sample, SampleFormat, SizedSample};
+use rubato::{FftFixedIn, Resampler as _};
+use std::f32::consts::FRAC_1_SQRT_2;
+
+// The resampler processes chunks of 10ms
+const RESAMPLER_CHUNKS_PER_SECOND: usize = 100;
+const RESAMPLER_SUB_CHUNKS: usize = 2;
+
+fn convert_samples<T>(data: &Data) -> Vec<f32>
+where
+    T: SizedSample,
+    f32: FromSample<T>,
+{
+    data.as_slice::<T>()
+        .unwrap_or_default()
+        .iter()
+        .map(|sample| sample.to_sample::<f32>())
+        .collect()
+}
+
+pub fn is_sample_format_supported(format: SampleFormat) -> bool {
+    matches!(
+        format,
+        SampleFormat::I8
+            | SampleFormat::I16
+            | SampleFormat::I32
+            | SampleFormat::I64
+            | SampleFormat::U8
+            | SampleFormat::U16
+            | SampleFormat::U32
+            | SampleFormat::U64
+            | SampleFormat::F32
+            | SampleFormat::F64
+    )
+}
+
+// Unsupported formats are rejected when the stream is created
+pub fn samples_to_f32(data: &Data) -> Vec<f32> {
+    match data.sample_format() {
+        SampleFormat::I8 => convert_samples::<i8>(data),
+        SampleFormat::I16 => convert_samples::<i16>(data),
+        SampleFormat::I32 => convert_samples::<i32>(data),
+        SampleFormat::I64 => convert_samples::<i64>(data),
+        SampleFormat::U8 => convert_samples::<u8>(data),
+        SampleFormat::U16 => convert_samples::<u16>(data),
+        SampleFormat::U32 => convert_samples::<u32>(data),
+        SampleFormat::U64 => convert_samples::<u64>(data),
+        SampleFormat::F32 => convert_samples::<f32>(data),
+        SampleFormat::F64 => convert_samples::<f64>(data),
+        _ => vec![],
+    }
+}
+
+// Rows of the left and right output channels. Layouts follow the WAVEFORMATEXTENSIBLE channel
+// order. Center and surround channels are attenuated by 3dB (ITU-R BS.775) and LFE is dropped.
+fn stereo_downmix_rows(input_channels_count: usize) -> [Vec<f32>; 2] {
+    const C: f32 = FRAC_1_SQRT_2;
+
+    let (left, right) = match input_channels_count {
+        1 => (vec![1.0], vec![1.0]),
+        2 => (vec![1.0, 0.0], vec![0.0, 1.0]),
+        // FL FR FC
+        3 => (vec![1.0, 0.0, C], vec![0.0, 1.0, C]),
+        // FL FR BL BR
+        4 => (vec![1.0, 0.0, C, 0.0], vec![0.0, 1.0, 0.0, C]),
+        // FL FR FC LFE BL BR
+        6 => (
+            vec![1.0, 0.0, C, 0.0, C, 0.0],
+            vec![0.0, 1.0, C, 0.0, 0.0, C],
+        ),
+        // FL FR FC LFE BL BR SL SR
+        8 => (
+            vec![1.0, 0.0, C, 0.0, C, 0.0, C, 0.0],
+            vec![0.0, 1.0, C, 0.0, 0.0, C, 0.0, C],
+        ),
+        count => {
+            warn!("Unknown layout for {count} audio channels. Using only the first two");
+            let mut left = vec![0.0; count];
+            let mut right = vec![0.0; count];
+            left[0] = 1.0;
+            right[usize::min(1, count - 1)] = 1.0;
+
+            (left, right)
+        }
+    };
+
+    // Normalize to avoid clipping when all channels are loud
+    [left, right].map(|row| {
+        let sum = row.iter().sum::<f32>();
+        row.into_iter().map(|coeff| coeff / sum).collect()
+    })
+}
+
+// Returns a matrix with a row for each output channel
+fn channel_mix_matrix(input_channels_count: usize, output_channels_count: usize) -> Vec<Vec<f32>> {
+    if output_channels_count <= 2 {
+        let [left, right] = stereo_downmix_rows(input_channels_count);
+        if output_channels_count == 1 {
+            vec![left
+                .iter()
+                .zip(&right)
+                .map(|(l, r)| (l + r) / 2.0)
+                .collect()]
+        } else {
+            vec![left, right]
+        }
+    } else if input_channels_count == 1 {
+        // Mono goes to front left and right only
+        (0..output_channels_count)
+            .map(|out| vec![if out < 2 { 1.0 } else { 0.0 }])
+            .collect()
+    } else {
+        // Channels are matched by position, extra output channels are silent
+        (0..output_channels_count)
+            .map(|out| {
+                (0..input_channels_count)
+                    .map(|input| if input == out { 1.0 } else { 0.0 })
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+// Wraps a resampler with fixed input chunks to accept any number of frames
+struct Resampler {
+    inner: FftFixedIn<f32>,
+    pending_frames: Vec<Vec<f32>>, // per channel
+}
+
+impl Resampler {
+    fn new(input_rate: u32, output_rate: u32, channels_count: usize) -> StrResult<Self> {
+        Ok(Self {
+            inner: FftFixedIn::new(
+                input_rate as _,
+                output_rate as _,
+                input_rate as usize / RESAMPLER_CHUNKS_PER_SECOND,
+                RESAMPLER_SUB_CHUNKS,
+                channels_count,
+            )
+            .map_err(err!())?,
+            pending_frames: vec![vec![]; channels_count],
+        })
+    }
+
+    fn process(&mut self, samples: &[f32]) -> StrResult<Vec<f32>> {
+        let channels_count = self.pending_frames.len();
+        for frame in samples.chunks_exact(channels_count) {
+            for (channel, sample) in self.pending_frames.iter_mut().zip(frame) {
+                channel.push(*sample);
+            }
+        }
+
+        let mut output = vec![];
+        while self.pending_frames[0].len() >= self.inner.input_frames_next() {
+            let chunk_frames_count = self.inner.input_frames_next();
+            let chunk = self
+                .pending_frames
+                .iter_mut()
+                .map(|channel| channel.drain(..chunk_frames_count).collect::<Vec<_>>())
+                .collect::<Vec<_>>();
+
+            let resampled = self.inner.process(&chunk, None).map_err(err!())?;
+            for frame_idx in 0..resampled[0].len() {
+                output.extend(resampled.iter().map(|channel| channel[frame_idx]));
+            }
+        }
+
+        Ok(output)
+    }
+}
+
+// Converts interleaved samples between formats. Channels are mixed before resampling
+pub struct AudioFormatConverter {
+    input_channels_count: usize,
+    input_sample_rate: u32,
+    output_sample_rate: u32,
+    binaural_renderer: Option<BinauralRenderer>,
+    mix_matrix: Option<Vec<Vec<f32>>>,
+    resampler: Option<Resampler>,
+}
+
+impl AudioFormatConverter {
+    // binaural_downmix is used only when converting a known surround layout to stereo
+    pub fn new(
+        input: AudioStreamFormat,
+        output: AudioStreamFormat,
+        binaural_downmix: bool,
+    ) -> StrResult<Self> {
+        let input_channels_count = input.channels_count as usize;
+        let output_channels_count = output.channels_count as usize;
+
+        let binaural_renderer = if binaural_downmix && output_channels_count == 2 {
+            BinauralRenderer::new(input_channels_count, input.sample_rate)
+        } else {
+            None
+        };
+
+        let mix_matrix = (binaural_renderer.is_none()
+            && input_channels_count != output_channels_count)
+            .then(|| channel_mix_matrix(input_channels_count, output_channels_count));
+
+        let resampler = if input.sample_rate != output.sample_rate {
+            Some(Resampler::new(
+                input.sample_rate,
+                output.sample_rate,
+                output_channels_count,
+            )?)
+        } else {
+            None
+        };
+
+        Ok(Self {
+            input_channels_count,
+            input_sample_rate: input.sample_rate,
+            output_sample_rate: output.sample_rate,
+            binaural_renderer,
+            mix_matrix,
+            resampler,
+        })
+    }
+
+    // Duration of the input samples in output frames. Unlike the length of the process() output, it
+    // does not depend on how the resampler splits the input in chunks.
+    pub fn output_frames_count(&self, input_samples_count: usize) -> usize {
+        let input_frames_count = (input_samples_count / self.input_channels_count) as u64;
+
+        (input_frames_count * self.output_sample_rate as u64 / self.input_sample_rate as u64) as _
+    }
+
+    pub fn process(&mut self, samples: Vec<f32>) -> StrResult<Vec<f32>> {
+        let samples = if let Some(renderer) = &mut self.binaural_renderer {
+            renderer.process(&samples)
+        } else if let Some(matrix) = &self.mix_matrix {
+            samples
+                .chunks_exact(self.input_channels_count)
+                .flat_map(|frame| {
+                    matrix.iter().map(|row| {
+                        row.iter()
+                            .zip(frame)
+                            .map(|(coeff, sample)| coeff * sample)
+                            .sum::<f32>()
+                    })
+                })
+                .collect()
+        } else {
+            samples
+        };
+
+        if let Some(resampler) = &mut self.resampler {
+            resampler.process(&samples)
+        } else {
+            Ok(samples)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn surround_downmix_does_not_clip() {
+        for channels_count in [3, 4, 6, 8] {
+            let matrix = channel_mix_matrix(channels_count, 2);
+
+            for row in matrix {
+                assert!((row.iter().sum::<f32>() - 1.0).abs() < 1e-6);
+            }
+        }
+    }
+
+    #[test]
+    fn resampled_length() {
+        let mut converter = AudioFormatConverter::new(
+            AudioStreamFormat {
+                sample_rate: 44100,
+                channels_count: 2,
+            },
+            AudioStreamFormat {
+                sample_rate: 48000,
+                channels_count: 1,
+            },
+            false,
+        )
+        .unwrap();
+
+        let output_len = (0..100)
+            .map(|_| converter.process(vec![0.0; 441 * 2]).unwrap().len())
+            .sum::<usize>();
+
+        // One second of input, minus what is still pending inside the resampler
+        assert!(output_len <= 48000);
+        assert!(output_len >= 48000 - 480 * RESAMPLER_SUB_CHUNKS * 2);
+    }
+
+    #[test]
+    fn output_frames_count_uses_the_input_duration() {
+        let converter = AudioFormatConverter::new(
+            AudioStreamFormat {
+                sample_rate: 48000,
+                channels_count: 2,
+            },
+            AudioStreamFormat {
+                sample_rate: 44100,
+                channels_count: 2,
+            },
+            false,
+        )
+        .unwrap();
+
+        // 10ms packet
+        assert_eq!(converter.output_frames_count(480 * 2), 441);
+    }
+}
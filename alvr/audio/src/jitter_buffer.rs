@@ -0,0 +1,215 @@
+use std::time::{Duration, Instant};
+
+// The buffering target is raised to cover this many times the mean packet interarrival jitter
+const JITTER_TARGET_MULTIPLIER: f32 = 4.0;
+const MAX_TARGET_BUFFERING: Duration = Duration::from_millis(200);
+
+// Audio is delayed at most this much to wait for the corresponding video frames
+const MAX_SYNC_DELAY: Duration = Duration::from_millis(500);
+
+// Fraction of the buffer level error corrected every second. The buffer level is also smoothed
+// because the audio callback drains it in batches.
+const LEVEL_CORRECTION_PER_SEC: f32 = 0.1;
+const LEVEL_SMOOTHING_FACTOR: f32 = 0.05;
+
+// 0.5% corresponds to a pitch shift of less than 10 cents, which is not noticeable. Clock drifts
+// are usually much smaller than this.
+const MAX_RATIO_DEVIATION: f32 = 0.005;
+
+// Linear interpolation is good enough for ratios this close to 1
+struct DriftResampler {
+    channels_count: usize,
+    // Last frame of the previous input, needed to interpolate across packets
+    last_frame: Vec<f32>,
+    // Position of the next output frame. 0 is last_frame, 1 is the first frame of the next input
+    position: f64,
+}
+
+impl DriftResampler {
+    fn new(channels_count: usize) -> Self {
+        Self {
+            channels_count,
+            last_frame: vec![0.0; channels_count],
+            position: 1.0,
+        }
+    }
+
+    // A ratio greater than 1 produces fewer frames than the input
+    fn process(&mut self, input: &[f32], ratio: f64) -> Vec<f32> {
+        let channels_count = self.channels_count;
+        let frames_count = input.len() / channels_count;
+        if frames_count == 0 {
+            return vec![];
+        }
+
+        let sample = |frame: usize, channel: usize| {
+            if frame == 0 {
+                self.last_frame[channel]
+            } else {
+                input[(frame - 1) * channels_count + channel]
+            }
+        };
+
+        let mut output = Vec::with_capacity((frames_count as f64 / ratio) as usize + 1);
+        while self.position <= frames_count as f64 {
+            let frame = self.position as usize;
+            let fraction = (self.position - frame as f64) as f32;
+            for channel in 0..channels_count {
+                let next = if frame < frames_count {
+                    sample(frame + 1, channel)
+                } else {
+                    sample(frame, channel)
+                };
+                output.push(sample(frame, channel) * (1.0 - fraction) + next * fraction);
+            }
+
+            self.position += ratio;
+        }
+
+        self.position -= frames_count as f64;
+        self.last_frame
+            .copy_from_slice(&input[(frames_count - 1) * channels_count..]);
+
+        output
+    }
+}
+
+// Keeps the playback buffer level around a target that follows the network jitter, compensating
+// the clock drift between the two devices with a continuous resampling
+pub struct JitterBufferController {
+    sample_rate: f32,
+    min_target_frames: usize,
+    last_arrival: Option<Instant>,
+    last_packet_frames: usize,
+    jitter_s: f32,
+    sync_delay_frames: Option<f32>,
+    smoothed_level_frames: f32,
+    resampling_ratio: f32,
+    resampler: DriftResampler,
+}
+
+impl JitterBufferController {
+    pub fn new(sample_rate: u32, channels_count: usize, min_target_frames: usize) -> Self {
+        Self {
+            sample_rate: sample_rate as _,
+            min_target_frames,
+            last_arrival: None,
+            last_packet_frames: 0,
+            jitter_s: 0.0,
+            sync_delay_frames: None,
+            smoothed_level_frames: min_target_frames as _,
+            resampling_ratio: 1.0,
+            resampler: DriftResampler::new(channels_count),
+        }
+    }
+
+    // Interarrival jitter estimator from RFC 3550. Packets are expected to arrive at the same rate
+    // as they are played.
+    pub fn report_packet(&mut self, frames_count: usize) {
+        let now = Instant::now();
+        if let Some(last_arrival) = self.last_arrival {
+            let interval_s = (now - last_arrival).as_secs_f32();
+            let expected_interval_s = self.last_packet_frames as f32 / self.sample_rate;
+            self.jitter_s += ((interval_s - expected_interval_s).abs() - self.jitter_s) / 16.0;
+        }
+
+        self.last_arrival = Some(now);
+        self.last_packet_frames = frames_count;
+    }
+
+    // Buffering needed for the newest packet to be played together with its video frame. It is
+    // negative if the video frame has already been displayed.
+    pub fn report_sync_delay(&mut self, delay_s: Option<f32>) {
+        self.sync_delay_frames = delay_s.map(|delay_s| {
+            let delay_frames = f32::min(delay_s, MAX_SYNC_DELAY.as_secs_f32()) * self.sample_rate;
+
+            // The delay is noisy because packets are not received at regular intervals
+            self.sync_delay_frames
+                .map(|frames| frames + (delay_frames - frames) * LEVEL_SMOOTHING_FACTOR)
+                .unwrap_or(delay_frames)
+        });
+    }
+
+    // Positive when the audio is played after its video frame, because the network does not allow
+    // a smaller buffering
+    pub fn av_offset_s(&self) -> Option<f32> {
+        self.sync_delay_frames
+            .map(|delay_frames| (self.smoothed_level_frames - delay_frames) / self.sample_rate)
+    }
+
+    pub fn target_frames(&self) -> usize {
+        let jitter_frames = (JITTER_TARGET_MULTIPLIER * self.jitter_s * self.sample_rate) as usize;
+        let max_frames = (MAX_TARGET_BUFFERING.as_secs_f32() * self.sample_rate) as usize;
+        let network_target_frames = usize::max(
+            self.min_target_frames,
+            usize::min(jitter_frames, max_frames),
+        );
+
+        // The audio can be delayed to match the video, but it cannot be played sooner than what
+        // the network allows
+        usize::max(
+            network_target_frames,
+            f32::max(self.sync_delay_frames.unwrap_or(0.0), 0.0) as usize,
+        )
+    }
+
+    pub fn jitter(&self) -> Duration {
+        Duration::from_secs_f32(self.jitter_s)
+    }
+
+    pub fn resampling_ratio(&self) -> f32 {
+        self.resampling_ratio
+    }
+
+    // Resamples the new samples to slowly drive the buffer level towards the target
+    pub fn process(&mut self, samples: &[f32], buffer_frames_count: usize) -> Vec<f32> {
+        self.smoothed_level_frames +=
+            (buffer_frames_count as f32 - self.smoothed_level_frames) * LEVEL_SMOOTHING_FACTOR;
+
+        let level_error_s =
+            (self.smoothed_level_frames - self.target_frames() as f32) / self.sample_rate;
+        self.resampling_ratio = 1.0
+            + (level_error_s * LEVEL_CORRECTION_PER_SEC)
+                .clamp(-MAX_RATIO_DEVIATION, MAX_RATIO_DEVIATION);
+
+        self.resampler.process(samples, self.resampling_ratio as _)
+    }
+
+    // Called when the buffer is flushed, the interpolation must not continue from old samples
+    pub fn reset_level(&mut self, buffer_frames_count: usize) {
+        self.smoothed_level_frames = buffer_frames_count as _;
+        self.resampler = DriftResampler::new(self.resampler.channels_count);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unit_ratio_is_passthrough() {
+        let mut resampler = DriftResampler::new(2);
+        let input = (0..200).map(|i| i as f32).collect::<Vec<_>>();
+
+        let output = resampler.process(&input, 1.0);
+
+        assert_eq!(output, input);
+    }
+
+    #[test]
+    fn ratio_changes_frames_count() {
+        let input = vec![0.5; 48_000];
+
+        let mut resampler = DriftResampler::new(1);
+        let faster_count = (0..10)
+            .map(|_| resampler.process(&input, 1.005).len())
+            .sum::<usize>();
+        assert!((faster_count as f32 - 480_000.0 / 1.005).abs() <= 1.0);
+
+        let mut resampler = DriftResampler::new(1);
+        let slower_count = (0..10)
+            .map(|_| resampler.process(&input, 0.995).len())
+            .sum::<usize>();
+        assert!((slower_count as f32 - 480_000.0 / 0.995).abs() <= 1.0);
+    }
+}
@@ -1,20 +1,52 @@
+mod binaural;
+mod codec;
+mod echo_canceller;
+mod format;
+mod jitter_buffer;
+mod microphone_processing;
+
+#[cfg(all(target_os = "linux", feature = "pipewire"))]
+pub mod linux;
+
+pub use alvr_audio_devices::{devices_list, AudioDeviceChange, AudioDevicesWatcher};
+pub use codec::{
+    negotiate_game_audio_channels, negotiate_stream_format, AudioDecoder, AudioEncoder,
+};
+pub use format::AudioFormatConverter;
+pub use microphone_processing::{report_played_samples, MicrophoneProcessor};
+
+use alvr_audio_devices::DeviceDirection;
 use alvr_common::{once_cell::sync::Lazy, parking_lot::Mutex, prelude::*};
+use alvr_packets::{AudioPacketHeader, AudioPlaybackStatistics, AudioStreamFormat};
 use alvr_session::{
-    AudioBufferingConfig, CustomAudioDeviceConfig, LinuxAudioBackend, MicrophoneDevicesConfig,
+    AudioBufferingConfig, AudioCodecConfig, CustomAudioDeviceConfig, LinuxAudioBackend,
+    MicrophoneDevicesConfig,
 };
 use alvr_sockets::{ReceiverBuffer, StreamReceiver, StreamSender};
 use cpal::{
-    traits::{DeviceTrait, HostTrait, StreamTrait},
-    BufferSize, Device, Host, Sample, SampleFormat, StreamConfig,
+    traits::{DeviceTrait, StreamTrait},
+    BufferSize, Device, Host, StreamConfig,
 };
+use jitter_buffer::JitterBufferController;
 use rodio::{OutputStream, Source};
 use std::{
     collections::{HashMap, VecDeque},
+    future::Future,
     sync::{mpsc as smpsc, Arc},
     thread,
+    time::{Duration, Instant},
 };
 use tokio::sync::mpsc as tmpsc;
 
+// There is only one playback stream per process: game audio on the client and microphone on the
+// streamer
+static PLAYBACK_STATISTICS: Lazy<Mutex<AudioPlaybackStatistics>> =
+    Lazy::new(|| Mutex::new(AudioPlaybackStatistics::default()));
+
+// Latest video frame timestamp and the time it was presented: by the game on the streamer, on the
+// display on the client. It maps the local clock to the video timestamps.
+static VIDEO_TIMELINE: Lazy<Mutex<Option<(Duration, Instant)>>> = Lazy::new(|| Mutex::new(None));
+
 static VIRTUAL_MICROPHONE_PAIRS: Lazy<HashMap<&str, &str>> = Lazy::new(|| {
     [
         ("CABLE Input", "CABLE Output"),
@@ -26,12 +58,16 @@ static VIRTUAL_MICROPHONE_PAIRS: Lazy<HashMap<&str, &str>> = Lazy::new(|| {
     .collect()
 });
 
-fn device_from_custom_config(host: &Host, config: &CustomAudioDeviceConfig) -> StrResult<Device> {
+fn device_from_custom_config(
+    host: &Host,
+    config: &CustomAudioDeviceConfig,
+) -> StrResult<(Device, String)> {
+    let devices = alvr_audio_devices::devices_with_ids(host, DeviceDirection::Any)?;
+
     Ok(match config {
-        CustomAudioDeviceConfig::NameSubstring(name_substring) => host
-            .devices()
-            .map_err(err!())?
-            .find(|d| {
+        CustomAudioDeviceConfig::NameSubstring(name_substring) => devices
+            .into_iter()
+            .find(|(d, _)| {
                 d.name()
                     .map(|name| name.to_lowercase().contains(&name_substring.to_lowercase()))
                     .unwrap_or(false)
@@ -39,19 +75,35 @@ fn device_from_custom_config(host: &Host, config: &CustomAudioDeviceConfig) -> S
             .ok_or_else(|| {
                 format!("Cannot find audio device which name contains \"{name_substring}\"")
             })?,
-        CustomAudioDeviceConfig::Index(index) => host
-            .devices()
-            .map_err(err!())?
+        CustomAudioDeviceConfig::Index(index) => devices
+            .into_iter()
             .nth(*index)
             .ok_or_else(|| format!("Cannot find audio device at index {index}"))?,
+        CustomAudioDeviceConfig::Id { id, name } => {
+            let mut devices = devices;
+
+            if let Some(index) = devices.iter().position(|(_, d_id)| d_id == id) {
+                devices.swap_remove(index)
+            } else {
+                warn!("Cannot find audio device with ID \"{id}\", using the name \"{name}\"");
+
+                devices
+                    .into_iter()
+                    .find(|(d, _)| d.name().map(|d_name| d_name == *name).unwrap_or(false))
+                    .ok_or_else(|| format!("Cannot find audio device \"{name}\""))?
+            }
+        }
     })
 }
 
-fn microphone_pair_from_sink_name(host: &Host, sink_name: &str) -> StrResult<(Device, Device)> {
-    let sink = host
-        .output_devices()
-        .map_err(err!())?
-        .find(|d| d.name().unwrap_or_default().contains(sink_name))
+// returns ((sink, sink ID), (source, source ID))
+fn microphone_pair_from_sink_name(
+    host: &Host,
+    sink_name: &str,
+) -> StrResult<((Device, String), (Device, String))> {
+    let sink = alvr_audio_devices::devices_with_ids(host, DeviceDirection::Output)?
+        .into_iter()
+        .find(|(d, _)| d.name().unwrap_or_default().contains(sink_name))
         .ok_or_else(|| {
             "VB-CABLE or Voice Meeter not found. Please install or reinstall either one".to_owned()
         })?;
@@ -59,9 +111,9 @@ fn microphone_pair_from_sink_name(host: &Host, sink_name: &str) -> StrResult<(De
     if let Some(source_name) = VIRTUAL_MICROPHONE_PAIRS.get(sink_name) {
         Ok((
             sink,
-            host.input_devices()
-                .map_err(err!())?
-                .find(|d| {
+            alvr_audio_devices::devices_with_ids(host, DeviceDirection::Input)?
+                .into_iter()
+                .find(|(d, _)| {
                     d.name()
                         .map(|name| name.contains(source_name))
                         .unwrap_or(false)
@@ -78,49 +130,40 @@ fn microphone_pair_from_sink_name(host: &Host, sink_name: &str) -> StrResult<(De
 #[allow(dead_code)]
 pub struct AudioDevice {
     inner: Device,
+    id: String,
     is_output: bool,
 }
 
-#[cfg_attr(not(target_os = "linux"), allow(unused_variables))]
 impl AudioDevice {
     pub fn new_output(
         linux_backend: Option<LinuxAudioBackend>,
         config: Option<&CustomAudioDeviceConfig>,
     ) -> StrResult<Self> {
-        #[cfg(target_os = "linux")]
-        let host = match linux_backend {
-            Some(LinuxAudioBackend::Alsa) => cpal::host_from_id(cpal::HostId::Alsa).unwrap(),
-            Some(LinuxAudioBackend::Jack) => cpal::host_from_id(cpal::HostId::Jack).unwrap(),
-            None => cpal::default_host(),
-        };
-        #[cfg(not(target_os = "linux"))]
-        let host = cpal::default_host();
+        let host = alvr_audio_devices::host(linux_backend);
 
-        let device = match config {
-            None => host
-                .default_output_device()
-                .ok_or_else(|| "No output audio device found".to_owned())?,
+        let (device, id) = match config {
+            None => alvr_audio_devices::default_device_with_id(&host, true)?,
             Some(config) => device_from_custom_config(&host, config)?,
         };
 
         Ok(Self {
             inner: device,
+            id,
             is_output: true,
         })
     }
 
     pub fn new_input(config: Option<CustomAudioDeviceConfig>) -> StrResult<Self> {
-        let host = cpal::default_host();
+        let host = alvr_audio_devices::host(None);
 
-        let device = match config {
-            None => host
-                .default_input_device()
-                .ok_or_else(|| "No input audio device found".to_owned())?,
+        let (device, id) = match config {
+            None => alvr_audio_devices::default_device_with_id(&host, false)?,
             Some(config) => device_from_custom_config(&host, &config)?,
         };
 
         Ok(Self {
             inner: device,
+            id,
             is_output: false,
         })
     }
@@ -130,16 +173,9 @@ impl AudioDevice {
         linux_backend: Option<LinuxAudioBackend>,
         config: MicrophoneDevicesConfig,
     ) -> StrResult<(Self, Self)> {
-        #[cfg(target_os = "linux")]
-        let host = match linux_backend {
-            Some(LinuxAudioBackend::Alsa) => cpal::host_from_id(cpal::HostId::Alsa).unwrap(),
-            Some(LinuxAudioBackend::Jack) => cpal::host_from_id(cpal::HostId::Jack).unwrap(),
-            None => cpal::default_host(),
-        };
-        #[cfg(not(target_os = "linux"))]
-        let host = cpal::default_host();
+        let host = alvr_audio_devices::host(linux_backend);
 
-        let (sink, source) = match config {
+        let ((sink, sink_id), (source, source_id)) = match config {
             MicrophoneDevicesConfig::Automatic => {
                 let mut pair = Err(String::new());
                 for sink_name in VIRTUAL_MICROPHONE_PAIRS.keys() {
@@ -172,16 +208,31 @@ impl AudioDevice {
         Ok((
             Self {
                 inner: sink,
+                id: sink_id,
                 is_output: true,
             },
             Self {
                 inner: source,
+                id: source_id,
                 is_output: false,
             },
         ))
     }
 
-    pub fn input_sample_rate(&self) -> StrResult<u32> {
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn output_format(&self) -> StrResult<AudioStreamFormat> {
+        let config = self.inner.default_output_config().map_err(err!())?;
+
+        Ok(AudioStreamFormat {
+            sample_rate: config.sample_rate().0,
+            channels_count: config.channels(),
+        })
+    }
+
+    pub fn input_format(&self) -> StrResult<AudioStreamFormat> {
         let config = self
             .inner
             .default_input_config()
@@ -189,80 +240,52 @@ impl AudioDevice {
             .or_else(|_| self.inner.default_output_config())
             .map_err(err!())?;
 
-        Ok(config.sample_rate().0)
+        Ok(AudioStreamFormat {
+            sample_rate: config.sample_rate().0,
+            channels_count: config.channels(),
+        })
     }
-}
 
-pub fn is_same_device(device1: &AudioDevice, device2: &AudioDevice) -> bool {
-    if let (Ok(name1), Ok(name2)) = (device1.inner.name(), device2.inner.name()) {
-        name1 == name2
-    } else {
-        false
+    pub fn input_sample_rate(&self) -> StrResult<u32> {
+        Ok(self.input_format()?.sample_rate)
     }
 }
 
-#[cfg(windows)]
-fn get_windows_device(device: &AudioDevice) -> StrResult<windows::Win32::Media::Audio::IMMDevice> {
-    use widestring::U16CStr;
-    use windows::Win32::{
-        Devices::FunctionDiscovery::PKEY_Device_FriendlyName,
-        Media::Audio::{eAll, IMMDeviceEnumerator, MMDeviceEnumerator, DEVICE_STATE_ACTIVE},
-        System::Com::{self, CLSCTX_ALL, COINIT_MULTITHREADED, STGM_READ},
-    };
-
-    let device_name = device.inner.name().map_err(err!())?;
-
-    unsafe {
-        // This will fail the second time is called, ignore the error
-        Com::CoInitializeEx(None, COINIT_MULTITHREADED).ok();
-
-        let imm_device_enumerator: IMMDeviceEnumerator =
-            Com::CoCreateInstance(&MMDeviceEnumerator, None, CLSCTX_ALL).map_err(err!())?;
-
-        let imm_device_collection = imm_device_enumerator
-            .EnumAudioEndpoints(eAll, DEVICE_STATE_ACTIVE)
-            .map_err(err!())?;
+pub fn report_video_frame_presented(timestamp: Duration, present_instant: Instant) {
+    *VIDEO_TIMELINE.lock() = Some((timestamp, present_instant));
+}
 
-        let count = imm_device_collection.GetCount().map_err(err!())?;
+pub fn video_timestamp_at(instant: Instant) -> Option<Duration> {
+    let (timestamp, present_instant) = (*VIDEO_TIMELINE.lock())?;
 
-        for i in 0..count {
-            let imm_device = imm_device_collection.Item(i).map_err(err!())?;
+    if instant >= present_instant {
+        Some(timestamp + (instant - present_instant))
+    } else {
+        timestamp.checked_sub(present_instant - instant)
+    }
+}
 
-            let property_store = imm_device.OpenPropertyStore(STGM_READ).map_err(err!())?;
+// Returns the signed time from now to when the video frame with this timestamp is presented
+fn seconds_until_video_timestamp(timestamp: Duration) -> Option<f32> {
+    let (present_timestamp, present_instant) = (*VIDEO_TIMELINE.lock())?;
 
-            let mut prop_variant = property_store
-                .GetValue(&PKEY_Device_FriendlyName)
-                .map_err(err!())?;
-            let utf16_name =
-                U16CStr::from_ptr_str(prop_variant.Anonymous.Anonymous.Anonymous.pwszVal.0);
-            Com::StructuredStorage::PropVariantClear(&mut prop_variant).map_err(err!())?;
+    let now = Instant::now();
+    let present_instant_s = if present_instant >= now {
+        (present_instant - now).as_secs_f32()
+    } else {
+        -(now - present_instant).as_secs_f32()
+    };
 
-            let imm_device_name = utf16_name.to_string().map_err(err!())?;
-            if imm_device_name == device_name {
-                return Ok(imm_device);
-            }
-        }
+    Some(present_instant_s + timestamp.as_secs_f32() - present_timestamp.as_secs_f32())
+}
 
-        fmt_e!("No device found with specified name")
-    }
+pub fn is_same_device(device1: &AudioDevice, device2: &AudioDevice) -> bool {
+    device1.id == device2.id
 }
 
 #[cfg(windows)]
 pub fn get_windows_device_id(device: &AudioDevice) -> StrResult<String> {
-    use widestring::U16CStr;
-    use windows::Win32::System::Com;
-
-    unsafe {
-        let imm_device = get_windows_device(device)?;
-
-        let id_str_ptr = imm_device.GetId().map_err(err!())?;
-        let id_str = U16CStr::from_ptr_str(id_str_ptr.0)
-            .to_string()
-            .map_err(err!())?;
-        Com::CoTaskMemFree(Some(id_str_ptr.0 as _));
-
-        Ok(id_str)
-    }
+    Ok(device.id.clone())
 }
 
 // device must be an output device
@@ -274,7 +297,7 @@ fn set_mute_windows_device(device: &AudioDevice, mute: bool) -> StrResult {
     };
 
     unsafe {
-        let imm_device = get_windows_device(device)?;
+        let imm_device = alvr_audio_devices::endpoint_from_id(&device.id)?;
 
         let endpoint_volume = imm_device
             .Activate::<IAudioEndpointVolume>(CLSCTX_ALL, None)
@@ -291,9 +314,11 @@ fn set_mute_windows_device(device: &AudioDevice, mute: bool) -> StrResult {
 #[cfg_attr(not(windows), allow(unused_variables))]
 pub async fn record_audio_loop(
     device: AudioDevice,
-    channels_count: u16,
+    format: AudioStreamFormat,
     mute: bool,
-    mut sender: StreamSender<()>,
+    binaural_downmix: bool,
+    codec: AudioCodecConfig,
+    sender: StreamSender<AudioPacketHeader>,
 ) -> StrResult {
     let config = device
         .inner
@@ -302,12 +327,8 @@ pub async fn record_audio_loop(
         .or_else(|_| device.inner.default_output_config())
         .map_err(err!())?;
 
-    if config.channels() > 2 {
-        // todo: handle more than 2 channels
-        return fmt_e!(
-            "Audio devices with more than 2 channels are not supported. {}",
-            "Please turn off surround audio."
-        );
+    if !format::is_sample_format_supported(config.sample_format()) {
+        return fmt_e!("Unsupported audio sample format {}", config.sample_format());
     }
 
     let stream_config = StreamConfig {
@@ -316,8 +337,17 @@ pub async fn record_audio_loop(
         buffer_size: BufferSize::Default,
     };
 
+    // The device format can be different from the one negotiated, for example after the default
+    // device changed
+    let device_format = AudioStreamFormat {
+        sample_rate: config.sample_rate().0,
+        channels_count: config.channels(),
+    };
+    let converter = AudioFormatConverter::new(device_format, format, binaural_downmix)?;
+    let encoder = AudioEncoder::new(&codec, format)?;
+
     // data_sender/receiver is the bridge between tokio and std thread
-    let (data_sender, mut data_receiver) = tmpsc::unbounded_channel::<StrResult<Vec<_>>>();
+    let (data_sender, data_receiver) = tmpsc::unbounded_channel();
     let (_shutdown_notifier, shutdown_receiver) = smpsc::channel::<()>();
 
     let thread_callback = {
@@ -335,34 +365,18 @@ pub async fn record_audio_loop(
                     config.sample_format(),
                     {
                         let data_sender = data_sender.clone();
+                        let channels_count = stream_config.channels as usize;
+                        let sample_rate = stream_config.sample_rate.0 as f32;
                         move |data, _| {
-                            let data = if config.sample_format() == SampleFormat::F32 {
-                                data.bytes()
-                                    .chunks_exact(4)
-                                    .flat_map(|b| {
-                                        f32::from_ne_bytes([b[0], b[1], b[2], b[3]])
-                                            .to_sample::<i16>()
-                                            .to_ne_bytes()
-                                            .to_vec()
-                                    })
-                                    .collect()
-                            } else {
-                                data.bytes().to_vec()
-                            };
-
-                            let data = if config.channels() == 1 && channels_count == 2 {
-                                data.chunks_exact(2)
-                                    .flat_map(|c| vec![c[0], c[1], c[0], c[1]])
-                                    .collect()
-                            } else if config.channels() == 2 && channels_count == 1 {
-                                data.chunks_exact(4)
-                                    .flat_map(|c| vec![c[0], c[1]])
-                                    .collect()
-                            } else {
-                                data
-                            };
-
-                            data_sender.send(Ok(data)).ok();
+                            // The callback is called once the buffer is full
+                            let capture_instant = Instant::now()
+                                - Duration::from_secs_f32(
+                                    (data.len() / channels_count) as f32 / sample_rate,
+                                );
+
+                            data_sender
+                                .send(Ok((capture_instant, format::samples_to_f32(data))))
+                                .ok();
                         }
                     },
                     {
@@ -386,7 +400,7 @@ pub async fn record_audio_loop(
                 set_mute_windows_device(&device, false).ok();
             }
 
-            Ok(vec![])
+            Ok((Instant::now(), vec![]))
         }
     };
 
@@ -399,9 +413,37 @@ pub async fn record_audio_loop(
         }
     });
 
+    send_samples_loop(data_receiver, converter, None, encoder, sender).await
+}
+
+// Receives the samples from the audio callback, with the capture time of the first one. The
+// processor, if any, also converts the samples and is used instead of the converter. Shared by the
+// streamer and the client capture.
+pub async fn send_samples_loop(
+    mut data_receiver: tmpsc::UnboundedReceiver<StrResult<(Instant, Vec<f32>)>>,
+    mut converter: AudioFormatConverter,
+    mut processor: Option<MicrophoneProcessor>,
+    mut encoder: AudioEncoder,
+    mut sender: StreamSender<AudioPacketHeader>,
+) -> StrResult {
     // todo: reuse buffers also in the audio callback
-    while let Some(maybe_data) = data_receiver.recv().await {
-        sender.send(&(), maybe_data?).await.ok();
+    while let Some(maybe_samples) = data_receiver.recv().await {
+        let (capture_instant, samples) = maybe_samples?;
+        let (capture_instant, samples) = if let Some(processor) = &mut processor {
+            processor.process(samples, capture_instant)?
+        } else {
+            (capture_instant, converter.process(samples)?)
+        };
+        if samples.is_empty() {
+            continue;
+        }
+
+        for (capture_instant, packet) in encoder.encode(&samples, capture_instant)? {
+            let header = AudioPacketHeader {
+                timestamp: video_timestamp_at(capture_instant),
+            };
+            sender.send(&header, packet).await.ok();
+        }
     }
 
     Ok(())
@@ -437,31 +479,58 @@ pub fn get_next_frame_batch(
     }
 }
 
+pub fn playback_statistics() -> AudioPlaybackStatistics {
+    *PLAYBACK_STATISTICS.lock()
+}
+
 // The receive loop is resposible for ensuring smooth transitions in case of disruptions (buffer
-// underflow, overflow, packet loss). In case the computation takes too much time, the audio
+// underflow, overflow, packet loss). Packet losses are handled by the decoder when it supports loss
+// concealment. Clock drift is compensated by resampling, so overflows and underflows should happen
+// only with network disruptions. In case the computation takes too much time, the audio
 // callback will gracefully handle an interruption, and the callback timing and sound wave
-// continuity will not be affected.
+// continuity will not be affected. If sync_with_video is true, the buffering is raised to play the
+// samples together with their video frame.
 pub async fn receive_samples_loop(
-    mut receiver: StreamReceiver<()>,
+    mut receiver: StreamReceiver<AudioPacketHeader>,
     sample_buffer: Arc<Mutex<VecDeque<f32>>>,
     channels_count: usize,
+    sample_rate: u32,
     batch_frames_count: usize,
-    average_buffer_frames_count: usize,
+    min_average_buffer_frames_count: usize,
+    mut decoder: AudioDecoder,
+    mut converter: AudioFormatConverter,
+    sync_with_video: bool,
 ) -> StrResult {
+    let mut jitter_buffer =
+        JitterBufferController::new(sample_rate, channels_count, min_average_buffer_frames_count);
+    let mut glitches_total = 0;
+    // The buffer starts empty, this is not a glitch
+    let mut is_recovering = true;
+    *PLAYBACK_STATISTICS.lock() = AudioPlaybackStatistics::default();
+
     let mut receiver_buffer = ReceiverBuffer::new();
     let mut recovery_sample_buffer = vec![];
     loop {
         receiver.recv_buffer(&mut receiver_buffer).await?;
-        let (_, packet) = receiver_buffer.get()?;
+        let (header, packet) = receiver_buffer.get()?;
 
-        let new_samples = packet
-            .chunks_exact(2)
-            .map(|c| i16::from_ne_bytes([c[0], c[1]]).to_sample::<f32>())
-            .collect::<Vec<_>>();
+        if sync_with_video {
+            jitter_buffer
+                .report_sync_delay(header.timestamp.and_then(seconds_until_video_timestamp));
+        }
+
+        let decoded_samples = decoder.decode(packet, receiver_buffer.had_packet_loss())?;
+        // The resampler output comes in chunks, use the packet duration for the jitter estimation
+        jitter_buffer.report_packet(converter.output_frames_count(decoded_samples.len()));
+
+        let new_samples = converter.process(decoded_samples)?;
+        let had_packet_loss = receiver_buffer.had_packet_loss() && !decoder.conceals_packet_loss();
+
+        let average_buffer_frames_count = jitter_buffer.target_frames();
 
         let mut sample_buffer_ref = sample_buffer.lock();
 
-        if receiver_buffer.had_packet_loss() {
+        if had_packet_loss {
             info!("Audio packet loss!");
 
             if sample_buffer_ref.len() / channels_count < batch_frames_count {
@@ -478,7 +547,12 @@ pub async fn receive_samples_loop(
             recovery_sample_buffer.extend(sample_buffer_ref.drain(..));
         }
 
-        if sample_buffer_ref.len() == 0 || receiver_buffer.had_packet_loss() {
+        if sample_buffer_ref.len() == 0 || had_packet_loss {
+            if !is_recovering {
+                glitches_total += 1;
+                is_recovering = true;
+            }
+
             recovery_sample_buffer.extend(&new_samples);
 
             if recovery_sample_buffer.len() / channels_count
@@ -492,8 +566,7 @@ pub async fn receive_samples_loop(
                     }
                 }
 
-                if receiver_buffer.had_packet_loss()
-                    && sample_buffer_ref.len() / channels_count == batch_frames_count
+                if had_packet_loss && sample_buffer_ref.len() / channels_count == batch_frames_count
                 {
                     // Add a fade-out to make a cross-fade.
                     for f in 0..batch_frames_count {
@@ -508,16 +581,21 @@ pub async fn receive_samples_loop(
                 }
 
                 sample_buffer_ref.extend(recovery_sample_buffer.drain(..));
+                jitter_buffer.reset_level(sample_buffer_ref.len() / channels_count);
+                is_recovering = false;
                 info!("Audio recovered");
             }
         } else {
-            sample_buffer_ref.extend(&new_samples);
+            let resampled_samples =
+                jitter_buffer.process(&new_samples, sample_buffer_ref.len() / channels_count);
+            sample_buffer_ref.extend(resampled_samples);
         }
 
-        // todo: use smarter policy with EventTiming
+        // This should happen only after a burst of packets
         let buffer_frames_size = sample_buffer_ref.len() / channels_count;
         if buffer_frames_size > 2 * average_buffer_frames_count + batch_frames_count {
             info!("Audio buffer overflow! size: {buffer_frames_size}");
+            glitches_total += 1;
 
             let drained_samples = sample_buffer_ref
                 .drain(0..(buffer_frames_size - average_buffer_frames_count) * channels_count)
@@ -532,7 +610,19 @@ pub async fn receive_samples_loop(
                         sample_buffer_ref[index] * volume + drained_samples[index] * (1. - volume);
                 }
             }
+
+            jitter_buffer.reset_level(average_buffer_frames_count);
         }
+
+        let frames_to_ms = |frames: usize| frames as f32 * 1000. / sample_rate as f32;
+        *PLAYBACK_STATISTICS.lock() = AudioPlaybackStatistics {
+            buffer_ms: frames_to_ms(sample_buffer_ref.len() / channels_count),
+            target_buffer_ms: frames_to_ms(average_buffer_frames_count),
+            jitter_ms: jitter_buffer.jitter().as_secs_f32() * 1000.,
+            resampling_ratio: jitter_buffer.resampling_ratio(),
+            glitches_total,
+            av_offset_ms: jitter_buffer.av_offset_s().map(|offset_s| offset_s * 1000.),
+        };
     }
 }
 
@@ -574,6 +664,14 @@ impl Iterator for StreamingSource {
                 self.channels_count,
                 self.batch_frames_count,
             );
+
+            report_played_samples(
+                &self.current_batch,
+                AudioStreamFormat {
+                    sample_rate: self.sample_rate,
+                    channels_count: self.channels_count as _,
+                },
+            );
         }
 
         let sample = self.current_batch[self.current_batch_cursor];
@@ -585,13 +683,21 @@ impl Iterator for StreamingSource {
     }
 }
 
-pub async fn play_audio_loop(
-    device: AudioDevice,
-    channels_count: u16,
-    sample_rate: u32,
+// Playback pipeline shared by the backends. The source must be played by the output device, while
+// the future receives the packets and fills the source buffer.
+fn playback_pipeline(
+    format: AudioStreamFormat,
+    output_format: AudioStreamFormat,
     config: AudioBufferingConfig,
-    receiver: StreamReceiver<()>,
-) -> StrResult {
+    codec: AudioCodecConfig,
+    sync_with_video: bool,
+    receiver: StreamReceiver<AudioPacketHeader>,
+) -> StrResult<(StreamingSource, impl Future<Output = StrResult>)> {
+    let decoder = AudioDecoder::new(&codec, format)?;
+    let converter = AudioFormatConverter::new(format, output_format, false)?;
+    let channels_count = output_format.channels_count as usize;
+    let sample_rate = output_format.sample_rate;
+
     // Size of a chunk of frames. It corresponds to the duration if a fade-in/out in frames.
     let batch_frames_count = sample_rate as usize * config.batch_ms as usize / 1000;
 
@@ -601,34 +707,56 @@ pub async fn play_audio_loop(
 
     let sample_buffer = Arc::new(Mutex::new(VecDeque::new()));
 
-    // Store the stream in a thread (because !Send)
-    let (_shutdown_notifier, shutdown_receiver) = smpsc::channel::<()>();
-    thread::spawn({
-        let sample_buffer = Arc::clone(&sample_buffer);
-        move || -> StrResult {
-            let (_stream, handle) = OutputStream::try_from_device(&device.inner).map_err(err!())?;
-
-            let source = StreamingSource {
-                sample_buffer,
-                current_batch: vec![],
-                current_batch_cursor: 0,
-                channels_count: channels_count as _,
-                sample_rate,
-                batch_frames_count,
-            };
-            handle.play_raw(source).map_err(err!())?;
-
-            shutdown_receiver.recv().ok();
-            Ok(())
-        }
-    });
+    let source = StreamingSource {
+        sample_buffer: Arc::clone(&sample_buffer),
+        current_batch: vec![],
+        current_batch_cursor: 0,
+        channels_count,
+        sample_rate,
+        batch_frames_count,
+    };
 
-    receive_samples_loop(
+    let receive_loop = receive_samples_loop(
         receiver,
         sample_buffer,
-        channels_count as _,
+        channels_count,
+        sample_rate,
         batch_frames_count,
         average_buffer_frames_count,
-    )
-    .await
+        decoder,
+        converter,
+        sync_with_video,
+    );
+
+    Ok((source, receive_loop))
+}
+
+pub async fn play_audio_loop(
+    device: AudioDevice,
+    format: AudioStreamFormat,
+    config: AudioBufferingConfig,
+    codec: AudioCodecConfig,
+    sync_with_video: bool,
+    receiver: StreamReceiver<AudioPacketHeader>,
+) -> StrResult {
+    let (source, receive_loop) = playback_pipeline(
+        format,
+        device.output_format()?,
+        config,
+        codec,
+        sync_with_video,
+        receiver,
+    )?;
+
+    // Store the stream in a thread (because !Send)
+    let (_shutdown_notifier, shutdown_receiver) = smpsc::channel::<()>();
+    thread::spawn(move || -> StrResult {
+        let (_stream, handle) = OutputStream::try_from_device(&device.inner).map_err(err!())?;
+        handle.play_raw(source).map_err(err!())?;
+
+        shutdown_receiver.recv().ok();
+        Ok(())
+    });
+
+    receive_loop.await
 }
@@ -0,0 +1,292 @@
+// Native PipeWire backend. Instead of using existing devices, the streamer creates a virtual sink
+// for the game audio and a virtual source for the microphone. They exist only while streaming and
+// are removed when the stream is dropped.
+
+use crate::{AudioEncoder, AudioFormatConverter};
+use alvr_common::prelude::*;
+use alvr_packets::{AudioPacketHeader, AudioStreamFormat};
+use alvr_session::{AudioBufferingConfig, AudioCodecConfig, SurroundAudioMode};
+use alvr_sockets::{StreamReceiver, StreamSender};
+use pipewire::{
+    self as pw,
+    properties::{properties, Properties},
+    spa::{
+        self,
+        param::audio::{AudioFormat, AudioInfoRaw, MAX_CHANNELS},
+        pod::{serialize::PodSerializer, Object, Pod, Value},
+        utils::Direction,
+    },
+    stream::{Stream, StreamFlags, StreamRef},
+};
+use std::{
+    io::Cursor,
+    mem, thread,
+    time::{Duration, Instant},
+};
+use tokio::sync::mpsc as tmpsc;
+
+const GAME_AUDIO_NODE_NAME: &str = "ALVR Audio";
+const MICROPHONE_NODE_NAME: &str = "ALVR Microphone";
+
+// Higher than hardware devices, so the session manager selects the virtual devices as default
+const VIRTUAL_DEVICE_PRIORITY: &str = "2000";
+
+const VIRTUAL_SINK_SAMPLE_RATE: u32 = 48000;
+
+// The virtual sink is created with the 7.1 layout only if the surround audio is used
+pub fn virtual_sink_format(surround: &SurroundAudioMode) -> AudioStreamFormat {
+    AudioStreamFormat {
+        sample_rate: VIRTUAL_SINK_SAMPLE_RATE,
+        channels_count: if *surround == SurroundAudioMode::StereoDownmix {
+            2
+        } else {
+            8
+        },
+    }
+}
+
+// Same order as the stream channels (WAVEFORMATEXTENSIBLE)
+fn channel_positions(channels_count: u16) -> StrResult<[u32; MAX_CHANNELS]> {
+    use spa::sys::*;
+
+    let layout: &[u32] = match channels_count {
+        1 => &[SPA_AUDIO_CHANNEL_MONO],
+        2 => &[SPA_AUDIO_CHANNEL_FL, SPA_AUDIO_CHANNEL_FR],
+        8 => &[
+            SPA_AUDIO_CHANNEL_FL,
+            SPA_AUDIO_CHANNEL_FR,
+            SPA_AUDIO_CHANNEL_FC,
+            SPA_AUDIO_CHANNEL_LFE,
+            SPA_AUDIO_CHANNEL_RL,
+            SPA_AUDIO_CHANNEL_RR,
+            SPA_AUDIO_CHANNEL_SL,
+            SPA_AUDIO_CHANNEL_SR,
+        ],
+        count => return fmt_e!("Unsupported channels count for PipeWire: {count}"),
+    };
+
+    let mut positions = [0; MAX_CHANNELS];
+    positions[..layout.len()].copy_from_slice(layout);
+
+    Ok(positions)
+}
+
+fn format_param_bytes(format: AudioStreamFormat) -> StrResult<Vec<u8>> {
+    let mut audio_info = AudioInfoRaw::new();
+    audio_info.set_format(AudioFormat::F32LE);
+    audio_info.set_rate(format.sample_rate);
+    audio_info.set_channels(format.channels_count as _);
+    audio_info.set_position(channel_positions(format.channels_count)?);
+
+    Ok(PodSerializer::serialize(
+        Cursor::new(vec![]),
+        &Value::Object(Object {
+            type_: spa::sys::SPA_TYPE_OBJECT_Format,
+            id: spa::sys::SPA_PARAM_EnumFormat,
+            properties: audio_info.into(),
+        }),
+    )
+    .map_err(err!())?
+    .0
+    .into_inner())
+}
+
+// Quits the PipeWire main loop when the future that owns it is dropped
+struct MainLoopTerminator(pw::channel::Sender<()>);
+
+impl Drop for MainLoopTerminator {
+    fn drop(&mut self) {
+        self.0.send(()).ok();
+    }
+}
+
+// Blocks until the terminator is dropped. The node exists as long as the stream, so it is removed
+// when this returns.
+fn run_stream<D: 'static>(
+    properties: Properties,
+    direction: Direction,
+    format: AudioStreamFormat,
+    user_data: D,
+    process: impl FnMut(&StreamRef, &mut D) + 'static,
+    terminate_receiver: pw::channel::Receiver<()>,
+) -> StrResult {
+    pw::init();
+
+    let main_loop = pw::main_loop::MainLoop::new(None).map_err(err!())?;
+    let context = pw::context::Context::new(&main_loop).map_err(err!())?;
+    let core = context.connect(None).map_err(err!())?;
+
+    let _terminate_receiver = terminate_receiver.attach(main_loop.loop_(), {
+        let main_loop = main_loop.clone();
+        move |_| main_loop.quit()
+    });
+
+    let stream = Stream::new(&core, "alvr-audio", properties).map_err(err!())?;
+    let _listener = stream
+        .add_local_listener_with_user_data(user_data)
+        .process(process)
+        .register()
+        .map_err(err!())?;
+
+    let format_bytes = format_param_bytes(format)?;
+    let mut params = [Pod::from_bytes(&format_bytes).ok_or_else(enone!())?];
+    stream
+        .connect(
+            direction,
+            None,
+            // Not AUTOCONNECT: the nodes are devices, the session manager links the client
+            // streams to them
+            StreamFlags::MAP_BUFFERS | StreamFlags::RT_PROCESS,
+            &mut params,
+        )
+        .map_err(err!())?;
+
+    main_loop.run();
+
+    Ok(())
+}
+
+// Creates a virtual sink with sink_format and streams what is played into it
+pub async fn record_audio_loop(
+    sink_format: AudioStreamFormat,
+    format: AudioStreamFormat,
+    binaural_downmix: bool,
+    codec: AudioCodecConfig,
+    sender: StreamSender<AudioPacketHeader>,
+) -> StrResult {
+    let converter = AudioFormatConverter::new(sink_format, format, binaural_downmix)?;
+    let encoder = AudioEncoder::new(&codec, format)?;
+
+    let (data_sender, data_receiver) = tmpsc::unbounded_channel();
+    let (terminate_sender, terminate_receiver) = pw::channel::channel();
+    let _terminator = MainLoopTerminator(terminate_sender);
+
+    thread::spawn({
+        let data_sender = data_sender.clone();
+        move || {
+            let properties = properties! {
+                *pw::keys::MEDIA_TYPE => "Audio",
+                *pw::keys::MEDIA_CATEGORY => "Capture",
+                *pw::keys::MEDIA_CLASS => "Audio/Sink",
+                *pw::keys::NODE_NAME => GAME_AUDIO_NODE_NAME,
+                *pw::keys::NODE_DESCRIPTION => GAME_AUDIO_NODE_NAME,
+                *pw::keys::PRIORITY_SESSION => VIRTUAL_DEVICE_PRIORITY,
+            };
+
+            let channels_count = sink_format.channels_count as usize;
+            let sample_rate = sink_format.sample_rate as f32;
+
+            let res = run_stream(
+                properties,
+                Direction::Input,
+                sink_format,
+                (),
+                {
+                    let data_sender = data_sender.clone();
+                    move |stream, _| {
+                        let Some(mut buffer) = stream.dequeue_buffer() else {
+                            return;
+                        };
+                        let data = &mut buffer.datas_mut()[0];
+                        let size = data.chunk().size() as usize;
+
+                        if let Some(bytes) = data.data() {
+                            let samples = bytes[..usize::min(size, bytes.len())]
+                                .chunks_exact(mem::size_of::<f32>())
+                                .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+                                .collect::<Vec<_>>();
+
+                            // The callback is called once the buffer is full
+                            let capture_instant = Instant::now()
+                                - Duration::from_secs_f32(
+                                    (samples.len() / channels_count) as f32 / sample_rate,
+                                );
+
+                            data_sender.send(Ok((capture_instant, samples))).ok();
+                        }
+                    }
+                },
+                terminate_receiver,
+            );
+
+            if res.is_err() {
+                data_sender.send(res.map(|_| (Instant::now(), vec![]))).ok();
+            }
+        }
+    });
+
+    crate::send_samples_loop(data_receiver, converter, None, encoder, sender).await
+}
+
+// Creates a virtual source with the stream format and plays the received samples into it
+pub async fn play_audio_loop(
+    format: AudioStreamFormat,
+    config: AudioBufferingConfig,
+    codec: AudioCodecConfig,
+    receiver: StreamReceiver<AudioPacketHeader>,
+) -> StrResult {
+    let (source, receive_loop) =
+        crate::playback_pipeline(format, format, config, codec, false, receiver)?;
+    let channels_count = source.channels_count;
+    let sample_rate = source.sample_rate;
+    let batch_frames_count = source.batch_frames_count;
+
+    let (terminate_sender, terminate_receiver) = pw::channel::channel();
+    let _terminator = MainLoopTerminator(terminate_sender);
+
+    thread::spawn(move || {
+        let properties = properties! {
+            *pw::keys::MEDIA_TYPE => "Audio",
+            *pw::keys::MEDIA_CATEGORY => "Playback",
+            *pw::keys::MEDIA_CLASS => "Audio/Source",
+            *pw::keys::NODE_NAME => MICROPHONE_NODE_NAME,
+            *pw::keys::NODE_DESCRIPTION => MICROPHONE_NODE_NAME,
+            *pw::keys::PRIORITY_SESSION => VIRTUAL_DEVICE_PRIORITY,
+            // Ask for buffers of the same size as the batches
+            *pw::keys::NODE_LATENCY => format!("{batch_frames_count}/{sample_rate}"),
+        };
+
+        let res = run_stream(
+            properties,
+            Direction::Output,
+            format,
+            source,
+            move |stream, source| {
+                let Some(mut buffer) = stream.dequeue_buffer() else {
+                    return;
+                };
+                // Filling the whole buffer would add up to the quantum limit of latency. The
+                // requested size is unknown with old PipeWire versions.
+                let requested_frames_count = match buffer.requested() as usize {
+                    0 => batch_frames_count,
+                    count => count,
+                };
+                let data = &mut buffer.datas_mut()[0];
+                let stride = mem::size_of::<f32>() * channels_count;
+
+                let mut frames_count = 0;
+                if let Some(bytes) = data.data() {
+                    frames_count = usize::min(bytes.len() / stride, requested_frames_count);
+                    for sample_bytes in
+                        bytes[..frames_count * stride].chunks_exact_mut(mem::size_of::<f32>())
+                    {
+                        let sample = source.next().unwrap_or(0.0);
+                        sample_bytes.copy_from_slice(&sample.to_le_bytes());
+                    }
+                }
+
+                let chunk = data.chunk_mut();
+                *chunk.offset_mut() = 0;
+                *chunk.stride_mut() = stride as _;
+                *chunk.size_mut() = (frames_count * stride) as _;
+            },
+            terminate_receiver,
+        );
+
+        if let Err(e) = res {
+            error!("PipeWire microphone error: {e}");
+        }
+    });
+
+    receive_loop.await
+}
@@ -0,0 +1,282 @@
+use crate::{echo_canceller::EchoCanceller, AudioFormatConverter};
+use alvr_common::{once_cell::sync::Lazy, parking_lot::Mutex, prelude::*};
+use alvr_packets::AudioStreamFormat;
+use alvr_session::{
+    settings_schema::Switch, AutomaticGainControlConfig, MicrophoneProcessingConfig,
+};
+use nnnoiseless::DenoiseState;
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+// The noise suppression model works only at 48 kHz with frames of 10ms
+const PROCESSING_FORMAT: AudioStreamFormat = AudioStreamFormat {
+    sample_rate: 48000,
+    channels_count: 1,
+};
+const FRAME_SIZE: usize = DenoiseState::FRAME_SIZE;
+const FRAME_DURATION: Duration = Duration::from_millis(10);
+
+// The model expects samples in the i16 range
+const NOISE_SUPPRESSION_SCALE: f32 = 32768.0;
+const SPEECH_PROBABILITY_THRESHOLD: f32 = 0.5;
+
+// Length of the echo path modeled by the echo canceller, in frames. It must cover the output
+// latency of the game audio and the acoustic path to the microphone.
+const ECHO_PATH_FRAMES: usize = 25;
+const MAX_REFERENCE_DURATION: Duration = Duration::from_secs(1);
+
+// The reference is read continuously and realigned to the microphone only if the capture
+// timestamps drift more than this, to not disturb the adaptive filter
+const MAX_REFERENCE_MISALIGNMENT: Duration = Duration::from_millis(5);
+
+// Used without noise suppression, which provides a voice activity detection
+const MIN_SPEECH_LEVEL_DBFS: f32 = -50.0;
+const LEVEL_SMOOTHING_FACTOR: f32 = 0.05;
+const GAIN_SMOOTHING_FACTOR: f32 = 0.1;
+
+// Game audio played by the speakers, available only while the echo cancellation is active
+static ECHO_REFERENCE: Lazy<Mutex<Option<EchoReference>>> = Lazy::new(|| Mutex::new(None));
+
+fn dbfs_to_amplitude(dbfs: f32) -> f32 {
+    10_f32.powf(dbfs / 20.0)
+}
+
+fn rms(samples: &[f32]) -> f32 {
+    (samples.iter().map(|s| s * s).sum::<f32>() / samples.len() as f32).sqrt()
+}
+
+struct EchoReference {
+    // Converts from the game audio playback format
+    converter: Option<(AudioStreamFormat, AudioFormatConverter)>,
+    samples: VecDeque<f32>,
+    // Time right after the last sample is played
+    end_instant: Instant,
+}
+
+impl EchoReference {
+    fn push(&mut self, samples: &[f32], format: AudioStreamFormat) {
+        if !matches!(&self.converter, Some((current_format, _)) if *current_format == format) {
+            self.converter = AudioFormatConverter::new(format, PROCESSING_FORMAT, false)
+                .ok()
+                .map(|converter| (format, converter));
+            self.samples.clear();
+        }
+
+        if let Some((_, converter)) = &mut self.converter {
+            if let Ok(samples) = converter.process(samples.to_vec()) {
+                self.samples.extend(samples);
+            }
+        }
+
+        let frames_count = samples.len() / format.channels_count as usize;
+        self.end_instant = Instant::now()
+            + Duration::from_secs_f32(frames_count as f32 / format.sample_rate as f32);
+
+        let max_len =
+            (MAX_REFERENCE_DURATION.as_secs_f32() * PROCESSING_FORMAT.sample_rate as f32) as usize;
+        if self.samples.len() > max_len {
+            self.samples.drain(..self.samples.len() - max_len);
+        }
+    }
+
+    // Returns the reference played from start_instant. Missing samples are silent
+    fn take(&mut self, start_instant: Instant, count: usize) -> Vec<f32> {
+        let sample_rate = PROCESSING_FORMAT.sample_rate as f32;
+        let samples_until_end = if self.end_instant >= start_instant {
+            ((self.end_instant - start_instant).as_secs_f32() * sample_rate) as isize
+        } else {
+            -(((start_instant - self.end_instant).as_secs_f32() * sample_rate) as isize)
+        };
+
+        // Consumed samples are removed, so a continuous read starts at index 0
+        let mut start_index = self.samples.len() as isize - samples_until_end;
+        let max_misalignment = (MAX_REFERENCE_MISALIGNMENT.as_secs_f32() * sample_rate) as isize;
+        if start_index.abs() <= max_misalignment {
+            start_index = 0;
+        }
+
+        let block = (start_index..start_index + count as isize)
+            .map(|index| {
+                usize::try_from(index)
+                    .ok()
+                    .and_then(|index| self.samples.get(index).copied())
+                    .unwrap_or(0.0)
+            })
+            .collect();
+
+        let consumed_count = (start_index + count as isize).clamp(0, self.samples.len() as isize);
+        self.samples.drain(..consumed_count as usize);
+
+        block
+    }
+}
+
+// Called by the audio callback of the game audio playback
+pub fn report_played_samples(samples: &[f32], format: AudioStreamFormat) {
+    if let Some(reference) = &mut *ECHO_REFERENCE.lock() {
+        reference.push(samples, format);
+    }
+}
+
+struct GainControl {
+    target_level: f32,
+    max_gain: f32,
+    speech_level: f32,
+    gain: f32,
+}
+
+impl GainControl {
+    fn new(config: &AutomaticGainControlConfig) -> Self {
+        let target_level = dbfs_to_amplitude(config.target_level_dbfs);
+
+        Self {
+            target_level,
+            max_gain: dbfs_to_amplitude(config.max_gain_db),
+            speech_level: target_level,
+            gain: 1.0,
+        }
+    }
+
+    // The level is tracked only during speech, so the noise is not amplified in pauses
+    fn process(&mut self, frame: &mut [f32], speech_probability: Option<f32>) {
+        let level = rms(frame);
+        let is_speech = match speech_probability {
+            Some(probability) => probability > SPEECH_PROBABILITY_THRESHOLD,
+            None => level > dbfs_to_amplitude(MIN_SPEECH_LEVEL_DBFS),
+        };
+        if is_speech {
+            self.speech_level += (level - self.speech_level) * LEVEL_SMOOTHING_FACTOR;
+        }
+
+        let target_gain = f32::min(self.target_level / self.speech_level, self.max_gain);
+        let new_gain = self.gain + (target_gain - self.gain) * GAIN_SMOOTHING_FACTOR;
+
+        // Ramp the gain inside the frame to avoid clicks
+        let frame_len = frame.len() as f32;
+        for (index, sample) in frame.iter_mut().enumerate() {
+            let gain = self.gain + (new_gain - self.gain) * index as f32 / frame_len;
+            *sample = (*sample * gain).clamp(-1.0, 1.0);
+        }
+
+        self.gain = new_gain;
+    }
+}
+
+// Echo cancellation, noise suppression and automatic gain control, in this order. The echo must be
+// removed first, because the other stages are not linear.
+pub struct MicrophoneProcessor {
+    input_converter: AudioFormatConverter,
+    output_converter: AudioFormatConverter,
+    pending_samples: Vec<f32>,
+    // Capture time of the first pending sample
+    pending_instant: Instant,
+    echo_canceller: Option<EchoCanceller>,
+    noise_suppressor: Option<Box<DenoiseState<'static>>>,
+    gain_control: Option<GainControl>,
+}
+
+impl MicrophoneProcessor {
+    // Returns None if all the stages are disabled, then the samples need only a format conversion
+    pub fn new(
+        config: &MicrophoneProcessingConfig,
+        input: AudioStreamFormat,
+        output: AudioStreamFormat,
+    ) -> StrResult<Option<Self>> {
+        if !config.noise_suppression
+            && !config.echo_cancellation
+            && matches!(config.automatic_gain_control, Switch::Disabled)
+        {
+            return Ok(None);
+        }
+
+        let echo_canceller = config.echo_cancellation.then(|| {
+            *ECHO_REFERENCE.lock() = Some(EchoReference {
+                converter: None,
+                samples: VecDeque::new(),
+                end_instant: Instant::now(),
+            });
+
+            EchoCanceller::new(FRAME_SIZE, ECHO_PATH_FRAMES)
+        });
+
+        let gain_control = if let Switch::Enabled(config) = &config.automatic_gain_control {
+            Some(GainControl::new(config))
+        } else {
+            None
+        };
+
+        Ok(Some(Self {
+            input_converter: AudioFormatConverter::new(input, PROCESSING_FORMAT, false)?,
+            output_converter: AudioFormatConverter::new(PROCESSING_FORMAT, output, false)?,
+            pending_samples: vec![],
+            pending_instant: Instant::now(),
+            echo_canceller,
+            noise_suppressor: config.noise_suppression.then(DenoiseState::new),
+            gain_control,
+        }))
+    }
+
+    // Returns the processed samples in the output format, with the capture time of the first one.
+    // Samples are processed in whole frames, the rest is kept for the next call.
+    pub fn process(
+        &mut self,
+        samples: Vec<f32>,
+        capture_instant: Instant,
+    ) -> StrResult<(Instant, Vec<f32>)> {
+        if self.pending_samples.is_empty() {
+            self.pending_instant = capture_instant;
+        }
+        self.pending_samples
+            .extend(self.input_converter.process(samples)?);
+
+        let output_instant = self.pending_instant;
+        let mut output = vec![];
+        while self.pending_samples.len() >= FRAME_SIZE {
+            let mut frame = self.pending_samples.drain(..FRAME_SIZE).collect::<Vec<_>>();
+
+            if let Some(echo_canceller) = &mut self.echo_canceller {
+                let reference = ECHO_REFERENCE
+                    .lock()
+                    .as_mut()
+                    .map(|reference| reference.take(self.pending_instant, FRAME_SIZE))
+                    .unwrap_or_else(|| vec![0.0; FRAME_SIZE]);
+
+                frame = echo_canceller.process(&reference, &frame);
+            }
+
+            let mut speech_probability = None;
+            if let Some(noise_suppressor) = &mut self.noise_suppressor {
+                let input = frame
+                    .iter()
+                    .map(|sample| sample * NOISE_SUPPRESSION_SCALE)
+                    .collect::<Vec<_>>();
+                let mut denoised = vec![0.0; FRAME_SIZE];
+                speech_probability = Some(noise_suppressor.process_frame(&mut denoised, &input));
+
+                frame = denoised
+                    .iter()
+                    .map(|sample| sample / NOISE_SUPPRESSION_SCALE)
+                    .collect();
+            }
+
+            if let Some(gain_control) = &mut self.gain_control {
+                gain_control.process(&mut frame, speech_probability);
+            }
+
+            output.extend(frame);
+            self.pending_instant += FRAME_DURATION;
+        }
+
+        Ok((output_instant, self.output_converter.process(output)?))
+    }
+}
+
+impl Drop for MicrophoneProcessor {
+    fn drop(&mut self) {
+        if self.echo_canceller.is_some() {
+            *ECHO_REFERENCE.lock() = None;
+        }
+    }
+}
@@ -0,0 +1,127 @@
+mod watcher;
+
+#[cfg(windows)]
+mod windows_endpoints;
+
+pub use watcher::{AudioDeviceChange, AudioDevicesWatcher};
+#[cfg(windows)]
+pub use windows_endpoints::endpoint_from_id;
+
+use alvr_common::prelude::*;
+use alvr_packets::{AudioDeviceInfo, AudioDevicesList};
+use alvr_session::LinuxAudioBackend;
+use cpal::{
+    traits::{DeviceTrait, HostTrait},
+    Device, Host,
+};
+
+#[cfg_attr(not(target_os = "linux"), allow(unused_variables))]
+pub fn host(linux_backend: Option<LinuxAudioBackend>) -> Host {
+    #[cfg(target_os = "linux")]
+    return match linux_backend {
+        Some(LinuxAudioBackend::Alsa) => cpal::host_from_id(cpal::HostId::Alsa).unwrap(),
+        Some(LinuxAudioBackend::Jack) => cpal::host_from_id(cpal::HostId::Jack).unwrap(),
+        Some(LinuxAudioBackend::PipeWire) | None => cpal::default_host(),
+    };
+    #[cfg(not(target_os = "linux"))]
+    cpal::default_host()
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum DeviceDirection {
+    Output,
+    Input,
+    Any,
+}
+
+// Pairs the devices with their ID. Names are not unique on Windows and can be changed by the user,
+// there the ID is the endpoint ID. On the other platforms cpal does not expose any ID, so the name is
+// used. With ALSA it is the PCM name, which refers to the card by its ID and not by its index (for
+// example "hw:CARD=PCH,DEV=0"), so it is stable. JACK has a single device per direction. On macOS the
+// name is the one displayed by the OS and is not guaranteed to be unique.
+pub fn devices_with_ids(
+    host: &Host,
+    direction: DeviceDirection,
+) -> StrResult<Vec<(Device, String)>> {
+    #[cfg(windows)]
+    {
+        // cpal enumerates the active endpoints in the same order, check that the list did not
+        // change in the meantime
+        let devices = host.devices().map_err(err!())?.collect::<Vec<_>>();
+        let endpoints = windows_endpoints::active_endpoints()?;
+        if devices.len() != endpoints.len() {
+            return fmt_e!("Audio devices changed while listing them");
+        }
+
+        let mut devices_with_ids = vec![];
+        for (device, endpoint) in devices.into_iter().zip(endpoints) {
+            if device.name().map_err(err!())? != endpoint.name {
+                return fmt_e!("Audio devices changed while listing them");
+            }
+
+            if direction == DeviceDirection::Any
+                || (direction == DeviceDirection::Output) == endpoint.is_output
+            {
+                devices_with_ids.push((device, endpoint.id));
+            }
+        }
+
+        Ok(devices_with_ids)
+    }
+    #[cfg(not(windows))]
+    {
+        let devices = match direction {
+            DeviceDirection::Output => host.output_devices().map_err(err!())?.collect::<Vec<_>>(),
+            DeviceDirection::Input => host.input_devices().map_err(err!())?.collect(),
+            DeviceDirection::Any => host.devices().map_err(err!())?.collect(),
+        };
+
+        Ok(devices
+            .into_iter()
+            .filter_map(|device| {
+                let name = device.name().ok()?;
+                Some((device, name))
+            })
+            .collect())
+    }
+}
+
+pub fn default_device_with_id(host: &Host, is_output: bool) -> StrResult<(Device, String)> {
+    let device = if is_output {
+        host.default_output_device()
+            .ok_or_else(|| "No output audio device found".to_owned())?
+    } else {
+        host.default_input_device()
+            .ok_or_else(|| "No input audio device found".to_owned())?
+    };
+
+    #[cfg(windows)]
+    let id = windows_endpoints::default_endpoint_id(is_output)?;
+    #[cfg(not(windows))]
+    let id = device.name().map_err(err!())?;
+
+    Ok((device, id))
+}
+
+fn devices_info(host: &Host, direction: DeviceDirection) -> StrResult<Vec<AudioDeviceInfo>> {
+    Ok(devices_with_ids(host, direction)?
+        .into_iter()
+        .filter_map(|(device, id)| {
+            Some(AudioDeviceInfo {
+                id,
+                name: device.name().ok()?,
+            })
+        })
+        .collect())
+}
+
+pub fn devices_list(linux_backend: Option<LinuxAudioBackend>) -> StrResult<AudioDevicesList> {
+    let host = host(linux_backend);
+
+    Ok(AudioDevicesList {
+        output: devices_info(&host, DeviceDirection::Output)?,
+        input: devices_info(&host, DeviceDirection::Input)?,
+        default_output: default_device_with_id(&host, true).ok().map(|(_, id)| id),
+        default_input: default_device_with_id(&host, false).ok().map(|(_, id)| id),
+    })
+}
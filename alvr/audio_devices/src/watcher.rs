@@ -0,0 +1,207 @@
+use alvr_common::prelude::*;
+use alvr_packets::{AudioDeviceInfo, AudioDevicesList};
+use alvr_session::LinuxAudioBackend;
+use std::time::Duration;
+
+// cpal has no device notifications, so the devices are polled. Listing the devices is cheap on
+// every backend except ALSA, where it opens every PCM.
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum AudioDeviceChange {
+    Added {
+        device: AudioDeviceInfo,
+        is_output: bool,
+    },
+    Removed {
+        device: AudioDeviceInfo,
+        is_output: bool,
+    },
+    // Contain the ID of the new default device
+    DefaultOutputChanged(Option<String>),
+    DefaultInputChanged(Option<String>),
+}
+
+fn diff_devices(
+    old: &[AudioDeviceInfo],
+    new: &[AudioDeviceInfo],
+    is_output: bool,
+) -> Vec<AudioDeviceChange> {
+    let removed = old
+        .iter()
+        .filter(|device| !new.iter().any(|d| d.id == device.id))
+        .map(|device| AudioDeviceChange::Removed {
+            device: device.clone(),
+            is_output,
+        });
+    let added = new
+        .iter()
+        .filter(|device| !old.iter().any(|d| d.id == device.id))
+        .map(|device| AudioDeviceChange::Added {
+            device: device.clone(),
+            is_output,
+        });
+
+    removed.chain(added).collect()
+}
+
+fn diff_lists(old: &AudioDevicesList, new: &AudioDevicesList) -> Vec<AudioDeviceChange> {
+    let mut changes = diff_devices(&old.output, &new.output, true);
+    changes.extend(diff_devices(&old.input, &new.input, false));
+
+    if new.default_output != old.default_output {
+        changes.push(AudioDeviceChange::DefaultOutputChanged(
+            new.default_output.clone(),
+        ));
+    }
+    if new.default_input != old.default_input {
+        changes.push(AudioDeviceChange::DefaultInputChanged(
+            new.default_input.clone(),
+        ));
+    }
+
+    changes
+}
+
+pub struct AudioDevicesWatcher {
+    linux_backend: Option<LinuxAudioBackend>,
+    list: AudioDevicesList,
+    // Devices missing only from the last poll. ALSA cannot open the PCMs that are in use, including
+    // the ones opened by ALVR, so a device is reported as removed only if it is missing from two
+    // consecutive polls
+    pending_removals: Vec<AudioDeviceChange>,
+}
+
+impl AudioDevicesWatcher {
+    pub fn new(linux_backend: Option<LinuxAudioBackend>) -> StrResult<Self> {
+        Ok(Self {
+            list: crate::devices_list(linux_backend)?,
+            linux_backend,
+            pending_removals: vec![],
+        })
+    }
+
+    fn update(&mut self, mut list: AudioDevicesList) -> Vec<AudioDeviceChange> {
+        let (removals, other_changes): (Vec<_>, Vec<_>) = diff_lists(&self.list, &list)
+            .into_iter()
+            .partition(|change| matches!(change, AudioDeviceChange::Removed { .. }));
+
+        let mut changes = vec![];
+        let mut pending_removals = vec![];
+        for removal in removals {
+            if self.pending_removals.contains(&removal) {
+                changes.push(removal);
+            } else {
+                // Keep the device until the removal is confirmed
+                if let AudioDeviceChange::Removed { device, is_output } = &removal {
+                    if *is_output {
+                        list.output.push(device.clone());
+                    } else {
+                        list.input.push(device.clone());
+                    }
+                }
+
+                pending_removals.push(removal);
+            }
+        }
+        changes.extend(other_changes);
+
+        self.list = list;
+        self.pending_removals = pending_removals;
+
+        changes
+    }
+
+    pub fn list(&self) -> &AudioDevicesList {
+        &self.list
+    }
+
+    // Waits until devices are added or removed or the default devices change. Errors while listing
+    // the devices are ignored, they are usually transient during the change.
+    pub async fn changed(&mut self) -> Vec<AudioDeviceChange> {
+        loop {
+            tokio::time::sleep(POLL_INTERVAL).await;
+
+            if let Ok(list) = crate::devices_list(self.linux_backend) {
+                let changes = self.update(list);
+
+                if !changes.is_empty() {
+                    return changes;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn device(id: &str) -> AudioDeviceInfo {
+        AudioDeviceInfo {
+            id: id.into(),
+            name: "Speakers".into(),
+        }
+    }
+
+    #[test]
+    fn devices_are_compared_by_id() {
+        let old = AudioDevicesList {
+            output: vec![device("a"), device("b")],
+            input: vec![],
+            default_output: Some("a".into()),
+            default_input: None,
+        };
+        let new = AudioDevicesList {
+            output: vec![device("b"), device("c")],
+            input: vec![],
+            default_output: Some("c".into()),
+            default_input: None,
+        };
+
+        assert_eq!(
+            diff_lists(&old, &new),
+            vec![
+                AudioDeviceChange::Removed {
+                    device: device("a"),
+                    is_output: true
+                },
+                AudioDeviceChange::Added {
+                    device: device("c"),
+                    is_output: true
+                },
+                AudioDeviceChange::DefaultOutputChanged(Some("c".into())),
+            ]
+        );
+    }
+
+    #[test]
+    fn removal_is_confirmed_by_second_poll() {
+        let list = |output| AudioDevicesList {
+            output,
+            input: vec![],
+            default_output: None,
+            default_input: None,
+        };
+        let removed_a = AudioDeviceChange::Removed {
+            device: device("a"),
+            is_output: true,
+        };
+
+        let mut watcher = AudioDevicesWatcher {
+            linux_backend: None,
+            list: list(vec![device("a"), device("b")]),
+            pending_removals: vec![],
+        };
+
+        // Missing from a single poll, for example because the device is in use
+        assert!(watcher.update(list(vec![device("b")])).is_empty());
+        assert!(watcher
+            .update(list(vec![device("a"), device("b")]))
+            .is_empty());
+
+        assert!(watcher.update(list(vec![device("b")])).is_empty());
+        assert_eq!(watcher.update(list(vec![device("b")])), vec![removed_a]);
+        assert!(watcher.update(list(vec![device("b")])).is_empty());
+    }
+}
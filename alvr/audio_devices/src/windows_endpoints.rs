@@ -0,0 +1,104 @@
+use alvr_common::prelude::*;
+use widestring::U16CStr;
+use windows::{
+    core::{ComInterface, PWSTR},
+    Win32::{
+        Devices::FunctionDiscovery::PKEY_Device_FriendlyName,
+        Media::Audio::{
+            eAll, eCapture, eConsole, eRender, IMMDevice, IMMDeviceEnumerator, IMMEndpoint,
+            MMDeviceEnumerator, DEVICE_STATE_ACTIVE,
+        },
+        System::Com::{self, CLSCTX_ALL, COINIT_MULTITHREADED, STGM_READ},
+    },
+};
+
+pub struct Endpoint {
+    pub id: String,
+    pub name: String,
+    pub is_output: bool,
+}
+
+fn device_enumerator() -> StrResult<IMMDeviceEnumerator> {
+    unsafe {
+        // This will fail the second time is called, ignore the error
+        Com::CoInitializeEx(None, COINIT_MULTITHREADED).ok();
+
+        Com::CoCreateInstance(&MMDeviceEnumerator, None, CLSCTX_ALL).map_err(err!())
+    }
+}
+
+unsafe fn take_string(string: PWSTR) -> StrResult<String> {
+    let res = U16CStr::from_ptr_str(string.0).to_string().map_err(err!());
+    Com::CoTaskMemFree(Some(string.0 as _));
+
+    res
+}
+
+unsafe fn endpoint_id(imm_device: &IMMDevice) -> StrResult<String> {
+    take_string(imm_device.GetId().map_err(err!())?)
+}
+
+// Same enumeration used by cpal, the order is the same as the devices returned by Host::devices()
+pub fn active_endpoints() -> StrResult<Vec<Endpoint>> {
+    let imm_device_enumerator = device_enumerator()?;
+
+    unsafe {
+        let imm_device_collection = imm_device_enumerator
+            .EnumAudioEndpoints(eAll, DEVICE_STATE_ACTIVE)
+            .map_err(err!())?;
+
+        let count = imm_device_collection.GetCount().map_err(err!())?;
+
+        let mut endpoints = vec![];
+        for i in 0..count {
+            let imm_device = imm_device_collection.Item(i).map_err(err!())?;
+
+            let property_store = imm_device.OpenPropertyStore(STGM_READ).map_err(err!())?;
+            let mut prop_variant = property_store
+                .GetValue(&PKEY_Device_FriendlyName)
+                .map_err(err!())?;
+            let name = U16CStr::from_ptr_str(prop_variant.Anonymous.Anonymous.Anonymous.pwszVal.0)
+                .to_string()
+                .map_err(err!())?;
+            Com::StructuredStorage::PropVariantClear(&mut prop_variant).map_err(err!())?;
+
+            let data_flow = imm_device
+                .cast::<IMMEndpoint>()
+                .map_err(err!())?
+                .GetDataFlow()
+                .map_err(err!())?;
+
+            endpoints.push(Endpoint {
+                id: endpoint_id(&imm_device)?,
+                name,
+                is_output: data_flow == eRender,
+            });
+        }
+
+        Ok(endpoints)
+    }
+}
+
+// cpal uses the console role for the default devices
+pub fn default_endpoint_id(is_output: bool) -> StrResult<String> {
+    let imm_device_enumerator = device_enumerator()?;
+
+    unsafe {
+        let imm_device = imm_device_enumerator
+            .GetDefaultAudioEndpoint(if is_output { eRender } else { eCapture }, eConsole)
+            .map_err(err!())?;
+
+        endpoint_id(&imm_device)
+    }
+}
+
+pub fn endpoint_from_id(id: &str) -> StrResult<IMMDevice> {
+    let imm_device_enumerator = device_enumerator()?;
+    let id = widestring::U16CString::from_str(id).map_err(err!())?;
+
+    unsafe {
+        imm_device_enumerator
+            .GetDevice(windows::core::PCWSTR(id.as_ptr()))
+            .map_err(err!())
+    }
+}
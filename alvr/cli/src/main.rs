@@ -0,0 +1,421 @@
+mod statistics_recording;
+
+use alvr_common::prelude::*;
+use alvr_events::EventFilter;
+use alvr_packets::{
+    ClientListAction, PathSegment, PathValuePair, ServerRequest, SessionChangeOrigin,
+    SESSION_CHANGE_ORIGIN_HEADER,
+};
+use alvr_server_io::ServerDataManager;
+use alvr_session::SessionDesc;
+use pico_args::Arguments;
+use serde_json as json;
+use std::{
+    env,
+    fs::{self, File},
+    io::{self, BufWriter},
+    net::IpAddr,
+    path::{Path, PathBuf},
+    process,
+    time::Duration,
+};
+use tungstenite::{client::IntoClientRequest, http::HeaderValue};
+
+const HELP_STR: &str = r#"
+alvr_cli
+Manage the ALVR streamer session and clients without the dashboard. Requests are sent to the
+streamer if it is running, otherwise the session file is edited directly.
+
+USAGE:
+    alvr_cli <SUBCOMMAND> [ARGS]
+
+SUBCOMMANDS:
+    get [PATH]                  Print the session, or the session value at PATH, as JSON
+    set <PATH> <VALUE>          Set the session value at PATH. VALUE is parsed as JSON, falling
+                                back to a plain string
+    list-clients                Print the known clients as JSON
+    trust-client <HOSTNAME>     Trust a client
+    remove-client <HOSTNAME>    Remove a client entry
+    add-ip <HOSTNAME> <IP>      Add a manual IP to a client. The client is added as trusted if
+                                missing
+    list-drivers                Print the registered SteamVR drivers
+    register-driver             Register the ALVR driver in SteamVR
+    unregister-driver <PATH>    Unregister a SteamVR driver
+    start-recording             Start recording the video stream. Requires the streamer
+    stop-recording              Stop recording the video stream. Requires the streamer
+    start-frame-tracing         Start recording the timeline of every frame. Requires the streamer
+    stop-frame-tracing          Save the frame timeline as a Chrome/Perfetto trace in the log
+                                folder. Requires the streamer
+    tail-events [FILTER]        Print streamer events as JSON lines. Requires the streamer.
+                                FILTER example: '{"event_types": ["Log"], "min_log_severity":
+                                "Warning", "max_statistics_rate_hz": 1}'
+    stats-summary <FILE>        Print latency percentiles and stutter counts of a statistics
+                                recording (.alvrstats)
+    stats-to-csv <FILE> [OUT]   Convert a statistics recording to CSV, printed or written to OUT
+
+FLAGS:
+    --help                      Print this text
+
+ARGS:
+    --session <PATH>            Path of session.json. By default it is found relative to the
+                                alvr_cli executable
+    --port <PORT>               Web server port. By default it is read from the session
+
+PATH EXAMPLE:
+    session_settings.video.preferred_fps
+"#;
+
+const REQUEST_TIMEOUT: Duration = Duration::from_millis(500);
+
+struct Context {
+    session_path: PathBuf,
+    // Read-only copy of the session file, used by the commands that do not modify it
+    session: SessionDesc,
+    // Loading it can migrate the session and enumerates the GPUs, so it is created only when the
+    // session file has to be modified
+    data_manager: Option<ServerDataManager>,
+    token_path: PathBuf,
+    port: u16,
+    request_agent: ureq::Agent,
+    // Checked on the first request, so the commands that only read the session do not wait for
+    // the ping timeout
+    server_connected: Option<bool>,
+}
+
+impl Context {
+    fn new(session_path: PathBuf, token_path: PathBuf, port: Option<u16>) -> Self {
+        let session = read_session(&session_path);
+        let port = port.unwrap_or(session.to_settings().connection.web_server_port);
+
+        let request_agent = ureq::AgentBuilder::new()
+            .timeout_connect(REQUEST_TIMEOUT)
+            .build();
+
+        Self {
+            session_path,
+            session,
+            data_manager: None,
+            token_path,
+            port,
+            request_agent,
+            server_connected: None,
+        }
+    }
+
+    fn server_connected(&mut self) -> bool {
+        let port = self.port;
+        let request_agent = &self.request_agent;
+
+        *self.server_connected.get_or_insert_with(|| {
+            request_agent
+                .get(&format!("http://127.0.0.1:{port}/api/ping"))
+                .call()
+                .is_ok()
+        })
+    }
+
+    // The token is created by the streamer, so it must exist if the streamer is running
+    fn authorization(&self) -> StrResult<String> {
+        alvr_server_io::load_web_server_token(&self.token_path)
+            .map(|token| format!("Bearer {token}"))
+    }
+
+    fn data_manager(&mut self) -> &mut ServerDataManager {
+        self.data_manager
+            .get_or_insert_with(|| ServerDataManager::new(&self.session_path))
+    }
+
+    // Send the request to the streamer, or apply it to the session file if it is not running
+    fn request(&mut self, request: ServerRequest) -> StrResult {
+        if self.server_connected() {
+            self.request_agent
+                .post(&format!(
+                    "http://127.0.0.1:{}/api/dashboard-request",
+                    self.port
+                ))
+                .set("Authorization", &self.authorization()?)
+                .set(SESSION_CHANGE_ORIGIN_HEADER, "Cli")
+                .send_json(&request)
+                .map_err(err!())?;
+
+            return Ok(());
+        }
+
+        match request {
+            ServerRequest::SetValues(descs) => self
+                .data_manager()
+                .set_values(descs, SessionChangeOrigin::Cli),
+            ServerRequest::UpdateClientList { hostname, action } => {
+                self.data_manager().update_client_list(hostname, action);
+
+                Ok(())
+            }
+            ServerRequest::RegisterAlvrDriver => {
+                let alvr_driver_dir = alvr_filesystem::filesystem_layout_from_dashboard_exe(
+                    &env::current_exe().map_err(err!())?,
+                )
+                .openvr_driver_root_dir;
+
+                alvr_server_io::driver_registration(&[alvr_driver_dir], true)
+            }
+            ServerRequest::UnregisterDriver(path) => {
+                alvr_server_io::driver_registration(&[path], false)
+            }
+            _ => fmt_e!("Cannot perform action, streamer is not running"),
+        }
+    }
+}
+
+// Unlike ServerDataManager::new(), this never writes the session file. Invalid or missing files
+// result in the default session
+fn read_session(session_path: &Path) -> SessionDesc {
+    let mut session = SessionDesc::default();
+
+    if let Ok(session_string) = fs::read_to_string(session_path) {
+        if let Err(e) = json::from_str(&session_string)
+            .map_err(err!())
+            .and_then(|session_json| session.merge_from_json(&session_json))
+        {
+            warn!("Failed to read session file: {e}");
+        }
+    }
+
+    session
+}
+
+// Unlike alvr_packets::parse_path(), malformed segments are reported instead of skipped
+fn parse_path(path: &str) -> StrResult<Vec<PathSegment>> {
+    let mut segments = vec![];
+    for part in path.split('.') {
+        let mut subparts = part.split('[');
+
+        // split() always returns at least one item
+        let name = subparts.next().unwrap();
+        if name.is_empty() {
+            return fmt_e!("Empty segment in path \"{path}\"");
+        }
+        segments.push(name.into());
+
+        for index in subparts {
+            let index = index
+                .strip_suffix(']')
+                .and_then(|index| index.parse::<usize>().ok())
+                .ok_or_else(|| format!("Invalid index \"[{index}\" in path \"{path}\""))?;
+            segments.push(index.into());
+        }
+    }
+
+    Ok(segments)
+}
+
+fn get_session_value(session_json: &json::Value, path: &[PathSegment]) -> StrResult<json::Value> {
+    let mut value_ref = session_json;
+    for segment in path {
+        value_ref = match segment {
+            PathSegment::Name(name) => value_ref
+                .get(name)
+                .ok_or_else(|| format!("From path {path:?}: segment \"{name}\" not found"))?,
+            PathSegment::Index(index) => value_ref
+                .get(index)
+                .ok_or_else(|| format!("From path {path:?}: segment [{index}] not found"))?,
+        };
+    }
+
+    Ok(value_ref.clone())
+}
+
+fn tail_events(context: &mut Context, filter: Option<&str>) -> StrResult {
+    if !context.server_connected() {
+        return fmt_e!("Cannot tail events, streamer is not running");
+    }
+    let authorization = context.authorization()?;
+
+    let mut request = format!("ws://127.0.0.1:{}/api/events", context.port)
+        .into_client_request()
+        .map_err(err!())?;
+    request.headers_mut().insert(
+        "Authorization",
+        HeaderValue::from_str(&authorization).map_err(err!())?,
+    );
+
+    let (mut ws, _) = tungstenite::connect(request)
+        .map_err(|e| format!("Failed to connect to the streamer: {e}"))?;
+
+    if let Some(filter) = filter {
+        // Validate locally, the streamer ignores invalid filters
+        json::from_str::<EventFilter>(filter).map_err(err!())?;
+
+        ws.write_message(tungstenite::Message::Text(filter.to_owned()))
+            .map_err(err!())?;
+    }
+
+    loop {
+        match ws.read_message().map_err(err!())? {
+            tungstenite::Message::Text(json_string) => println!("{json_string}"),
+            tungstenite::Message::Close(_) => return Ok(()),
+            _ => (),
+        }
+    }
+}
+
+fn run(subcommand: &str, free_args: &[String], context: &mut Context) -> StrResult {
+    let session_json = json::to_value(&context.session).map_err(err!())?;
+
+    match (subcommand, free_args) {
+        ("get", []) => println!("{session_json:#}"),
+        ("get", [path]) => println!(
+            "{:#}",
+            get_session_value(&session_json, &parse_path(path)?)?
+        ),
+        ("set", [path, value]) => {
+            let path = parse_path(path)?;
+
+            // Validate the path before sending the request, since the streamer only logs errors
+            get_session_value(&session_json, &path)?;
+
+            let value =
+                json::from_str(value).unwrap_or_else(|_| json::Value::String(value.clone()));
+
+            context.request(ServerRequest::SetValues(vec![PathValuePair {
+                path,
+                value,
+            }]))?;
+        }
+        ("list-clients", []) => println!(
+            "{:#}",
+            json::to_value(&context.session.client_connections).map_err(err!())?
+        ),
+        ("trust-client", [hostname]) => context.request(ServerRequest::UpdateClientList {
+            hostname: hostname.clone(),
+            action: ClientListAction::Trust,
+        })?,
+        ("remove-client", [hostname]) => context.request(ServerRequest::UpdateClientList {
+            hostname: hostname.clone(),
+            action: ClientListAction::RemoveEntry,
+        })?,
+        ("add-ip", [hostname, ip]) => {
+            let ip = ip.parse::<IpAddr>().map_err(err!())?;
+
+            // SetManualIps replaces the whole list
+            let action = if let Some(connection) = context.session.client_connections.get(hostname)
+            {
+                let mut manual_ips = connection.manual_ips.iter().cloned().collect::<Vec<_>>();
+                if !manual_ips.contains(&ip) {
+                    manual_ips.push(ip);
+                }
+
+                ClientListAction::SetManualIps(manual_ips)
+            } else {
+                ClientListAction::AddIfMissing {
+                    trusted: true,
+                    manual_ips: vec![ip],
+                }
+            };
+
+            context.request(ServerRequest::UpdateClientList {
+                hostname: hostname.clone(),
+                action,
+            })?;
+        }
+        ("list-drivers", []) => {
+            for path in alvr_server_io::get_registered_drivers()? {
+                println!("{}", path.to_string_lossy());
+            }
+        }
+        ("register-driver", []) => context.request(ServerRequest::RegisterAlvrDriver)?,
+        ("unregister-driver", [path]) => {
+            context.request(ServerRequest::UnregisterDriver(PathBuf::from(path)))?
+        }
+        ("start-recording", []) => context.request(ServerRequest::StartRecording)?,
+        ("stop-recording", []) => context.request(ServerRequest::StopRecording)?,
+        ("start-frame-tracing", []) => context.request(ServerRequest::StartFrameTracing)?,
+        ("stop-frame-tracing", []) => context.request(ServerRequest::StopFrameTracing)?,
+        ("tail-events", []) => tail_events(context, None)?,
+        ("tail-events", [filter]) => tail_events(context, Some(filter))?,
+        _ => return fmt_e!("Unrecognized subcommand or wrong arguments"),
+    }
+
+    Ok(())
+}
+
+// These commands work only on local files, so they do not need the session or the streamer
+fn run_statistics(subcommand: &str, free_args: &[String]) -> StrResult {
+    match (subcommand, free_args) {
+        ("stats-summary", [path]) => {
+            let records = statistics_recording::read_recording(path.as_ref())?;
+            print!("{}", statistics_recording::summary(&records));
+        }
+        ("stats-to-csv", [path]) => {
+            let records = statistics_recording::read_recording(path.as_ref())?;
+            statistics_recording::write_csv(&records, io::stdout().lock())?;
+        }
+        ("stats-to-csv", [path, out_path]) => {
+            let records = statistics_recording::read_recording(path.as_ref())?;
+            let file = File::create(out_path).map_err(err!())?;
+            statistics_recording::write_csv(&records, BufWriter::new(file))?;
+        }
+        _ => return fmt_e!("Unrecognized subcommand or wrong arguments"),
+    }
+
+    Ok(())
+}
+
+fn main() {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("warn")).init();
+
+    let mut args = Arguments::from_env();
+
+    if args.contains(["-h", "--help"]) {
+        println!("{HELP_STR}");
+        return;
+    }
+
+    let Ok(Some(subcommand)) = args.subcommand() else {
+        eprintln!("\nMissing subcommand.");
+        println!("{HELP_STR}");
+        process::exit(1);
+    };
+
+    let session_path: Option<PathBuf> = args.opt_value_from_str("--session").unwrap_or(None);
+    let port: Option<u16> = args.opt_value_from_str("--port").unwrap_or(None);
+
+    let free_args = args
+        .finish()
+        .into_iter()
+        .map(|arg| arg.to_string_lossy().into_owned())
+        .collect::<Vec<_>>();
+
+    let res = if subcommand.starts_with("stats-") {
+        run_statistics(&subcommand, &free_args)
+    } else {
+        let layout =
+            alvr_filesystem::filesystem_layout_from_dashboard_exe(&env::current_exe().unwrap());
+        let session_path = session_path.unwrap_or_else(|| layout.session());
+
+        let mut context = Context::new(session_path, layout.web_server_token(), port);
+
+        run(&subcommand, &free_args, &mut context)
+    };
+
+    if let Err(e) = res {
+        eprintln!("{e}");
+        process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn path_parsing() {
+        assert_eq!(
+            format!("{:?}", parse_path("a.b[0][1].c").unwrap()),
+            "[a, b, [0], [1], c]"
+        );
+
+        assert!(parse_path("a.b[x]").is_err());
+        assert!(parse_path("a.b[0").is_err());
+        assert!(parse_path("a..b").is_err());
+    }
+}
@@ -0,0 +1,251 @@
+use alvr_common::{prelude::*, Histogram};
+use alvr_events::{FrameStatisticsRecord, StatisticsRecord};
+use std::{
+    fmt::Write as _,
+    fs::File,
+    io::{BufReader, ErrorKind, Write},
+    path::Path,
+    time::Duration,
+};
+
+// A frame is a stutter if the client waited this many times the nominal frame interval for it
+const STUTTER_INTERVAL_MULTIPLIER: f32 = 1.5;
+
+const CSV_HEADER: &str = "time_s,target_timestamp_ns,game_time_s,server_compositor_s,encoder_s,\
+network_s,decoder_s,decoder_queue_s,client_compositor_s,vsync_queue_s,total_pipeline_latency_s,\
+client_frame_interval_s,server_frame_interval_s,target_bitrate_bps,packets_lost";
+
+pub fn read_recording(path: &Path) -> StrResult<Vec<StatisticsRecord>> {
+    let mut reader = BufReader::new(File::open(path).map_err(err!())?);
+
+    let mut records = vec![];
+    loop {
+        match bincode::deserialize_from(&mut reader) {
+            Ok(record) => records.push(record),
+            Err(e) => match *e {
+                // A recording interrupted by a crash can end with a partial record
+                bincode::ErrorKind::Io(e) if e.kind() == ErrorKind::UnexpectedEof => break,
+                e => return fmt_e!("Invalid recording {}: {e}", path.display()),
+            },
+        }
+    }
+
+    Ok(records)
+}
+
+// One row per frame. The target bitrate is the last one set before the frame, packets_lost counts
+// the losses since the previous frame
+pub fn write_csv(records: &[StatisticsRecord], mut out: impl Write) -> StrResult {
+    writeln!(out, "{CSV_HEADER}").map_err(err!())?;
+
+    let mut target_bitrate_bps = 0;
+    let mut packets_lost = 0;
+    for record in records {
+        match record {
+            StatisticsRecord::Start { .. } => (),
+            StatisticsRecord::Frame { time_s, frame: f } => {
+                writeln!(
+                    out,
+                    "{time_s},{},{},{},{},{},{},{},{},{},{},{},{},{target_bitrate_bps},{packets_lost}",
+                    f.target_timestamp_ns,
+                    f.game_time_s,
+                    f.server_compositor_s,
+                    f.encoder_s,
+                    f.network_s,
+                    f.decoder_s,
+                    f.decoder_queue_s,
+                    f.client_compositor_s,
+                    f.vsync_queue_s,
+                    f.total_pipeline_latency_s,
+                    f.client_frame_interval_s,
+                    f.server_frame_interval_s,
+                )
+                .map_err(err!())?;
+
+                packets_lost = 0;
+            }
+            StatisticsRecord::TargetBitrate { bitrate_bps, .. } => {
+                target_bitrate_bps = *bitrate_bps
+            }
+            StatisticsRecord::PacketLoss { .. } => packets_lost += 1,
+        }
+    }
+
+    Ok(())
+}
+
+pub fn summary(records: &[StatisticsRecord]) -> String {
+    let mut recording_info = None;
+    let mut nominal_frame_interval_s = None;
+    let mut frames = vec![];
+    let mut duration_s = 0.0;
+    let mut bitrate_changes = 0;
+    let mut packets_lost = 0;
+
+    for record in records {
+        match record {
+            StatisticsRecord::Start {
+                alvr_version,
+                client_hostname,
+                nominal_frame_interval_s: interval_s,
+            } => {
+                recording_info = Some(format!("Client {client_hostname}, ALVR v{alvr_version}"));
+                nominal_frame_interval_s = Some(*interval_s);
+            }
+            StatisticsRecord::Frame { time_s, frame } => {
+                duration_s = *time_s;
+                frames.push(frame);
+            }
+            StatisticsRecord::TargetBitrate { .. } => bitrate_changes += 1,
+            StatisticsRecord::PacketLoss { .. } => packets_lost += 1,
+        }
+    }
+
+    let stages: [(&str, fn(&FrameStatisticsRecord) -> f32); 10] = [
+        ("Total", |f| f.total_pipeline_latency_s),
+        ("Game", |f| f.game_time_s),
+        ("Server compositor", |f| f.server_compositor_s),
+        ("Encoder", |f| f.encoder_s),
+        ("Network", |f| f.network_s),
+        ("Decoder", |f| f.decoder_s),
+        ("Decoder queue", |f| f.decoder_queue_s),
+        ("Client compositor", |f| f.client_compositor_s),
+        ("VSync queue", |f| f.vsync_queue_s),
+        ("Client frame interval", |f| f.client_frame_interval_s),
+    ];
+
+    // Writing to a String never fails
+    let mut out = String::new();
+    if let Some(info) = recording_info {
+        writeln!(out, "{info}").ok();
+    }
+    writeln!(
+        out,
+        "{} frames in {duration_s:.1}s, {packets_lost} packets lost, {bitrate_changes} bitrate changes",
+        frames.len()
+    )
+    .ok();
+
+    if let Some(interval_s) = nominal_frame_interval_s {
+        let stutters = frames
+            .iter()
+            .filter(|f| f.client_frame_interval_s > interval_s * STUTTER_INTERVAL_MULTIPLIER)
+            .count();
+        writeln!(
+            out,
+            "{stutters} stutters (frame interval over {:.1}ms)",
+            interval_s * STUTTER_INTERVAL_MULTIPLIER * 1000.0
+        )
+        .ok();
+    }
+
+    writeln!(
+        out,
+        "\n{:<24}{:>10}{:>10}{:>10}{:>10}",
+        "Stage (ms)", "p50", "p95", "p99", "max"
+    )
+    .ok();
+    for (name, value_fn) in stages {
+        let mut histogram = Histogram::default();
+        let mut max = Duration::ZERO;
+        for frame in &frames {
+            let value = Duration::from_secs_f32(value_fn(frame).max(0.0));
+            histogram.submit_sample(value);
+            max = max.max(value);
+        }
+
+        let ms = |duration: Duration| duration.as_secs_f32() * 1000.0;
+        writeln!(
+            out,
+            "{name:<24}{:>10.2}{:>10.2}{:>10.2}{:>10.2}",
+            ms(histogram.get_percentile(50.0)),
+            ms(histogram.get_percentile(95.0)),
+            ms(histogram.get_percentile(99.0)),
+            ms(max)
+        )
+        .ok();
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(total_pipeline_latency_s: f32, client_frame_interval_s: f32) -> StatisticsRecord {
+        StatisticsRecord::Frame {
+            time_s: 1.0,
+            frame: FrameStatisticsRecord {
+                target_timestamp_ns: 0,
+                game_time_s: 0.0,
+                server_compositor_s: 0.0,
+                encoder_s: 0.0,
+                network_s: 0.0,
+                decoder_s: 0.0,
+                decoder_queue_s: 0.0,
+                client_compositor_s: 0.0,
+                vsync_queue_s: 0.0,
+                total_pipeline_latency_s,
+                client_frame_interval_s,
+                server_frame_interval_s: 0.0,
+            },
+        }
+    }
+
+    #[test]
+    fn csv_rows() {
+        let records = [
+            StatisticsRecord::TargetBitrate {
+                time_s: 0.0,
+                bitrate_bps: 30_000_000,
+            },
+            StatisticsRecord::PacketLoss { time_s: 0.5 },
+            StatisticsRecord::PacketLoss { time_s: 0.6 },
+            frame(0.05, 0.011),
+            frame(0.05, 0.011),
+        ];
+
+        let mut out = vec![];
+        write_csv(&records, &mut out).unwrap();
+        let csv = String::from_utf8(out).unwrap();
+        let rows = csv.lines().collect::<Vec<_>>();
+
+        assert_eq!(rows.len(), 3);
+        assert_eq!(rows[0], CSV_HEADER);
+        assert!(rows[1].ends_with(",30000000,2"));
+        assert!(rows[2].ends_with(",30000000,0"));
+    }
+
+    #[test]
+    fn summary_stutters_and_percentiles() {
+        let mut records = vec![StatisticsRecord::Start {
+            alvr_version: "1.0.0".into(),
+            client_hostname: "client".into(),
+            nominal_frame_interval_s: 0.01,
+        }];
+        for _ in 0..98 {
+            records.push(frame(0.05, 0.01));
+        }
+        records.push(frame(0.1, 0.02));
+        records.push(frame(0.1, 0.02));
+
+        let summary = summary(&records);
+
+        assert!(summary.contains("100 frames"));
+        assert!(summary.contains("2 stutters"));
+        let total_row = summary
+            .lines()
+            .find(|line| line.starts_with("Total"))
+            .unwrap()
+            .split_whitespace()
+            .skip(1)
+            .map(|value| value.parse::<f32>().unwrap())
+            .collect::<Vec<_>>();
+        // p50, p95, p99 and max, within the histogram resolution
+        assert!((total_row[0] - 50.0).abs() < 1.5);
+        assert!((total_row[1] - 50.0).abs() < 1.5);
+        assert!((total_row[2] - 100.0).abs() < 3.0);
+        assert_eq!(total_row[3], 100.0);
+    }
+}
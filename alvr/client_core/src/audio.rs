@@ -1,6 +1,9 @@
-use alvr_audio::AudioDevice;
+use alvr_audio::{
+    AudioDecoder, AudioDevice, AudioEncoder, AudioFormatConverter, MicrophoneProcessor,
+};
 use alvr_common::{parking_lot::Mutex, prelude::*};
-use alvr_session::AudioBufferingConfig;
+use alvr_packets::{AudioPacketHeader, AudioStreamFormat};
+use alvr_session::{AudioBufferingConfig, AudioCodecConfig, MicrophoneProcessingConfig};
 use alvr_sockets::{StreamReceiver, StreamSender};
 use oboe::{
     AudioInputCallback, AudioInputStreamSafe, AudioOutputCallback, AudioOutputStreamSafe,
@@ -9,14 +12,15 @@ use oboe::{
 };
 use std::{
     collections::VecDeque,
-    mem,
     sync::{mpsc as smpsc, Arc},
     thread,
+    time::{Duration, Instant},
 };
 use tokio::sync::mpsc as tmpsc;
 
 struct RecorderCallback {
-    sender: tmpsc::UnboundedSender<Vec<u8>>,
+    sender: tmpsc::UnboundedSender<StrResult<(Instant, Vec<f32>)>>,
+    sample_rate: u32,
 }
 
 impl AudioInputCallback for RecorderCallback {
@@ -27,13 +31,16 @@ impl AudioInputCallback for RecorderCallback {
         _: &mut dyn AudioInputStreamSafe,
         frames: &[i16],
     ) -> DataCallbackResult {
-        let mut sample_buffer = Vec::with_capacity(frames.len() * mem::size_of::<i16>());
-
-        for frame in frames {
-            sample_buffer.extend(&frame.to_ne_bytes());
-        }
+        // The callback is called once the buffer is full
+        let capture_instant =
+            Instant::now() - Duration::from_secs_f32(frames.len() as f32 / self.sample_rate as f32);
 
-        self.sender.send(sample_buffer).ok();
+        self.sender
+            .send(Ok((
+                capture_instant,
+                frames.iter().map(|frame| *frame as f32 / 32768.0).collect(),
+            )))
+            .ok();
 
         DataCallbackResult::Continue
     }
@@ -42,14 +49,26 @@ impl AudioInputCallback for RecorderCallback {
 #[allow(unused_variables)]
 pub async fn record_audio_loop(
     device: AudioDevice,
-    channels_count: u16,
+    format: AudioStreamFormat,
     mute: bool,
-    mut sender: StreamSender<()>,
+    processing: Option<MicrophoneProcessingConfig>,
+    codec: AudioCodecConfig,
+    sender: StreamSender<AudioPacketHeader>,
 ) -> StrResult {
     let sample_rate = device.input_sample_rate()?;
+    let input_format = AudioStreamFormat {
+        sample_rate,
+        channels_count: 1,
+    };
+    let converter = AudioFormatConverter::new(input_format, format, false)?;
+    let processor = match processing {
+        Some(config) => MicrophoneProcessor::new(&config, input_format, format)?,
+        None => None,
+    };
+    let encoder = AudioEncoder::new(&codec, format)?;
 
     let (_shutdown_notifier, shutdown_receiver) = smpsc::channel::<()>();
-    let (data_sender, mut data_receiver) = tmpsc::unbounded_channel();
+    let (data_sender, data_receiver) = tmpsc::unbounded_channel();
 
     thread::spawn(move || -> StrResult {
         let mut stream = AudioStreamBuilder::default()
@@ -64,6 +83,7 @@ pub async fn record_audio_loop(
             .set_input_preset(InputPreset::VoiceCommunication)
             .set_callback(RecorderCallback {
                 sender: data_sender,
+                sample_rate,
             })
             .open_stream()
             .map_err(err!())?;
@@ -78,15 +98,12 @@ pub async fn record_audio_loop(
         Ok(())
     });
 
-    while let Some(data) = data_receiver.recv().await {
-        sender.send(&(), data).await.ok();
-    }
-
-    Ok(())
+    alvr_audio::send_samples_loop(data_receiver, converter, processor, encoder, sender).await
 }
 
 struct PlayerCallback {
     sample_buffer: Arc<Mutex<VecDeque<f32>>>,
+    sample_rate: u32,
     batch_frames_count: usize,
 }
 
@@ -106,6 +123,15 @@ impl AudioOutputCallback for PlayerCallback {
             self.batch_frames_count,
         );
 
+        // Used as reference by the microphone echo cancellation
+        alvr_audio::report_played_samples(
+            &samples,
+            AudioStreamFormat {
+                sample_rate: self.sample_rate,
+                channels_count: 2,
+            },
+        );
+
         for f in 0..out_frames.len() {
             out_frames[f] = (samples[f * 2], samples[f * 2 + 1]);
         }
@@ -117,17 +143,30 @@ impl AudioOutputCallback for PlayerCallback {
 #[allow(unused_variables)]
 pub async fn play_audio_loop(
     device: AudioDevice,
-    channels_count: u16,
-    sample_rate: u32,
+    format: AudioStreamFormat,
     config: AudioBufferingConfig,
-    receiver: StreamReceiver<()>,
+    codec: AudioCodecConfig,
+    sync_with_video: bool,
+    receiver: StreamReceiver<AudioPacketHeader>,
 ) -> StrResult {
     // the client sends invalid sample rates sometimes, and we crash if we try and use one
     // (batch_frames_count ends up zero and the audio callback gets confused)
-    if sample_rate < 8000 {
+    if format.sample_rate < 8000 {
         return fmt_e!("Invalid audio sample rate");
     }
 
+    // Oboe converts the sample rate if needed, only the channels are converted here
+    let sample_rate = format.sample_rate;
+    let decoder = AudioDecoder::new(&codec, format)?;
+    let converter = AudioFormatConverter::new(
+        format,
+        AudioStreamFormat {
+            sample_rate,
+            channels_count: 2,
+        },
+        false,
+    )?;
+
     let batch_frames_count = sample_rate as usize * config.batch_ms as usize / 1000;
     let average_buffer_frames_count =
         sample_rate as usize * config.average_buffering_ms as usize / 1000;
@@ -143,7 +182,7 @@ pub async fn play_audio_loop(
                 .set_shared()
                 .set_performance_mode(PerformanceMode::LowLatency)
                 .set_sample_rate(sample_rate as _)
-                .set_sample_rate_conversion_quality(SampleRateConversionQuality::Fastest)
+                .set_sample_rate_conversion_quality(SampleRateConversionQuality::Medium)
                 .set_stereo()
                 .set_f32()
                 .set_frames_per_callback(batch_frames_count as _)
@@ -151,6 +190,7 @@ pub async fn play_audio_loop(
                 .set_usage(Usage::Game)
                 .set_callback(PlayerCallback {
                     sample_buffer,
+                    sample_rate,
                     batch_frames_count,
                 })
                 .open_stream()
@@ -171,8 +211,12 @@ pub async fn play_audio_loop(
         receiver,
         sample_buffer,
         2,
+        sample_rate,
         batch_frames_count,
         average_buffer_frames_count,
+        decoder,
+        converter,
+        sync_with_video,
     )
     .await
 }
@@ -6,17 +6,18 @@ use crate::{
     sockets::AnnouncerSocket,
     statistics::StatisticsManager,
     storage::Config,
-    ClientCoreEvent, CONTROL_CHANNEL_SENDER, DISCONNECT_NOTIFIER, EVENT_QUEUE, IS_ALIVE,
-    IS_RESUMED, IS_STREAMING, STATISTICS_MANAGER, STATISTICS_SENDER, TRACKING_SENDER,
+    ClientCoreEvent, CONTROL_CHANNEL_SENDER, CPU_PERFORMANCE_LEVEL, DISCONNECT_NOTIFIER,
+    EVENT_QUEUE, GPU_PERFORMANCE_LEVEL, IS_ALIVE, IS_RESUMED, IS_STREAMING, STATISTICS_MANAGER,
+    STATISTICS_SENDER, TRACKING_SENDER,
 };
 use alvr_audio::AudioDevice;
-use alvr_common::{glam::UVec2, prelude::*, ALVR_VERSION, HEAD_ID};
+use alvr_common::{glam::UVec2, prelude::*, RelaxedAtomic, ALVR_VERSION, HEAD_ID};
 use alvr_packets::{
     BatteryPacket, ClientConnectionResult, ClientControlPacket, Haptics, ServerControlPacket,
     StreamConfigPacket, VideoPacketHeader, VideoStreamingCapabilities, AUDIO, HAPTICS, STATISTICS,
     TRACKING, VIDEO,
 };
-use alvr_session::{settings_schema::Switch, SessionDesc};
+use alvr_session::{settings_schema::Switch, SessionDesc, Settings};
 use alvr_sockets::{
     spawn_cancelable, PeerType, ProtoControlSocket, ReceiverBuffer, StreamSocketBuilder,
 };
@@ -61,6 +62,7 @@ const RETRY_CONNECT_MIN_INTERVAL: Duration = Duration::from_secs(1);
 const NETWORK_KEEPALIVE_INTERVAL: Duration = Duration::from_secs(1);
 const CONNECTION_RETRY_INTERVAL: Duration = Duration::from_secs(1);
 const BATTERY_POLL_INTERVAL: Duration = Duration::from_secs(60);
+const CLIENT_HEALTH_POLL_INTERVAL: Duration = Duration::from_secs(5);
 
 fn set_hud_message(message: &str) {
     let message = format!(
@@ -157,6 +159,15 @@ fn connection_pipeline(
         .input_sample_rate()
         .unwrap();
 
+    // The Android playback stream is always stereo
+    #[cfg(target_os = "android")]
+    let game_audio_channels_count = 2;
+    #[cfg(not(target_os = "android"))]
+    let game_audio_channels_count = AudioDevice::new_output(None, None)
+        .and_then(|device| device.output_format())
+        .map(|format| format.channels_count)
+        .unwrap_or(2);
+
     runtime
         .block_on(
             proto_control_socket.send(&ClientConnectionResult::ConnectionAccepted {
@@ -166,6 +177,7 @@ fn connection_pipeline(
                     default_view_resolution: recommended_view_resolution,
                     supported_refresh_rates,
                     microphone_sample_rate,
+                    game_audio_channels_count,
                 }),
             }),
         )
@@ -184,18 +196,20 @@ fn connection_pipeline(
         .map_err(to_int_e!())
 }
 
+fn settings_from_session_json(session_desc: &str) -> StrResult<Settings> {
+    let mut session = SessionDesc::default();
+    session.merge_from_json(&json::from_str(session_desc).map_err(err!())?)?;
+
+    Ok(session.to_settings())
+}
+
 async fn stream_pipeline(
     proto_socket: ProtoControlSocket,
     stream_config: StreamConfigPacket,
     server_ip: IpAddr,
     decoder_guard: Arc<Mutex<()>>,
 ) -> StrResult {
-    let settings = {
-        let mut session_desc = SessionDesc::default();
-        session_desc
-            .merge_from_json(&json::from_str(&stream_config.session_desc).map_err(err!())?)?;
-        session_desc.to_settings()
-    };
+    let settings = settings_from_session_json(&stream_config.session_desc)?;
 
     let streaming_start_event = ClientCoreEvent::StreamingStarted {
         view_resolution: stream_config.view_resolution,
@@ -236,6 +250,7 @@ async fn stream_pipeline(
         } else {
             0.0
         },
+        matches!(settings.headset.pose_prediction, Switch::Enabled(_)),
     ));
 
     let stream_socket_builder = StreamSocketBuilder::listen_for_server(
@@ -322,7 +337,13 @@ async fn stream_pipeline(
 
     IS_STREAMING.set(true);
 
+    // Updated when the streamer sends new settings
+    let avoid_video_glitching = Arc::new(RelaxedAtomic::new(
+        settings.connection.avoid_video_glitching,
+    ));
+
     let video_receive_loop = {
+        let avoid_video_glitching = Arc::clone(&avoid_video_glitching);
         let mut receiver = stream_socket
             .subscribe_to_stream::<VideoPacketHeader>(VIDEO)
             .await?;
@@ -376,7 +397,7 @@ async fn stream_pipeline(
                     warn!("Network dropped video packet");
                 }
 
-                if !stream_corrupted || !settings.connection.avoid_video_glitching {
+                if !stream_corrupted || !avoid_video_glitching.value() {
                     if !decoder::push_nal(header.timestamp, nal) {
                         stream_corrupted = true;
                         if let Some(sender) = &*CONTROL_CHANNEL_SENDER.lock() {
@@ -415,23 +436,27 @@ async fn stream_pipeline(
         let game_audio_receiver = stream_socket.subscribe_to_stream(AUDIO).await?;
         Box::pin(audio::play_audio_loop(
             device,
-            2,
-            stream_config.game_audio_sample_rate,
+            stream_config.game_audio_format,
             config.buffering,
+            stream_config.game_audio_codec,
+            true,
             game_audio_receiver,
         ))
     } else {
         Box::pin(future::pending())
     };
 
-    let microphone_loop: BoxFuture<_> = if matches!(settings.audio.microphone, Switch::Enabled(_)) {
+    let microphone_loop: BoxFuture<_> = if let Switch::Enabled(config) = &settings.audio.microphone
+    {
         let device = AudioDevice::new_input(None).map_err(err!())?;
 
         let microphone_sender = stream_socket.request_stream(AUDIO).await?;
         Box::pin(audio::record_audio_loop(
             device,
-            1,
+            stream_config.microphone_format,
             false,
+            Some(config.processing.clone()),
+            stream_config.microphone_codec,
             microphone_sender,
         ))
     } else {
@@ -447,6 +472,7 @@ async fn stream_pipeline(
 
         let mut previous_hmd_battery_status = (0.0, false);
         let mut battery_poll_deadline = Instant::now();
+        let mut client_health_poll_deadline = Instant::now();
 
         while IS_STREAMING.value() {
             if battery_poll_deadline < Instant::now() {
@@ -469,6 +495,18 @@ async fn stream_pipeline(
                 battery_poll_deadline += BATTERY_POLL_INTERVAL;
             }
 
+            if client_health_poll_deadline < Instant::now() {
+                let mut health = platform::client_health();
+                health.cpu_level = *CPU_PERFORMANCE_LEVEL.lock();
+                health.gpu_level = *GPU_PERFORMANCE_LEVEL.lock();
+
+                if let Some(sender) = &*CONTROL_CHANNEL_SENDER.lock() {
+                    sender.send(ClientControlPacket::Health(health)).ok();
+                }
+
+                client_health_poll_deadline += CLIENT_HEALTH_POLL_INTERVAL;
+            }
+
             thread::sleep(Duration::from_secs(1));
         }
     });
@@ -507,6 +545,22 @@ async fn stream_pipeline(
                 Ok(ServerControlPacket::InitializeDecoder(config)) => {
                     decoder::create_decoder(config);
                 }
+                Ok(ServerControlPacket::UpdateSettings(session_desc)) => {
+                    // Only a subset of the settings can be applied without reconnecting. The
+                    // decoder settings take effect the next time the decoder is created.
+                    match settings_from_session_json(&session_desc) {
+                        Ok(settings) => {
+                            avoid_video_glitching.set(settings.connection.avoid_video_glitching);
+
+                            let config = &mut *DECODER_INIT_CONFIG.lock();
+                            config.max_buffering_frames = settings.video.max_buffering_frames;
+                            config.buffering_history_weight =
+                                settings.video.buffering_history_weight;
+                            config.options = settings.video.mediacodec_extra_options;
+                        }
+                        Err(e) => warn!("Failed to apply new settings: {e}"),
+                    }
+                }
                 Ok(ServerControlPacket::Restarting) => {
                     info!("{SERVER_RESTART_MESSAGE}");
                     set_hud_message(SERVER_RESTART_MESSAGE);
@@ -32,7 +32,8 @@ use alvr_common::{
     Fov, RelaxedAtomic,
 };
 use alvr_packets::{
-    BatteryPacket, ButtonEntry, ClientControlPacket, ClientStatistics, Tracking, ViewsConfig,
+    BatteryPacket, ButtonEntry, ClientControlPacket, ClientStatistics, PerformanceLevel, Tracking,
+    ViewsConfig,
 };
 use alvr_session::{CodecType, Settings};
 use decoder::EXTERNAL_DECODER;
@@ -41,7 +42,7 @@ use statistics::StatisticsManager;
 use std::{
     collections::VecDeque,
     thread::{self, JoinHandle},
-    time::Duration,
+    time::{Duration, Instant},
 };
 use storage::Config;
 use tokio::{sync::mpsc, sync::Notify};
@@ -65,6 +66,12 @@ static IS_STREAMING: RelaxedAtomic = RelaxedAtomic::new(false);
 
 static CONNECTION_THREAD: Lazy<Mutex<Option<JoinHandle<()>>>> = Lazy::new(|| Mutex::new(None));
 
+// Reported by the runtime, not available through the platform APIs
+static CPU_PERFORMANCE_LEVEL: Lazy<Mutex<Option<PerformanceLevel>>> =
+    Lazy::new(|| Mutex::new(None));
+static GPU_PERFORMANCE_LEVEL: Lazy<Mutex<Option<PerformanceLevel>>> =
+    Lazy::new(|| Mutex::new(None));
+
 #[derive(Serialize, Deserialize)]
 pub enum ClientCoreEvent {
     UpdateHudMessage(String),
@@ -163,6 +170,12 @@ pub fn send_battery(device_id: u64, gauge_value: f32, is_plugged: bool) {
     }
 }
 
+// The levels are sent to the streamer together with the rest of the client health
+pub fn set_performance_levels(cpu_level: PerformanceLevel, gpu_level: PerformanceLevel) {
+    *CPU_PERFORMANCE_LEVEL.lock() = Some(cpu_level);
+    *GPU_PERFORMANCE_LEVEL.lock() = Some(gpu_level);
+}
+
 pub fn send_playspace(area: Option<Vec2>) {
     if let Some(sender) = &*CONTROL_CHANNEL_SENDER.lock() {
         sender.send(ClientControlPacket::PlayspaceSync(area)).ok();
@@ -198,6 +211,8 @@ pub fn get_tracker_prediction_offset() -> Duration {
 }
 
 pub fn report_submit(target_timestamp: Duration, vsync_queue: Duration) {
+    alvr_audio::report_video_frame_presented(target_timestamp, Instant::now() + vsync_queue);
+
     if let Some(stats) = &mut *STATISTICS_MANAGER.lock() {
         stats.report_submit(target_timestamp, vsync_queue);
 
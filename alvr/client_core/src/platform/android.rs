@@ -5,6 +5,7 @@ use alvr_common::{
     prelude::*,
     RelaxedAtomic,
 };
+use alvr_packets::{ClientHealth, WifiStatus};
 use alvr_session::{CodecType, MediacodecDataType};
 use jni::{
     objects::{GlobalRef, JObject},
@@ -245,6 +246,120 @@ pub fn battery_status() -> (f32, bool) {
     (percentage as f32 / 100.0, is_charging)
 }
 
+// CPU and GPU levels are not exposed by Android, they are filled in by the OpenXR runtime
+pub fn client_health() -> ClientHealth {
+    let vm = vm();
+    let mut env = vm.attach_current_thread().unwrap();
+
+    // ACTION_BATTERY_CHANGED is sticky, so the last battery status is returned without a receiver
+    let action_jstring = env
+        .new_string("android.intent.action.BATTERY_CHANGED")
+        .unwrap();
+    let intent_filter = env
+        .new_object(
+            "android/content/IntentFilter",
+            "(Ljava/lang/String;)V",
+            &[(&action_jstring).into()],
+        )
+        .unwrap();
+    let battery_intent = env
+        .call_method(
+            unsafe { JObject::from_raw(context()) },
+            "registerReceiver",
+            "(Landroid/content/BroadcastReceiver;Landroid/content/IntentFilter;)Landroid/content/Intent;",
+            &[(&JObject::null()).into(), (&intent_filter).into()],
+        )
+        .unwrap()
+        .l()
+        .unwrap();
+    let battery_temperature_c = if !battery_intent.is_null() {
+        let temperature_jstring = env.new_string("temperature").unwrap();
+        // In tenths of degree Celsius
+        let temperature = env
+            .call_method(
+                &battery_intent,
+                "getIntExtra",
+                "(Ljava/lang/String;I)I",
+                &[(&temperature_jstring).into(), i32::MIN.into()],
+            )
+            .unwrap()
+            .i()
+            .unwrap();
+
+        (temperature != i32::MIN).then_some(temperature as f32 / 10.0)
+    } else {
+        None
+    };
+
+    let thermal_throttling = if get_api_level() >= 29 {
+        const THERMAL_STATUS_MODERATE: i32 = 2;
+
+        let power_manager = get_system_service(&mut env, "power");
+        let thermal_status = env
+            .call_method(power_manager, "getCurrentThermalStatus", "()I", &[])
+            .unwrap()
+            .i()
+            .unwrap();
+
+        Some(thermal_status >= THERMAL_STATUS_MODERATE)
+    } else {
+        None
+    };
+
+    let wifi_manager = get_system_service(&mut env, "wifi");
+    let wifi_info = env
+        .call_method(
+            wifi_manager,
+            "getConnectionInfo",
+            "()Landroid/net/wifi/WifiInfo;",
+            &[],
+        )
+        .unwrap()
+        .l()
+        .unwrap();
+    let mut get_wifi_int = |method: &str| {
+        env.call_method(&wifi_info, method, "()I", &[])
+            .unwrap()
+            .i()
+            .unwrap()
+    };
+    let rssi_dbm = get_wifi_int("getRssi");
+    let link_speed_mbps = get_wifi_int("getLinkSpeed");
+    let frequency_mhz = get_wifi_int("getFrequency");
+    // The frequency is -1 when Wi-Fi is not connected, for example with a wired connection
+    let wifi = (frequency_mhz > 0).then_some(WifiStatus {
+        rssi_dbm,
+        link_speed_mbps: link_speed_mbps.max(0) as u32,
+        frequency_mhz: frequency_mhz as u32,
+    });
+
+    let activity_manager = get_system_service(&mut env, "activity");
+    let memory_info = env
+        .new_object("android/app/ActivityManager$MemoryInfo", "()V", &[])
+        .unwrap();
+    env.call_method(
+        activity_manager,
+        "getMemoryInfo",
+        "(Landroid/app/ActivityManager$MemoryInfo;)V",
+        &[(&memory_info).into()],
+    )
+    .unwrap();
+    let free_memory_bytes = env
+        .get_field(&memory_info, "availMem", "J")
+        .unwrap()
+        .j()
+        .unwrap();
+
+    ClientHealth {
+        battery_temperature_c,
+        cpu_level: None,
+        gpu_level: None,
+        thermal_throttling,
+        wifi,
+        free_memory_bytes: Some(free_memory_bytes as u64),
+    }
+}
+
 pub struct VideoDecoderEnqueuer {
     inner: Arc<Mutex<Option<SharedMediaCodec>>>,
 }
@@ -3,9 +3,9 @@ pub mod android;
 
 #[cfg(target_os = "android")]
 pub use android::{
-    acquire_wifi_lock, battery_status, context, device_model, local_ip, manufacturer_name,
-    release_wifi_lock, try_get_permission, video_decoder_split, vm, VideoDecoderDequeuer,
-    VideoDecoderEnqueuer, MICROPHONE_PERMISSION,
+    acquire_wifi_lock, battery_status, client_health, context, device_model, local_ip,
+    manufacturer_name, release_wifi_lock, try_get_permission, video_decoder_split, vm,
+    VideoDecoderDequeuer, VideoDecoderEnqueuer, MICROPHONE_PERMISSION,
 };
 
 #[cfg(not(target_os = "android"))]
@@ -35,3 +35,8 @@ pub fn local_ip() -> std::net::IpAddr {
 pub fn battery_status() -> (f32, bool) {
     (1.0, true)
 }
+
+#[cfg(not(target_os = "android"))]
+pub fn client_health() -> alvr_packets::ClientHealth {
+    alvr_packets::ClientHealth::default()
+}
@@ -12,11 +12,13 @@ struct HistoryFrame {
 }
 
 pub struct StatisticsManager {
+    start_instant: Instant,
     history_buffer: VecDeque<HistoryFrame>,
     max_history_size: usize,
     prev_vsync: Instant,
     total_pipeline_latency_average: SlidingWindowAverage<Duration>,
     steamvr_pipeline_latency: Duration,
+    server_pose_prediction: bool,
 }
 
 impl StatisticsManager {
@@ -24,8 +26,10 @@ impl StatisticsManager {
         max_history_size: usize,
         nominal_server_frame_interval: Duration,
         steamvr_pipeline_frames: f32,
+        server_pose_prediction: bool,
     ) -> Self {
         Self {
+            start_instant: Instant::now(),
             max_history_size,
             history_buffer: VecDeque::new(),
             prev_vsync: Instant::now(),
@@ -36,6 +40,7 @@ impl StatisticsManager {
             steamvr_pipeline_latency: Duration::from_secs_f32(
                 steamvr_pipeline_frames * nominal_server_frame_interval.as_secs_f32(),
             ),
+            server_pose_prediction,
         }
     }
 
@@ -125,7 +130,16 @@ impl StatisticsManager {
         self.history_buffer
             .iter()
             .find(|frame| frame.client_stats.target_timestamp == target_timestamp)
-            .map(|frame| frame.client_stats.clone())
+            .map(|frame| ClientStatistics {
+                game_audio: alvr_audio::playback_statistics(),
+                input_acquired: frame
+                    .input_acquired
+                    .saturating_duration_since(self.start_instant),
+                video_packet_received: frame
+                    .video_packet_received
+                    .saturating_duration_since(self.start_instant),
+                ..frame.client_stats.clone()
+            })
     }
 
     // latency used for head prediction
@@ -135,6 +149,11 @@ impl StatisticsManager {
 
     // latency used for controllers/trackers prediction
     pub fn tracker_prediction_offset(&self) -> Duration {
+        // The server extrapolates the poses itself, they must be sent unpredicted
+        if self.server_pose_prediction {
+            return Duration::ZERO;
+        }
+
         self.total_pipeline_latency_average
             .get_average()
             .saturating_sub(self.steamvr_pipeline_latency)
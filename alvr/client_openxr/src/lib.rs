@@ -8,7 +8,7 @@ use alvr_common::{
     settings_schema::Switch,
     DeviceMotion, Fov, Pose, RelaxedAtomic, HEAD_ID, LEFT_HAND_ID, RIGHT_HAND_ID,
 };
-use alvr_packets::{FaceData, Tracking};
+use alvr_packets::{FaceData, PerformanceLevel, Tracking};
 use interaction::{FaceInputContext, HandsInteractionContext};
 use khronos_egl::{self as egl, EGL1_4};
 use openxr as xr;
@@ -367,6 +367,7 @@ pub fn entry_point() {
 
     let mut exts = xr::ExtensionSet::default();
     exts.ext_hand_tracking = available_extensions.ext_hand_tracking;
+    exts.ext_performance_settings = available_extensions.ext_performance_settings;
     exts.fb_color_space = available_extensions.fb_color_space;
     exts.fb_display_refresh_rate = available_extensions.fb_display_refresh_rate;
     exts.fb_eye_tracking_social = available_extensions.fb_eye_tracking_social;
@@ -472,6 +473,9 @@ pub fn entry_point() {
 
         let mut last_good_views = vec![default_view, default_view];
 
+        // Indexed by CPU/GPU domain and compositing/rendering/thermal sub-domain
+        let mut performance_levels = [[PerformanceLevel::Normal; 3]; 2];
+
         let mut event_storage = xr::EventDataBuffer::new();
         'render_loop: loop {
             while let Some(event) = xr_instance.poll_event(&mut event_storage).unwrap() {
@@ -569,6 +573,35 @@ pub fn entry_point() {
                             event.domain(),
                             event.sub_domain(),
                         );
+
+                        let domain_index = match event.domain() {
+                            xr::PerfSettingsDomainEXT::CPU => 0,
+                            xr::PerfSettingsDomainEXT::GPU => 1,
+                            _ => continue,
+                        };
+                        let sub_domain_index = match event.sub_domain() {
+                            xr::PerfSettingsSubDomainEXT::COMPOSITING => 0,
+                            xr::PerfSettingsSubDomainEXT::RENDERING => 1,
+                            xr::PerfSettingsSubDomainEXT::THERMAL => 2,
+                            _ => continue,
+                        };
+                        performance_levels[domain_index][sub_domain_index] = match event.to_level()
+                        {
+                            xr::PerfSettingsNotificationLevelEXT::NORMAL => {
+                                PerformanceLevel::Normal
+                            }
+                            xr::PerfSettingsNotificationLevelEXT::WARNING => {
+                                PerformanceLevel::Warning
+                            }
+                            _ => PerformanceLevel::Impaired,
+                        };
+
+                        // Report the worst level of each domain
+                        let [cpu_levels, gpu_levels] = performance_levels;
+                        alvr_client_core::set_performance_levels(
+                            cpu_levels.into_iter().max().unwrap(),
+                            gpu_levels.into_iter().max().unwrap(),
+                        );
                     }
                     xr::Event::InteractionProfileChanged(_) => {
                         // todo
@@ -0,0 +1,203 @@
+use std::time::{Duration, Instant};
+
+// Log-linear buckets, like HDR histograms: every power of two of microseconds is split in
+// SUB_BUCKETS_HALF buckets, so samples are stored with a relative error of about 3%
+const SUB_BUCKETS_LOG2: u32 = 5;
+const SUB_BUCKETS: u64 = 1 << SUB_BUCKETS_LOG2;
+const SUB_BUCKETS_HALF: u64 = SUB_BUCKETS / 2;
+const MAX_VALUE_US: u64 = (1 << 26) - 1; // about 67s, larger samples are clamped
+const BUCKET_COUNT: usize = bucket_index(MAX_VALUE_US) + 1;
+
+const fn bucket_index(value_us: u64) -> usize {
+    if value_us < SUB_BUCKETS {
+        return value_us as usize;
+    }
+
+    let shift = 64 - value_us.leading_zeros() - SUB_BUCKETS_LOG2;
+
+    (shift as u64 * SUB_BUCKETS_HALF + (value_us >> shift)) as usize
+}
+
+// Midpoint of the range of values stored in the bucket
+fn bucket_value_us(index: usize) -> u64 {
+    let index = index as u64;
+    if index < SUB_BUCKETS {
+        return index;
+    }
+
+    let shift = index / SUB_BUCKETS_HALF - 1;
+    let lower_bound = (index - shift * SUB_BUCKETS_HALF) << shift;
+
+    lower_bound + ((1 << shift) - 1) / 2
+}
+
+// Nearest rank percentile over the bucket counts
+fn percentile(counts: impl Iterator<Item = u64>, samples_count: u64, percent: f32) -> Duration {
+    if samples_count == 0 {
+        return Duration::ZERO;
+    }
+
+    let rank = ((percent / 100.0 * samples_count as f32).ceil() as u64).clamp(1, samples_count);
+
+    let mut cumulative_count = 0;
+    for (index, count) in counts.enumerate() {
+        cumulative_count += count;
+        if cumulative_count >= rank {
+            return Duration::from_micros(bucket_value_us(index));
+        }
+    }
+
+    Duration::from_micros(MAX_VALUE_US)
+}
+
+// Histogram of all the samples submitted
+#[derive(Clone)]
+pub struct Histogram {
+    counts: Vec<u64>,
+    sum: Duration,
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Self {
+            counts: vec![0; BUCKET_COUNT],
+            sum: Duration::ZERO,
+        }
+    }
+}
+
+impl Histogram {
+    pub fn submit_sample(&mut self, sample: Duration) {
+        let value_us = (sample.as_micros() as u64).min(MAX_VALUE_US);
+        self.counts[bucket_index(value_us)] += 1;
+        self.sum += sample;
+    }
+
+    pub fn samples_count(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    pub fn sum(&self) -> Duration {
+        self.sum
+    }
+
+    // Samples less than or equal to the bound. Samples in the same bucket of the bound are counted
+    // too, so the bound has the same relative error of the samples.
+    pub fn count_up_to(&self, bound: Duration) -> u64 {
+        let bound_us = (bound.as_micros() as u64).min(MAX_VALUE_US);
+
+        self.counts[..=bucket_index(bound_us)].iter().sum()
+    }
+
+    // percent is in the range [0, 100]. Returns zero if there are no samples
+    pub fn get_percentile(&self, percent: f32) -> Duration {
+        percentile(self.counts.iter().copied(), self.samples_count(), percent)
+    }
+
+    fn clear(&mut self) {
+        self.counts.fill(0);
+        self.sum = Duration::ZERO;
+    }
+}
+
+// Percentiles of the samples submitted in the last one to two windows
+pub struct SlidingWindowHistogram {
+    window: Duration,
+    current: Histogram,
+    previous: Histogram,
+    current_window_start: Instant,
+}
+
+impl SlidingWindowHistogram {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            current: Histogram::default(),
+            previous: Histogram::default(),
+            current_window_start: Instant::now(),
+        }
+    }
+
+    fn rotate_windows(&mut self) {
+        let now = Instant::now();
+        if now < self.current_window_start + self.window {
+            return;
+        }
+
+        if now < self.current_window_start + self.window * 2 {
+            self.previous = self.current.clone();
+        } else {
+            // No samples for a whole window, the current samples are too old too
+            self.previous.clear();
+        }
+        self.current.clear();
+        self.current_window_start = now;
+    }
+
+    pub fn submit_sample(&mut self, sample: Duration) {
+        self.rotate_windows();
+
+        self.current.submit_sample(sample);
+    }
+
+    pub fn samples_count(&self) -> u64 {
+        self.current.samples_count() + self.previous.samples_count()
+    }
+
+    // percent is in the range [0, 100]. Returns zero if there are no samples
+    pub fn get_percentile(&self, percent: f32) -> Duration {
+        percentile(
+            self.current
+                .counts
+                .iter()
+                .zip(&self.previous.counts)
+                .map(|(current, previous)| current + previous),
+            self.samples_count(),
+            percent,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bucket_values_roundtrip() {
+        for value_us in [0, 1, 31, 32, 33, 1000, 11_111, 50_000, MAX_VALUE_US] {
+            let stored_us = bucket_value_us(bucket_index(value_us));
+            let error = (stored_us as f64 - value_us as f64).abs() / value_us.max(1) as f64;
+
+            assert!(
+                error <= 1.0 / SUB_BUCKETS_HALF as f64,
+                "{value_us} -> {stored_us}"
+            );
+        }
+    }
+
+    #[test]
+    fn percentiles() {
+        let mut histogram = SlidingWindowHistogram::new(Duration::from_secs(60));
+        for ms in 1..=100 {
+            histogram.submit_sample(Duration::from_millis(ms));
+        }
+
+        let p50_ms = histogram.get_percentile(50.0).as_secs_f32() * 1000.0;
+        let p99_ms = histogram.get_percentile(99.0).as_secs_f32() * 1000.0;
+        assert!((p50_ms - 50.0).abs() < 2.0, "{p50_ms}");
+        assert!((p99_ms - 99.0).abs() < 4.0, "{p99_ms}");
+        assert_eq!(histogram.samples_count(), 100);
+    }
+
+    #[test]
+    fn count_up_to_bound() {
+        let mut histogram = Histogram::default();
+        for ms in 1..=100 {
+            histogram.submit_sample(Duration::from_millis(ms));
+        }
+
+        assert_eq!(histogram.count_up_to(Duration::from_millis(10)), 10);
+        assert_eq!(histogram.count_up_to(Duration::from_secs(1)), 100);
+        assert_eq!(histogram.sum(), Duration::from_millis(5050));
+    }
+}
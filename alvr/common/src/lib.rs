@@ -1,4 +1,5 @@
 mod average;
+mod histogram;
 mod logging;
 mod paths;
 mod primitives;
@@ -24,6 +25,7 @@ pub use semver;
 pub use settings_schema;
 
 pub use average::*;
+pub use histogram::*;
 pub use logging::*;
 pub use paths::*;
 pub use primitives::*;
@@ -22,5 +22,19 @@ pub fn debug_tab_ui(ui: &mut Ui) -> Option<ServerRequest> {
         }
     });
 
+    ui.columns(4, |ui| {
+        if ui[0].button("Create diagnostics bundle").clicked() {
+            request = Some(ServerRequest::CreateDiagnosticsBundle);
+        }
+
+        if ui[2].button("Start frame tracing").clicked() {
+            request = Some(ServerRequest::StartFrameTracing);
+        }
+
+        if ui[3].button("Stop frame tracing").clicked() {
+            request = Some(ServerRequest::StopFrameTracing);
+        }
+    });
+
     request
 }
@@ -4,11 +4,17 @@ use super::{
     NestingInfo, SettingControl,
 };
 use crate::dashboard::{get_id, ServerRequest};
-use alvr_packets::AudioDevicesList;
+use alvr_packets::{AudioDevicesList, SessionHistoryEntry};
 use alvr_session::{SessionSettings, Settings};
-use eframe::egui::{Grid, ScrollArea, Ui};
+use chrono::{DateTime, Local};
+use eframe::{
+    egui::{Button, Grid, Layout, ScrollArea, Ui},
+    emath::Align,
+};
 use serde_json as json;
 
+const MAX_DISPLAYED_CHANGED_PATHS: usize = 3;
+
 pub struct SettingsTab {
     presets_grid_id: usize,
     resolution_preset: PresetControl,
@@ -18,7 +24,9 @@ pub struct SettingsTab {
     microphone_preset: PresetControl,
     eye_face_tracking_preset: PresetControl,
     advanced_grid_id: usize,
+    history_grid_id: usize,
     session_settings_json: json::Value,
+    session_history: Vec<SessionHistoryEntry>,
     root_control: SettingControl,
 }
 
@@ -41,7 +49,9 @@ impl SettingsTab {
             microphone_preset: PresetControl::new(builtin_schema::null_preset_schema()),
             eye_face_tracking_preset: PresetControl::new(builtin_schema::eye_face_tracking_schema()),
             advanced_grid_id: get_id(),
+            history_grid_id: get_id(),
             session_settings_json: json::to_value(session_settings).unwrap(),
+            session_history: vec![],
             root_control: SettingControl::new(nesting_info, schema),
         }
     }
@@ -63,6 +73,10 @@ impl SettingsTab {
             .update_session_settings(&self.session_settings_json);
     }
 
+    pub fn update_session_history(&mut self, history: Vec<SessionHistoryEntry>) {
+        self.session_history = history;
+    }
+
     pub fn update_audio_devices(&mut self, list: AudioDevicesList) {
         let mut all_devices = list.output.clone();
         all_devices.extend(list.input);
@@ -76,7 +90,8 @@ impl SettingsTab {
             .update_session_settings(&self.session_settings_json);
     }
 
-    pub fn ui(&mut self, ui: &mut Ui) -> Option<ServerRequest> {
+    pub fn ui(&mut self, ui: &mut Ui) -> Vec<ServerRequest> {
+        let mut server_requests = vec![];
         let mut requests = vec![];
 
         ui.heading("Presets");
@@ -131,10 +146,64 @@ impl SettingsTab {
                     })
             });
 
+        ui.add_space(15.0);
+
+        ui.horizontal(|ui| {
+            ui.heading("History");
+            ui.with_layout(Layout::right_to_left(Align::Center), |ui| {
+                if ui
+                    .add_enabled(self.session_history.len() > 1, Button::new("Undo"))
+                    .clicked()
+                {
+                    server_requests.push(ServerRequest::UndoSessionChange);
+                }
+            });
+        });
+        ScrollArea::new([true, false])
+            .id_source(self.history_grid_id)
+            .show(ui, |ui| {
+                Grid::new(self.history_grid_id)
+                    .striped(true)
+                    .num_columns(4)
+                    .show(ui, |ui| {
+                        let latest_id = self.session_history.last().map(|entry| entry.id);
+
+                        for entry in self.session_history.iter().rev() {
+                            ui.label(
+                                DateTime::<Local>::from(entry.timestamp)
+                                    .format("%H:%M:%S")
+                                    .to_string(),
+                            );
+                            ui.label(format!("{:?}", entry.origin));
+
+                            let mut description = entry
+                                .changed_paths
+                                .iter()
+                                .take(MAX_DISPLAYED_CHANGED_PATHS)
+                                .cloned()
+                                .collect::<Vec<_>>()
+                                .join(", ");
+                            if entry.changed_paths.len() > MAX_DISPLAYED_CHANGED_PATHS {
+                                description += &format!(
+                                    " and {} more",
+                                    entry.changed_paths.len() - MAX_DISPLAYED_CHANGED_PATHS
+                                );
+                            }
+                            ui.label(description);
+
+                            if Some(entry.id) != latest_id && ui.button("Restore").clicked() {
+                                server_requests
+                                    .push(ServerRequest::RestoreSessionSnapshot(entry.id));
+                            }
+                            ui.end_row();
+                        }
+                    })
+            });
+
         if !requests.is_empty() {
-            Some(ServerRequest::SetValues(requests))
-        } else {
-            None
+            server_requests.push(ServerRequest::SetValues(requests));
         }
+
+        server_requests
     }
 }
@@ -2,6 +2,7 @@ use super::schema::{
     HigherOrderChoiceOption, HigherOrderChoiceSchema, PresetModifier, PresetSchemaNode,
 };
 use crate::dashboard::components::presets::schema::PresetModifierOperation;
+use alvr_packets::AudioDeviceInfo;
 use settings_schema::ChoiceControlType;
 use std::{
     collections::{HashMap, HashSet},
@@ -142,7 +143,7 @@ pub fn encoder_preset_schema() -> PresetSchemaNode {
     })
 }
 
-pub fn game_audio_schema(devices: Vec<String>) -> PresetSchemaNode {
+pub fn game_audio_schema(devices: Vec<AudioDeviceInfo>) -> PresetSchemaNode {
     let mut game_audio_options = vec![
         HigherOrderChoiceOption {
             display_name: "Disabled".into(),
@@ -165,20 +166,16 @@ pub fn game_audio_schema(devices: Vec<String>) -> PresetSchemaNode {
         },
     ];
 
-    for name in devices {
+    const PREFIX: &str = "session_settings.audio.game_audio.content.device";
+    for device in devices {
         game_audio_options.push(HigherOrderChoiceOption {
-            display_name: name.clone(),
+            display_name: device.name.clone(),
             modifiers: vec![
                 bool_modifier("session_settings.audio.game_audio.enabled", true),
-                bool_modifier("session_settings.audio.game_audio.content.device.set", true),
-                string_modifier(
-                    "session_settings.audio.game_audio.content.device.content.variant",
-                    "NameSubstring",
-                ),
-                string_modifier(
-                    "session_settings.audio.game_audio.content.device.content.NameSubstring",
-                    &name,
-                ),
+                bool_modifier(&format!("{PREFIX}.set"), true),
+                string_modifier(&format!("{PREFIX}.content.variant"), "Id"),
+                string_modifier(&format!("{PREFIX}.content.Id.id"), &device.id),
+                string_modifier(&format!("{PREFIX}.content.Id.name"), &device.name),
             ],
             content: None,
         })
@@ -199,7 +196,7 @@ pub fn game_audio_schema(devices: Vec<String>) -> PresetSchemaNode {
     })
 }
 
-pub fn microphone_schema(devices: Vec<String>) -> PresetSchemaNode {
+pub fn microphone_schema(devices: Vec<AudioDeviceInfo>) -> PresetSchemaNode {
     let mut microhone_options = vec![HigherOrderChoiceOption {
         display_name: "Disabled".to_owned(),
         modifiers: vec![bool_modifier(
@@ -231,14 +228,15 @@ pub fn microphone_schema(devices: Vec<String>) -> PresetSchemaNode {
         }
     } else {
         const PREFIX: &str = "session_settings.audio.microphone.content.devices";
-        for name in devices {
+        for device in devices {
             microhone_options.push(HigherOrderChoiceOption {
-                display_name: name.clone(),
+                display_name: device.name.clone(),
                 modifiers: vec![
                     bool_modifier("session_settings.audio.microphone.enabled", true),
                     string_modifier(&format!("{PREFIX}.variant"), "Custom"),
-                    string_modifier(&format!("{PREFIX}.Custom.sink.variant"), "NameSubstring"),
-                    string_modifier(&format!("{PREFIX}.Custom.sink.NameSubstring"), &name),
+                    string_modifier(&format!("{PREFIX}.Custom.sink.variant"), "Id"),
+                    string_modifier(&format!("{PREFIX}.Custom.sink.Id.id"), &device.id),
+                    string_modifier(&format!("{PREFIX}.Custom.sink.Id.name"), &device.name),
                     // string_modifier(&format!("{PREFIX}.Custom.source.variant"), "NameSubstring"),
                     // string_modifier(&format!("{PREFIX}.Custom.source.NameSubstring"), ""),
                 ],
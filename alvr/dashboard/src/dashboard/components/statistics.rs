@@ -320,6 +320,13 @@ impl StatisticsTab {
             ui[0].label("Total latency:");
             ui[1].label(&format!("{:.2} ms", statistics.total_latency_ms));
 
+            let total_percentiles = &statistics.latency_percentiles.total;
+            ui[0].label("Total latency p50/p95/p99:");
+            ui[1].label(&format!(
+                "{:.2} / {:.2} / {:.2} ms",
+                total_percentiles.p50_ms, total_percentiles.p95_ms, total_percentiles.p99_ms
+            ));
+
             ui[0].label("Encoder latency:");
             ui[1].label(&format!("{:.2} ms", statistics.encode_latency_ms));
 
@@ -341,8 +348,64 @@ impl StatisticsTab {
             ui[0].label("Streamer FPS:");
             ui[1].label(&format!("{} FPS", statistics.server_fps));
 
+            ui[0].label("Frame jitter:");
+            ui[1].label(&format!("{:.2} ms", statistics.frame_jitter_ms));
+
+            ui[0].label("Stutters:");
+            ui[1].label(&format!("{}", statistics.stutters_total));
+
+            for (name, audio) in [
+                ("Game audio", statistics.game_audio),
+                ("Microphone", statistics.microphone),
+            ] {
+                ui[0].label(&format!("{name} buffer (target):"));
+                ui[1].label(&format!(
+                    "{:.0} ms ({:.0} ms), jitter {:.1} ms",
+                    audio.buffer_ms, audio.target_buffer_ms, audio.jitter_ms
+                ));
+
+                ui[0].label(&format!("{name} glitches:"));
+                ui[1].label(&format!("{}", audio.glitches_total));
+
+                if let Some(offset) = audio.av_offset_ms {
+                    ui[0].label(&format!("{name} A/V offset:"));
+                    ui[1].label(&format!("{offset:.0} ms"));
+                }
+            }
+
             ui[0].label("Headset battery");
             ui[1].label(&format!("{}%", statistics.battery_hmd));
+
+            let health = &statistics.client_health;
+            if let Some(temperature) = health.battery_temperature_c {
+                ui[0].label("Headset battery temperature:");
+                ui[1].label(&format!("{temperature:.1} °C"));
+            }
+
+            if let (Some(cpu_level), Some(gpu_level)) = (health.cpu_level, health.gpu_level) {
+                ui[0].label("Headset CPU/GPU level:");
+                ui[1].label(&format!("{cpu_level:?} / {gpu_level:?}"));
+            }
+
+            if let Some(thermal_throttling) = health.thermal_throttling {
+                ui[0].label("Thermal throttling:");
+                ui[1].label(if thermal_throttling { "Yes" } else { "No" });
+            }
+
+            if let Some(wifi) = health.wifi {
+                ui[0].label("Wi-Fi signal:");
+                ui[1].label(&format!(
+                    "{} dBm, {} Mbps, {:.1} GHz",
+                    wifi.rssi_dbm,
+                    wifi.link_speed_mbps,
+                    wifi.frequency_mhz as f32 / 1000.
+                ));
+            }
+
+            if let Some(free_memory_bytes) = health.free_memory_bytes {
+                ui[0].label("Headset free memory:");
+                ui[1].label(&format!("{} MB", free_memory_bytes / 1_000_000));
+            }
         });
     }
 }
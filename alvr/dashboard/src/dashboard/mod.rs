@@ -77,6 +77,7 @@ pub struct Dashboard {
 impl Dashboard {
     pub fn new(creation_context: &eframe::CreationContext<'_>, data_sources: DataSources) -> Self {
         data_sources.request(ServerRequest::GetSession);
+        data_sources.request(ServerRequest::GetSessionHistory);
         data_sources.request(ServerRequest::GetAudioDevices);
         data_sources.request(ServerRequest::GetDriverList);
 
@@ -146,6 +147,9 @@ impl eframe::App for Dashboard {
 
                     self.session = *session;
                 }
+                EventType::SessionHistory(history) => {
+                    self.settings_tab.update_session_history(history)
+                }
                 EventType::ServerRequestsSelfRestart => {
                     if !self.server_restarting.value() {
                         self.server_restarting.set(true);
@@ -163,7 +167,11 @@ impl eframe::App for Dashboard {
                 }
                 EventType::AudioDevices(list) => self.settings_tab.update_audio_devices(list),
                 EventType::DriversList(list) => self.installation_tab.update_drivers(list),
-                EventType::Tracking(_) | EventType::Buttons(_) | EventType::Haptics(_) => (),
+                EventType::Tracking(_)
+                | EventType::Buttons(_)
+                | EventType::Haptics(_)
+                | EventType::Stutter(_)
+                | EventType::LogFiles(_) => (),
             }
         }
 
@@ -1,6 +1,6 @@
 use alvr_common::{parking_lot::Mutex, prelude::*, RelaxedAtomic};
 use alvr_events::{Event, EventType};
-use alvr_packets::ServerRequest;
+use alvr_packets::{ServerRequest, SessionChangeOrigin, SESSION_CHANGE_ORIGIN_HEADER};
 use alvr_server_io::ServerDataManager;
 use eframe::egui;
 use std::{
@@ -12,7 +12,8 @@ use std::{
     thread::{self, JoinHandle},
     time::{Duration, Instant},
 };
-use tungstenite::http::Uri;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tungstenite::{client::IntoClientRequest, http::HeaderValue};
 
 const REQUEST_TIMEOUT: Duration = Duration::from_millis(200);
 
@@ -29,6 +30,17 @@ pub fn get_local_data_source() -> ServerDataManager {
     ServerDataManager::new(&session_file_path)
 }
 
+// Without the token the streamer rejects every request
+fn get_web_server_token() -> Option<String> {
+    let token_path =
+        alvr_filesystem::filesystem_layout_from_dashboard_exe(&env::current_exe().unwrap())
+            .web_server_token();
+
+    alvr_server_io::load_or_create_web_server_token(&token_path)
+        .map_err(|e| error!("{e}"))
+        .ok()
+}
+
 fn report_event_local(
     context: &egui::Context,
     sender: &mpsc::Sender<Event>,
@@ -52,6 +64,11 @@ fn report_session_local(
         context,
         sender,
         EventType::Session(Box::new(data_manager.session().clone())),
+    );
+    report_event_local(
+        context,
+        sender,
+        EventType::SessionHistory(data_manager.session_history()),
     )
 }
 
@@ -62,21 +79,23 @@ pub struct DataSources {
     server_connected: Arc<RelaxedAtomic>,
     requests_thread: Option<JoinHandle<()>>,
     events_thread: Option<JoinHandle<()>>,
+    local_events_thread: Option<JoinHandle<()>>,
     ping_thread: Option<JoinHandle<()>>,
 }
 
 impl DataSources {
     pub fn new(
         context: egui::Context,
-        events_sender: mpsc::Sender<Event>,
-        events_receiver: mpsc::Receiver<Event>,
+        mut local_events_receiver: broadcast::Receiver<Event>,
     ) -> Self {
         let running = Arc::new(RelaxedAtomic::new(true));
         let (requests_sender, requests_receiver) = mpsc::channel();
+        let (events_sender, events_receiver) = mpsc::channel();
         let server_connected = Arc::new(RelaxedAtomic::new(false));
 
         let server_data_manager = get_local_data_source();
         let port = server_data_manager.settings().connection.web_server_port;
+        let authorization = get_web_server_token().map(|token| format!("Bearer {token}"));
         let data_source = Arc::new(Mutex::new(DataSource::Local(Box::new(server_data_manager))));
 
         let requests_thread = thread::spawn({
@@ -84,6 +103,7 @@ impl DataSources {
             let context = context.clone();
             let data_source = Arc::clone(&data_source);
             let events_sender = events_sender.clone();
+            let authorization = authorization.clone();
             move || {
                 let uri = format!("http://127.0.0.1:{port}/api/dashboard-request");
                 let request_agent = ureq::AgentBuilder::new()
@@ -91,6 +111,11 @@ impl DataSources {
                     .build();
 
                 while running.value() {
+                    if let DataSource::Local(data_manager) = &mut *data_source.lock() {
+                        // Changes are reported through the local event bus
+                        data_manager.reload_if_changed();
+                    }
+
                     while let Ok(request) = requests_receiver.try_recv() {
                         debug!("Dashboard request: {request:?}");
 
@@ -101,21 +126,53 @@ impl DataSources {
                                     report_session_local(&context, &events_sender, data_manager);
                                 }
                                 ServerRequest::UpdateSession(session) => {
-                                    *data_manager.session_mut() = *session;
-
-                                    report_session_local(&context, &events_sender, data_manager);
+                                    data_manager
+                                        .update_session(*session, SessionChangeOrigin::Dashboard);
                                 }
                                 ServerRequest::SetValues(descs) => {
-                                    if let Err(e) = data_manager.set_values(descs) {
-                                        error!("Failed to set session value: {e}")
+                                    if let Err(e) = data_manager
+                                        .set_values(descs, SessionChangeOrigin::Dashboard)
+                                    {
+                                        error!("Failed to set session value: {e}");
+
+                                        // On success the change is reported through the local
+                                        // event bus. On failure the UI has to be reverted
+                                        report_session_local(
+                                            &context,
+                                            &events_sender,
+                                            data_manager,
+                                        );
                                     }
+                                }
+                                ServerRequest::GetSessionHistory => report_event_local(
+                                    &context,
+                                    &events_sender,
+                                    EventType::SessionHistory(data_manager.session_history()),
+                                ),
+                                ServerRequest::UndoSessionChange => {
+                                    if let Err(e) = data_manager.undo_session_change() {
+                                        warn!("Failed to undo session change: {e}");
+
+                                        report_session_local(
+                                            &context,
+                                            &events_sender,
+                                            data_manager,
+                                        );
+                                    }
+                                }
+                                ServerRequest::RestoreSessionSnapshot(id) => {
+                                    if let Err(e) = data_manager.restore_session_snapshot(id) {
+                                        error!("Failed to restore session snapshot: {e}");
 
-                                    report_session_local(&context, &events_sender, data_manager);
+                                        report_session_local(
+                                            &context,
+                                            &events_sender,
+                                            data_manager,
+                                        );
+                                    }
                                 }
                                 ServerRequest::UpdateClientList { hostname, action } => {
                                     data_manager.update_client_list(hostname, action);
-
-                                    report_session_local(&context, &events_sender, data_manager);
                                 }
                                 ServerRequest::GetAudioDevices => {
                                     if let Ok(list) = data_manager.get_audio_devices_list() {
@@ -171,18 +228,67 @@ impl DataSources {
                                         )
                                     }
                                 }
+                                ServerRequest::GetLogFiles => {
+                                    let logs_dir =
+                                        alvr_filesystem::filesystem_layout_from_dashboard_exe(
+                                            &env::current_exe().unwrap(),
+                                        )
+                                        .logs_dir();
+
+                                    if let Ok(list) = alvr_server_io::get_log_files(&logs_dir) {
+                                        report_event_local(
+                                            &context,
+                                            &events_sender,
+                                            EventType::LogFiles(list),
+                                        )
+                                    }
+                                }
+                                ServerRequest::CreateDiagnosticsBundle => {
+                                    let layout =
+                                        alvr_filesystem::filesystem_layout_from_dashboard_exe(
+                                            &env::current_exe().unwrap(),
+                                        );
+
+                                    // Statistics are only available while the streamer is running
+                                    match alvr_server_io::create_diagnostics_bundle(
+                                        data_manager,
+                                        &layout.logs_dir(),
+                                        &layout.crash_log(),
+                                        &[],
+                                    )
+                                    .and_then(|bundle| {
+                                        alvr_server_io::save_diagnostics_bundle(
+                                            &bundle,
+                                            &layout.diagnostics_bundles_dir(),
+                                        )
+                                    }) {
+                                        Ok(path) => {
+                                            info!("Diagnostics bundle saved to {}", path.display())
+                                        }
+                                        Err(e) => {
+                                            error!("Failed to create diagnostics bundle: {e}")
+                                        }
+                                    }
+                                }
                                 ServerRequest::CaptureFrame
                                 | ServerRequest::InsertIdr
                                 | ServerRequest::StartRecording
-                                | ServerRequest::StopRecording => {
+                                | ServerRequest::StopRecording
+                                | ServerRequest::StartFrameTracing
+                                | ServerRequest::StopFrameTracing => {
                                     warn!("Cannot perform action, streamer is not connected.")
                                 }
                                 ServerRequest::RestartSteamvr | ServerRequest::ShutdownSteamvr => {
                                     warn!("SteamVR not launched")
                                 }
                             }
-                        } else {
-                            request_agent.get(&uri).send_json(&request).ok();
+                        } else if let Some(authorization) = &authorization {
+                            request_agent
+                                .get(&uri)
+                                .set("Authorization", authorization)
+                                .set(SESSION_CHANGE_ORIGIN_HEADER, "Dashboard")
+                                .send_json(&request)
+                                .ok();
                         }
                     }
 
@@ -191,11 +297,40 @@ impl DataSources {
             }
         });
 
+        // Events generated inside the dashboard process, like logs and local session changes
+        let local_events_thread = thread::spawn({
+            let running = Arc::clone(&running);
+            let context = context.clone();
+            let events_sender = events_sender.clone();
+            move || {
+                while running.value() {
+                    match local_events_receiver.blocking_recv() {
+                        Ok(event) => {
+                            events_sender.send(event).ok();
+                            context.request_repaint();
+                        }
+                        Err(RecvError::Lagged(count)) => {
+                            warn!("Dashboard skipped {count} events")
+                        }
+                        Err(RecvError::Closed) => return,
+                    }
+                }
+            }
+        });
+
         let events_thread = thread::spawn({
             let running = Arc::clone(&running);
             move || {
                 while running.value() {
-                    let uri = Uri::from_str(&format!("ws://127.0.0.1:{port}/api/events")).unwrap();
+                    let mut ws_request = format!("ws://127.0.0.1:{port}/api/events")
+                        .into_client_request()
+                        .unwrap();
+                    if let Some(authorization) = &authorization {
+                        ws_request.headers_mut().insert(
+                            "Authorization",
+                            HeaderValue::from_str(authorization).unwrap(),
+                        );
+                    }
 
                     let maybe_socket = TcpStream::connect_timeout(
                         &SocketAddr::from_str(&format!("127.0.0.1:{port}")).unwrap(),
@@ -209,7 +344,7 @@ impl DataSources {
                         continue;
                     };
 
-                    let mut ws = if let Ok((ws, _)) = tungstenite::client(uri, socket) {
+                    let mut ws = if let Ok((ws, _)) = tungstenite::client(ws_request, socket) {
                         ws
                     } else {
                         thread::sleep(Duration::from_millis(500));
@@ -295,6 +430,7 @@ impl DataSources {
             running,
             requests_thread: Some(requests_thread),
             events_thread: Some(events_thread),
+            local_events_thread: Some(local_events_thread),
             ping_thread: Some(ping_thread),
         }
     }
@@ -318,6 +454,8 @@ impl Drop for DataSources {
 
         self.requests_thread.take().unwrap().join().ok();
         self.events_thread.take().unwrap().join().ok();
+        // The local events thread is blocked until the next event, it exits on its own after that
+        self.local_events_thread.take();
         self.ping_thread.take().unwrap().join().ok();
     }
 }
@@ -1,15 +1,9 @@
-use alvr_common::{log::LevelFilter, parking_lot::Mutex, LogEntry, LogSeverity};
-use alvr_events::{Event, EventType};
-use std::{
-    io::Write,
-    sync::{mpsc, Arc},
-};
-
-pub fn init_logging(event_sender: mpsc::Sender<Event>) {
-    let event_sender = Arc::new(Mutex::new(event_sender));
+use alvr_common::{log::LevelFilter, LogEntry, LogSeverity};
+use alvr_events::EventType;
+use std::io::Write;
 
+pub fn init_logging() {
     env_logger::Builder::new()
-        .filter(Some("alvr_events"), LevelFilter::Off)
         .filter(Some("naga"), LevelFilter::Off)
         .filter(Some("ureq"), LevelFilter::Off)
         .filter(Some("wgpu_core"), LevelFilter::Off)
@@ -19,19 +13,13 @@ pub fn init_logging(event_sender: mpsc::Sender<Event>) {
         } else {
             LevelFilter::Info
         })
-        .format(move |f, record| {
+        .format(|f, record| {
             let timestamp = chrono::Local::now().format("%H:%M:%S.%f").to_string();
 
-            event_sender
-                .lock()
-                .send(Event {
-                    timestamp: timestamp.clone(),
-                    event_type: EventType::Log(LogEntry {
-                        severity: LogSeverity::from_log_level(record.level()),
-                        content: format!("{}", record.args()),
-                    }),
-                })
-                .ok();
+            alvr_events::send_event(EventType::Log(LogEntry {
+                severity: LogSeverity::from_log_level(record.level()),
+                content: format!("{}", record.args()),
+            }));
 
             writeln!(
                 f,
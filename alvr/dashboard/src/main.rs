@@ -21,10 +21,11 @@ fn main() {
     use eframe::{egui, IconData, NativeOptions};
     use ico::IconDir;
     use std::env;
-    use std::{io::Cursor, sync::mpsc};
+    use std::io::Cursor;
 
-    let (server_events_sender, server_events_receiver) = mpsc::channel();
-    logging_backend::init_logging(server_events_sender.clone());
+    // Subscribe before installing the logger so no log is missed
+    let events_receiver = alvr_events::subscribe();
+    logging_backend::init_logging();
 
     env::set_var("WINIT_X11_SCALE_FACTOR", "1");
 
@@ -74,11 +75,8 @@ fn main() {
         },
         {
             Box::new(move |creation_context| {
-                let data_source = DataSources::new(
-                    creation_context.egui_ctx.clone(),
-                    server_events_sender,
-                    server_events_receiver,
-                );
+                let data_source =
+                    DataSources::new(creation_context.egui_ctx.clone(), events_receiver);
 
                 Box::new(Dashboard::new(creation_context, data_source))
             })
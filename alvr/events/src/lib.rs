@@ -1,8 +1,38 @@
-use alvr_common::{prelude::*, DeviceMotion, Pose};
-use alvr_packets::{AudioDevicesList, ButtonValue};
+use alvr_common::{once_cell::sync::Lazy, prelude::*, DeviceMotion, LogSeverity, Pose};
+use alvr_packets::{
+    AudioDevicesList, AudioPlaybackStatistics, ButtonValue, ClientHealth, LogFileInfo,
+    SessionHistoryEntry,
+};
 use alvr_session::SessionDesc;
 use serde::{Deserialize, Serialize};
 use std::{path::PathBuf, time::Duration};
+use tokio::sync::broadcast;
+
+// Subscribers that fall behind more than this many events will skip the oldest ones
+pub const EVENTS_BUS_CAPACITY: usize = 256;
+
+static EVENTS_SENDER: Lazy<broadcast::Sender<Event>> =
+    Lazy::new(|| broadcast::channel(EVENTS_BUS_CAPACITY).0);
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct LatencyPercentiles {
+    pub p50_ms: f32,
+    pub p95_ms: f32,
+    pub p99_ms: f32,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct StageLatencyPercentiles {
+    pub total: LatencyPercentiles,
+    pub game: LatencyPercentiles,
+    pub server_compositor: LatencyPercentiles,
+    pub encoder: LatencyPercentiles,
+    pub network: LatencyPercentiles,
+    pub decoder: LatencyPercentiles,
+    pub decoder_queue: LatencyPercentiles,
+    pub client_compositor: LatencyPercentiles,
+    pub vsync_queue: LatencyPercentiles,
+}
 
 #[derive(Serialize, Deserialize, Clone, Debug, Default)]
 pub struct Statistics {
@@ -21,6 +51,12 @@ pub struct Statistics {
     pub battery_hmd: u32,
     pub battery_left: u32,
     pub battery_right: u32,
+    pub latency_percentiles: StageLatencyPercentiles,
+    pub frame_jitter_ms: f32,
+    pub stutters_total: usize,
+    pub client_health: ClientHealth,
+    pub game_audio: AudioPlaybackStatistics,
+    pub microphone: AudioPlaybackStatistics,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, Default)]
@@ -38,8 +74,57 @@ pub struct GraphStatistics {
     pub server_fps: f32,
 }
 
+// A frame displayed later than the target interval after the previous one, with the latency of every
+// stage
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct StutterEvent {
+    pub target_interval_s: f32,
+    pub frame: GraphStatistics,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct FrameStatisticsRecord {
+    pub target_timestamp_ns: u64, // identifies the frame
+    pub game_time_s: f32,
+    pub server_compositor_s: f32,
+    pub encoder_s: f32,
+    pub network_s: f32,
+    pub decoder_s: f32,
+    pub decoder_queue_s: f32,
+    pub client_compositor_s: f32,
+    pub vsync_queue_s: f32,
+    pub total_pipeline_latency_s: f32,
+    pub client_frame_interval_s: f32,
+    pub server_frame_interval_s: f32,
+}
+
+// Entries of a statistics recording, stored in sequence with bincode. time_s is the time since
+// the start of the recording
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub enum StatisticsRecord {
+    // Always the first entry
+    Start {
+        alvr_version: String,
+        client_hostname: String,
+        nominal_frame_interval_s: f32,
+    },
+    Frame {
+        time_s: f64,
+        frame: FrameStatisticsRecord,
+    },
+    TargetBitrate {
+        time_s: f64,
+        bitrate_bps: u64,
+    },
+    PacketLoss {
+        time_s: f64,
+    },
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct TrackingEvent {
+    // Target timestamp of the tracking sample, in the client clock
+    pub target_timestamp: Duration,
     pub head_motion: Option<DeviceMotion>,
     pub controller_motions: [Option<DeviceMotion>; 2],
     pub hand_skeletons: [Option<[Pose; 26]>; 2],
@@ -68,22 +153,95 @@ pub struct HapticsEvent {
 pub enum EventType {
     Log(LogEntry),
     Session(Box<SessionDesc>),
+    SessionHistory(Vec<SessionHistoryEntry>),
     Statistics(Statistics),
     GraphStatistics(GraphStatistics),
+    Stutter(StutterEvent),
     Tracking(Box<TrackingEvent>),
     Buttons(Vec<ButtonEvent>),
     Haptics(HapticsEvent),
     AudioDevices(AudioDevicesList),
     DriversList(Vec<PathBuf>),
+    LogFiles(Vec<LogFileInfo>),
     ServerRequestsSelfRestart,
 }
 
+impl EventType {
+    // Same as the serialized "id" field
+    pub fn id(&self) -> &'static str {
+        match self {
+            EventType::Log(_) => "Log",
+            EventType::Session(_) => "Session",
+            EventType::SessionHistory(_) => "SessionHistory",
+            EventType::Statistics(_) => "Statistics",
+            EventType::GraphStatistics(_) => "GraphStatistics",
+            EventType::Stutter(_) => "Stutter",
+            EventType::Tracking(_) => "Tracking",
+            EventType::Buttons(_) => "Buttons",
+            EventType::Haptics(_) => "Haptics",
+            EventType::AudioDevices(_) => "AudioDevices",
+            EventType::DriversList(_) => "DriversList",
+            EventType::LogFiles(_) => "LogFiles",
+            EventType::ServerRequestsSelfRestart => "ServerRequestsSelfRestart",
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct Event {
     pub timestamp: String,
     pub event_type: EventType,
 }
 
+// Sent by /api/events subscribers to select which events they receive. It can be sent right after
+// connecting and replaced at any time. Missing fields do not filter anything.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct EventFilter {
+    pub event_types: Option<Vec<String>>, // event ids, like "Log" or "Statistics"
+    pub min_log_severity: Option<LogSeverity>,
+    pub max_tracking_rate_hz: Option<f32>,
+    pub max_statistics_rate_hz: Option<f32>, // applies to Statistics and GraphStatistics
+}
+
+impl EventFilter {
+    pub fn matches(&self, event_type: &EventType) -> bool {
+        if let Some(ids) = &self.event_types {
+            if !ids.iter().any(|id| id == event_type.id()) {
+                return false;
+            }
+        }
+
+        match (event_type, self.min_log_severity) {
+            (EventType::Log(entry), Some(min_severity)) => entry.severity >= min_severity,
+            _ => true,
+        }
+    }
+
+    // Minimum interval between two events of the same type, if rate limited
+    pub fn min_interval(&self, event_type: &EventType) -> Option<Duration> {
+        let max_rate_hz = match event_type {
+            EventType::Tracking(_) => self.max_tracking_rate_hz,
+            EventType::Statistics(_) | EventType::GraphStatistics(_) => self.max_statistics_rate_hz,
+            _ => None,
+        }?;
+
+        Some(Duration::from_secs_f32(
+            1.0 / f32::max(max_rate_hz, f32::EPSILON),
+        ))
+    }
+}
+
+// Events sent before any subscriber is created are dropped
 pub fn send_event(event_type: EventType) {
-    info!("{}", serde_json::to_string(&event_type).unwrap());
+    EVENTS_SENDER
+        .send(Event {
+            timestamp: chrono::Local::now().format("%H:%M:%S.%f").to_string(),
+            event_type,
+        })
+        .ok();
+}
+
+pub fn subscribe() -> broadcast::Receiver<Event> {
+    EVENTS_SENDER.subscribe()
 }
@@ -163,6 +163,10 @@ impl Layout {
         self.executables_dir.join(dashboard_fname())
     }
 
+    pub fn cli_exe(&self) -> PathBuf {
+        self.executables_dir.join(exec_fname("alvr_cli"))
+    }
+
     pub fn resources_dir(&self) -> PathBuf {
         self.openvr_driver_root_dir.join("resources")
     }
@@ -179,11 +183,31 @@ impl Layout {
         self.config_dir.join("session.json")
     }
 
-    pub fn session_log(&self) -> PathBuf {
+    pub fn web_server_token(&self) -> PathBuf {
+        self.config_dir.join("web_server_token")
+    }
+
+    pub fn logs_dir(&self) -> PathBuf {
+        if cfg!(target_os = "linux") {
+            self.log_dir.join("alvr_logs")
+        } else {
+            self.log_dir.join("logs")
+        }
+    }
+
+    pub fn diagnostics_bundles_dir(&self) -> PathBuf {
+        if cfg!(target_os = "linux") {
+            self.log_dir.join("alvr_diagnostics")
+        } else {
+            self.log_dir.join("diagnostics")
+        }
+    }
+
+    pub fn statistics_recordings_dir(&self) -> PathBuf {
         if cfg!(target_os = "linux") {
-            self.log_dir.join("alvr_session_log.txt")
+            self.log_dir.join("alvr_statistics")
         } else {
-            self.log_dir.join("session_log.txt")
+            self.log_dir.join("statistics")
         }
     }
 
@@ -2,13 +2,13 @@ use alvr_common::{
     glam::{UVec2, Vec2},
     DeviceMotion, Fov, LogEntry, LogSeverity, Pose,
 };
-use alvr_session::{CodecType, SessionDesc};
+use alvr_session::{AudioCodecConfig, CodecType, SessionDesc};
 use serde::{Deserialize, Serialize};
 use std::{
     fmt::{self, Debug},
     net::IpAddr,
     path::PathBuf,
-    time::Duration,
+    time::{Duration, SystemTime},
 };
 
 pub const TRACKING: u16 = 0;
@@ -22,6 +22,8 @@ pub struct VideoStreamingCapabilities {
     pub default_view_resolution: UVec2,
     pub supported_refresh_rates: Vec<f32>,
     pub microphone_sample_rate: u32,
+    // Maximum number of game audio channels the client can play
+    pub game_audio_channels_count: u16,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -34,12 +36,25 @@ pub enum ClientConnectionResult {
     ClientStandby,
 }
 
+// Format of the samples sent over the network. Each side converts from and to the format of its
+// devices. The sample format is defined by the codec. Streams with more than 2 channels follow the
+// WAVEFORMATEXTENSIBLE order (FL FR FC LFE BL BR SL SR).
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AudioStreamFormat {
+    pub sample_rate: u32,
+    pub channels_count: u16,
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct StreamConfigPacket {
     pub session_desc: String, // transfer session as string to allow for extrapolation
     pub view_resolution: UVec2,
     pub fps: f32,
-    pub game_audio_sample_rate: u32,
+    // Chosen by the streamer according to the settings and the device formats
+    pub game_audio_format: AudioStreamFormat,
+    pub game_audio_codec: AudioCodecConfig,
+    pub microphone_format: AudioStreamFormat,
+    pub microphone_codec: AudioCodecConfig,
 }
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -57,6 +72,8 @@ pub enum ServerControlPacket {
     ServerPredictionAverage(Duration),
     Reserved(String),
     ReservedBuffer(Vec<u8>),
+    // New variants go after the reserved ones, to keep their indices
+    UpdateSettings(String), // session_desc in json format, sent when settings change mid-stream
 }
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -73,6 +90,32 @@ pub struct BatteryPacket {
     pub is_plugged: bool,
 }
 
+// Ordered from best to worst
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum PerformanceLevel {
+    Normal,
+    Warning,
+    Impaired,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct WifiStatus {
+    pub rssi_dbm: i32,
+    pub link_speed_mbps: u32,
+    pub frequency_mhz: u32,
+}
+
+// Fields are None when they are not available on the client platform
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct ClientHealth {
+    pub battery_temperature_c: Option<f32>,
+    pub cpu_level: Option<PerformanceLevel>,
+    pub gpu_level: Option<PerformanceLevel>,
+    pub thermal_throttling: Option<bool>,
+    pub wifi: Option<WifiStatus>,
+    pub free_memory_bytes: Option<u64>,
+}
+
 #[derive(Serialize, Deserialize, Clone, Copy, Debug)]
 pub enum ButtonValue {
     Binary(bool),
@@ -99,6 +142,8 @@ pub enum ClientControlPacket {
     Log { level: LogSeverity, message: String },
     Reserved(String),
     ReservedBuffer(Vec<u8>),
+    // New variants go after the reserved ones, to keep their indices
+    Health(ClientHealth),
 }
 
 #[derive(Serialize, Deserialize, Default)]
@@ -115,6 +160,13 @@ pub struct VideoPacketHeader {
     pub is_idr: bool,
 }
 
+// timestamp is on the same clock as VideoPacketHeader::timestamp and refers to the first sample of
+// the packet. It is None until the first video frame is available.
+#[derive(Serialize, Deserialize)]
+pub struct AudioPacketHeader {
+    pub timestamp: Option<Duration>,
+}
+
 // Note: face_data does not respect target_timestamp.
 #[derive(Serialize, Deserialize, Default)]
 pub struct Tracking {
@@ -132,12 +184,30 @@ pub struct Haptics {
     pub amplitude: f32,
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct AudioDeviceInfo {
+    // Endpoint ID on Windows, the backend device name on other platforms (the PCM name with ALSA)
+    pub id: String,
+    pub name: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct AudioDevicesList {
-    pub output: Vec<String>,
-    pub input: Vec<String>,
+    pub output: Vec<AudioDeviceInfo>,
+    pub input: Vec<AudioDeviceInfo>,
+    // IDs of the devices selected as default by the OS
+    pub default_output: Option<String>,
+    pub default_input: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct LogFileInfo {
+    pub name: String,
+    pub size_bytes: u64,
+    pub modified_unix_secs: u64,
 }
 
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub enum GpuVendor {
     Nvidia,
     Amd,
@@ -177,9 +247,25 @@ impl From<usize> for PathSegment {
     }
 }
 
-// todo: support indices
+// Example: "session_settings.video.foveated_rendering.content.strength". Indices are written
+// in brackets, like "a.b[0].c"
 pub fn parse_path(path: &str) -> Vec<PathSegment> {
-    path.split('.').map(|s| s.into()).collect()
+    let mut segments = vec![];
+    for part in path.split('.') {
+        let mut subparts = part.split('[');
+
+        if let Some(name) = subparts.next().filter(|name| !name.is_empty()) {
+            segments.push(name.into());
+        }
+
+        for index in subparts {
+            if let Ok(index) = index.trim_end_matches(']').parse::<usize>() {
+                segments.push(index.into());
+            }
+        }
+    }
+
+    segments
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -195,6 +281,20 @@ pub enum ClientListAction {
     UpdateCurrentIp(Option<IpAddr>),
 }
 
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default)]
+pub struct AudioPlaybackStatistics {
+    pub buffer_ms: f32,
+    pub target_buffer_ms: f32,
+    pub jitter_ms: f32,
+    // Greater than 1 when the buffer is drained faster to reduce its level
+    pub resampling_ratio: f32,
+    // Underflows, overflows and packet losses not concealed by the codec
+    pub glitches_total: usize,
+    // Positive when the audio is played after the corresponding video frame. None if the playback
+    // is not synchronized with the video
+    pub av_offset_ms: Option<f32>,
+}
+
 #[derive(Serialize, Deserialize, Default, Clone)]
 pub struct ClientStatistics {
     pub target_timestamp: Duration, // identifies the frame
@@ -204,6 +304,10 @@ pub struct ClientStatistics {
     pub rendering: Duration,
     pub vsync_queue: Duration,
     pub total_pipeline_latency: Duration,
+    pub game_audio: AudioPlaybackStatistics,
+    // Client clock, relative to the start of the stream. Used to align the client timeline
+    pub input_acquired: Duration,
+    pub video_packet_received: Duration,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -212,6 +316,30 @@ pub struct PathValuePair {
     pub value: serde_json::Value,
 }
 
+// Header of the /api/dashboard-request requests, with the name of the SessionChangeOrigin variant of
+// the caller. Requests without it are recorded as HttpApi changes.
+pub const SESSION_CHANGE_ORIGIN_HEADER: &str = "X-ALVR-Session-Change-Origin";
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SessionChangeOrigin {
+    Dashboard,
+    HttpApi,
+    Cli,
+    Migration,
+    Rollback,
+    External, // written by another process
+    Internal,
+}
+
+// Describes a session change. The corresponding snapshot is retained by the streamer.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SessionHistoryEntry {
+    pub id: u64,
+    pub timestamp: SystemTime,
+    pub origin: SessionChangeOrigin,
+    pub changed_paths: Vec<String>,
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub enum FirewallRulesAction {
     Add,
@@ -224,6 +352,9 @@ pub enum ServerRequest {
     GetSession,
     UpdateSession(Box<SessionDesc>),
     SetValues(Vec<PathValuePair>),
+    GetSessionHistory,
+    UndoSessionChange,
+    RestoreSessionSnapshot(u64),
     UpdateClientList {
         hostname: String,
         action: ClientListAction,
@@ -233,10 +364,14 @@ pub enum ServerRequest {
     InsertIdr,
     StartRecording,
     StopRecording,
+    StartFrameTracing,
+    StopFrameTracing,
     FirewallRules(FirewallRulesAction),
     RegisterAlvrDriver,
     UnregisterDriver(PathBuf),
     GetDriverList,
+    GetLogFiles,
+    CreateDiagnosticsBundle,
     RestartSteamvr,
     ShutdownSteamvr,
 }
@@ -47,6 +47,15 @@ impl BitrateManager {
         }
     }
 
+    // The learned bitrate bound is discarded, since it depends on the previous decoder latency
+    // fixer config
+    pub fn update_config(&mut self, config: BitrateConfig) {
+        self.config = config;
+        self.dynamic_max_bitrate = f32::MAX;
+        self.decoder_latency_overstep_count = 0;
+        self.update_needed = true;
+    }
+
     // Note: This is used to calculate the framerate/frame interval. The frame present is the most
     // accurate event for this use.
     pub fn report_frame_present(&mut self) {
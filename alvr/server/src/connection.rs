@@ -3,14 +3,17 @@ use crate::{
     buttons::BUTTON_PATH_FROM_ID,
     face_tracking::FaceTrackingSink,
     haptics::HapticsManager,
+    logging_backend,
     sockets::WelcomeSocket,
     statistics::StatisticsManager,
+    statistics_recorder::StatisticsRecorder,
     tracking::{self, TrackingManager},
-    FfiButtonValue, FfiFov, FfiViewsConfig, VideoPacket, BITRATE_MANAGER, CONTROL_CHANNEL_SENDER,
-    DECODER_CONFIG, DISCONNECT_CLIENT_NOTIFIER, HAPTICS_SENDER, IS_ALIVE, RESTART_NOTIFIER,
-    SERVER_DATA_MANAGER, STATISTICS_MANAGER, VIDEO_RECORDING_FILE, VIDEO_SENDER,
+    FfiButtonValue, FfiFov, FfiViewsConfig, VideoPacket, AUDIO_DEVICE_CHANGES, BITRATE_MANAGER,
+    CONTROL_CHANNEL_SENDER, DECODER_CONFIG, DISCONNECT_CLIENT_NOTIFIER, HAPTICS_SENDER, IS_ALIVE,
+    METRICS, RESTART_NOTIFIER, SERVER_DATA_MANAGER, SETTINGS_CHANGE_NOTIFIER, STATISTICS_MANAGER,
+    STATISTICS_RECORDER, VIDEO_RECORDING_FILE, VIDEO_SENDER,
 };
-use alvr_audio::AudioDevice;
+use alvr_audio::{AudioDevice, AudioDeviceChange};
 use alvr_common::{
     glam::{UVec2, Vec2},
     once_cell::sync::Lazy,
@@ -21,15 +24,20 @@ use alvr_common::{
 };
 use alvr_events::{ButtonEvent, EventType, HapticsEvent, TrackingEvent};
 use alvr_packets::{
-    ButtonValue, ClientConnectionResult, ClientControlPacket, ClientListAction, ClientStatistics,
-    ServerControlPacket, StreamConfigPacket, Tracking, AUDIO, HAPTICS, STATISTICS, TRACKING, VIDEO,
+    AudioStreamFormat, ButtonValue, ClientConnectionResult, ClientControlPacket, ClientListAction,
+    ClientStatistics, ServerControlPacket, StreamConfigPacket, Tracking, AUDIO, HAPTICS,
+    STATISTICS, TRACKING, VIDEO,
+};
+use alvr_session::{
+    AudioCodecConfig, CodecType, ControllersEmulationMode, FrameSize, HapticsConfig, HeadsetDesc,
+    OpenvrConfig, SurroundAudioMode,
 };
-use alvr_session::{CodecType, ControllersEmulationMode, FrameSize, OpenvrConfig};
 use alvr_sockets::{
     spawn_cancelable, ControlSocketReceiver, ControlSocketSender, PeerType, ProtoControlSocket,
     StreamSocketBuilder, KEEPALIVE_INTERVAL,
 };
 use futures::future::BoxFuture;
+use serde::Serialize;
 use std::{
     collections::{HashMap, HashSet},
     future,
@@ -42,7 +50,7 @@ use std::{
 };
 use tokio::{
     runtime::Runtime,
-    sync::{mpsc as tmpsc, Mutex},
+    sync::{broadcast, mpsc as tmpsc, Mutex},
     time,
 };
 
@@ -57,6 +65,21 @@ fn align32(value: f32) -> u32 {
     ((value / 32.).floor() * 32.) as u32
 }
 
+fn haptics_config(settings: &HeadsetDesc) -> Option<&HapticsConfig> {
+    settings
+        .controllers
+        .as_option()
+        .and_then(|c| c.haptics.as_option())
+}
+
+fn create_haptics_manager(settings: &HeadsetDesc) -> Option<HapticsManager> {
+    haptics_config(settings).cloned().map(HapticsManager::new)
+}
+
+fn changed<T: Serialize>(old: &T, new: &T) -> bool {
+    serde_json::to_value(old).ok() != serde_json::to_value(new).ok()
+}
+
 // Alternate connection trials with manual IPs and clients discovered on the local network
 pub fn handshake_loop() -> IntResult {
     let mut welcome_socket = WelcomeSocket::new().map_err(to_int_e!())?;
@@ -244,33 +267,82 @@ fn try_connect(mut client_ips: HashMap<IpAddr, String>) -> IntResult {
         warn!("Chosen refresh rate not supported. Using {fps}Hz");
     }
 
-    let game_audio_sample_rate = if let Switch::Enabled(game_audio_config) =
-        settings.audio.game_audio
-    {
-        let game_audio_device = AudioDevice::new_output(
-            Some(settings.audio.linux_backend),
-            game_audio_config.device.as_ref(),
-        )
-        .map_err(to_int_e!())?;
-
-        #[cfg(not(target_os = "linux"))]
-        if let Switch::Enabled(microphone_desc) = settings.audio.microphone {
-            let (sink, source) = AudioDevice::new_virtual_microphone_pair(
-                Some(settings.audio.linux_backend),
-                microphone_desc.devices,
+    let game_audio_device_format =
+        if let Switch::Enabled(game_audio_config) = settings.audio.game_audio {
+            // With PipeWire, the virtual sink is created later with this format
+            #[cfg(all(target_os = "linux", feature = "pipewire"))]
+            let pipewire_sink_format = matches!(
+                settings.audio.linux_backend,
+                alvr_session::LinuxAudioBackend::PipeWire
             )
-            .map_err(to_int_e!())?;
-            if alvr_audio::is_same_device(&game_audio_device, &sink)
-                || alvr_audio::is_same_device(&game_audio_device, &source)
-            {
-                return int_fmt_e!("Game audio and microphone cannot point to the same device!");
+            .then(|| alvr_audio::linux::virtual_sink_format(&game_audio_config.surround));
+            #[cfg(not(all(target_os = "linux", feature = "pipewire")))]
+            let pipewire_sink_format = None;
+
+            if let Some(format) = pipewire_sink_format {
+                format
+            } else {
+                let game_audio_device = AudioDevice::new_output(
+                    Some(settings.audio.linux_backend),
+                    game_audio_config.device.as_ref(),
+                )
+                .map_err(to_int_e!())?;
+
+                #[cfg(not(target_os = "linux"))]
+                if let Switch::Enabled(microphone_desc) = settings.audio.microphone {
+                    let (sink, source) = AudioDevice::new_virtual_microphone_pair(
+                        Some(settings.audio.linux_backend),
+                        microphone_desc.devices,
+                    )
+                    .map_err(to_int_e!())?;
+                    if alvr_audio::is_same_device(&game_audio_device, &sink)
+                        || alvr_audio::is_same_device(&game_audio_device, &source)
+                    {
+                        return int_fmt_e!(
+                            "Game audio and microphone cannot point to the same device!"
+                        );
+                    }
+                }
+
+                game_audio_device.input_format().map_err(to_int_e!())?
             }
-        }
+        } else {
+            AudioStreamFormat {
+                sample_rate: 0,
+                channels_count: 2,
+            }
+        };
 
-        game_audio_device.input_sample_rate().map_err(to_int_e!())?
+    let (game_audio_codec, game_audio_channels_count) =
+        if let Switch::Enabled(config) = &settings.audio.game_audio {
+            (
+                config.codec.clone(),
+                alvr_audio::negotiate_game_audio_channels(
+                    &config.surround,
+                    &config.codec,
+                    game_audio_device_format.channels_count,
+                    streaming_caps.game_audio_channels_count,
+                ),
+            )
+        } else {
+            (AudioCodecConfig::Pcm, 2)
+        };
+    let game_audio_format = alvr_audio::negotiate_stream_format(
+        &game_audio_codec,
+        game_audio_device_format.sample_rate,
+        game_audio_channels_count,
+    );
+
+    let microphone_codec = if let Switch::Enabled(config) = &settings.audio.microphone {
+        config.codec.clone()
     } else {
-        0
+        AudioCodecConfig::Pcm
     };
+    let microphone_format = alvr_audio::negotiate_stream_format(
+        &microphone_codec,
+        streaming_caps.microphone_sample_rate,
+        1,
+    );
 
     let client_config = StreamConfigPacket {
         session_desc: {
@@ -279,7 +351,10 @@ fn try_connect(mut client_ips: HashMap<IpAddr, String>) -> IntResult {
         },
         view_resolution: stream_view_resolution,
         fps,
-        game_audio_sample_rate,
+        game_audio_format,
+        game_audio_codec: game_audio_codec.clone(),
+        microphone_format,
+        microphone_codec: microphone_codec.clone(),
     };
     runtime
         .block_on(proto_socket.send(&client_config))
@@ -440,6 +515,8 @@ fn try_connect(mut client_ips: HashMap<IpAddr, String>) -> IntResult {
 
     *STREAMING_CLIENT_HOSTNAME.lock() = Some(client_hostname.clone());
 
+    logging_backend::start_stream_log(&client_hostname);
+
     thread::spawn(move || {
         runtime.block_on({
             let client_hostname = client_hostname.clone();
@@ -457,7 +534,10 @@ fn try_connect(mut client_ips: HashMap<IpAddr, String>) -> IntResult {
                         client_ip,
                         control_sender,
                         control_receiver,
-                        streaming_caps.microphone_sample_rate,
+                        game_audio_format,
+                        game_audio_codec,
+                        microphone_format,
+                        microphone_codec,
                         fps,
                     ) => {
                         warn!("Connection interrupted: {res:?}");
@@ -478,6 +558,8 @@ fn try_connect(mut client_ips: HashMap<IpAddr, String>) -> IntResult {
         }
 
         CONNECTED_CLIENT_HOSTNAMES.lock().remove(&client_hostname);
+
+        logging_backend::end_stream_log();
     });
 
     Ok(())
@@ -490,7 +572,10 @@ impl Drop for StreamCloseGuard {
     fn drop(&mut self) {
         self.0.set(false);
 
+        METRICS.lock().report_connection_state(false);
+
         *VIDEO_RECORDING_FILE.lock() = None;
+        *STATISTICS_RECORDER.lock() = None;
 
         unsafe { crate::DeinitializeStreaming() };
 
@@ -517,7 +602,10 @@ async fn connection_pipeline(
     client_ip: IpAddr,
     control_sender: ControlSocketSender<ServerControlPacket>,
     mut control_receiver: ControlSocketReceiver<ClientControlPacket>,
-    microphone_sample_rate: u32,
+    game_audio_format: AudioStreamFormat,
+    game_audio_codec: AudioCodecConfig,
+    microphone_format: AudioStreamFormat,
+    microphone_codec: AudioCodecConfig,
     refresh_rate: f32,
 ) -> StrResult {
     let control_sender = Arc::new(Mutex::new(control_sender));
@@ -565,6 +653,17 @@ async fn connection_pipeline(
         },
     ));
 
+    *STATISTICS_RECORDER.lock() = if settings.logging.record_statistics {
+        StatisticsRecorder::new(
+            &client_hostname,
+            Duration::from_secs_f32(1.0 / refresh_rate),
+        )
+        .map_err(|e| warn!("Failed to start statistics recording: {e}"))
+        .ok()
+    } else {
+        None
+    };
+
     *BITRATE_MANAGER.lock() = BitrateManager::new(
         settings.video.bitrate,
         settings.connection.statistics_history_size as _,
@@ -594,10 +693,35 @@ async fn connection_pipeline(
     let is_streaming = Arc::new(RelaxedAtomic::new(true));
     let _stream_guard = StreamCloseGuard(Arc::clone(&is_streaming));
 
+    METRICS.lock().report_connection_state(true);
+
     let game_audio_loop: BoxFuture<_> = if let Switch::Enabled(config) = settings.audio.game_audio {
         let sender = stream_socket.request_stream(AUDIO).await?;
         Box::pin(async move {
+            let mut device_changes = AUDIO_DEVICE_CHANGES.subscribe();
+
             loop {
+                #[cfg(all(target_os = "linux", feature = "pipewire"))]
+                if matches!(
+                    settings.audio.linux_backend,
+                    alvr_session::LinuxAudioBackend::PipeWire
+                ) {
+                    if let Err(e) = alvr_audio::linux::record_audio_loop(
+                        alvr_audio::linux::virtual_sink_format(&config.surround),
+                        game_audio_format,
+                        config.surround != SurroundAudioMode::StereoDownmix,
+                        game_audio_codec.clone(),
+                        sender.clone(),
+                    )
+                    .await
+                    {
+                        warn!("Audio task exit with error : {e}");
+                    }
+
+                    time::sleep(RETRY_CONNECT_MIN_INTERVAL).await;
+                    continue;
+                }
+
                 let device = match AudioDevice::new_output(
                     Some(settings.audio.linux_backend),
                     config.device.as_ref(),
@@ -605,11 +729,20 @@ async fn connection_pipeline(
                     Ok(data) => data,
                     Err(e) => {
                         warn!("New audio device Failed : {e}");
-                        time::sleep(RETRY_CONNECT_MIN_INTERVAL).await;
+
+                        // Retry once the device is plugged, or after a while if the devices
+                        // cannot be watched
+                        tokio::select! {
+                            _ = device_changes.recv() => (),
+                            _ = time::sleep(RETRY_CONNECT_MIN_INTERVAL) => (),
+                        }
+
                         continue;
                     }
                 };
+                let device_id = device.id().to_owned();
                 let mute_when_streaming = config.mute_when_streaming;
+                let binaural_downmix = config.surround != SurroundAudioMode::StereoDownmix;
 
                 #[cfg(windows)]
                 if let Ok(id) = alvr_audio::get_windows_device_id(&device) {
@@ -626,11 +759,52 @@ async fn connection_pipeline(
                     continue;
                 };
 
+                // The capture is restarted if the device is unplugged or, when no device is
+                // selected, if the default device changes
+                let device_changed = async {
+                    loop {
+                        match device_changes.recv().await {
+                            Ok(changes) => {
+                                for change in changes {
+                                    match change {
+                                        AudioDeviceChange::Removed { device, .. }
+                                            if device.id == device_id =>
+                                        {
+                                            return;
+                                        }
+                                        AudioDeviceChange::DefaultOutputChanged(_)
+                                            if config.device.is_none() =>
+                                        {
+                                            return;
+                                        }
+                                        _ => (),
+                                    }
+                                }
+                            }
+                            // Some changes were missed, the device could have been removed
+                            Err(broadcast::error::RecvError::Lagged(_)) => return,
+                            Err(broadcast::error::RecvError::Closed) => {
+                                return future::pending().await
+                            }
+                        }
+                    }
+                };
+
                 let new_sender = sender.clone();
-                if let Err(e) =
-                    alvr_audio::record_audio_loop(device, 2, mute_when_streaming, new_sender).await
-                {
-                    warn!("Audio task exit with error : {e}")
+                tokio::select! {
+                    res = alvr_audio::record_audio_loop(
+                        device,
+                        game_audio_format,
+                        mute_when_streaming,
+                        binaural_downmix,
+                        game_audio_codec.clone(),
+                        new_sender,
+                    ) => {
+                        if let Err(e) = res {
+                            warn!("Audio task exit with error : {e}")
+                        }
+                    }
+                    _ = device_changed => info!("Game audio device changed, restarting capture"),
                 }
 
                 #[cfg(windows)]
@@ -652,36 +826,54 @@ async fn connection_pipeline(
     } else {
         Box::pin(future::pending())
     };
-    let microphone_loop: BoxFuture<_> = if let Switch::Enabled(config) = settings.audio.microphone {
-        #[allow(unused_variables)]
-        let (sink, source) = AudioDevice::new_virtual_microphone_pair(
-            Some(settings.audio.linux_backend),
-            config.devices,
-        )?;
-        let receiver = stream_socket.subscribe_to_stream(AUDIO).await?;
-
-        #[cfg(windows)]
-        if let Ok(id) = alvr_audio::get_windows_device_id(&source) {
-            unsafe {
-                crate::SetOpenvrProperty(
-                    *alvr_common::HEAD_ID,
-                    crate::openvr_props::to_ffi_openvr_prop(
-                        alvr_session::OpenvrPropertyKey::AudioDefaultRecordingDeviceId,
-                        alvr_session::OpenvrPropValue::String(id),
-                    ),
-                )
-            }
+    let microphone_loop: BoxFuture<_> = match settings.audio.microphone {
+        #[cfg(all(target_os = "linux", feature = "pipewire"))]
+        Switch::Enabled(config)
+            if matches!(
+                settings.audio.linux_backend,
+                alvr_session::LinuxAudioBackend::PipeWire
+            ) =>
+        {
+            let receiver = stream_socket.subscribe_to_stream(AUDIO).await?;
+
+            Box::pin(alvr_audio::linux::play_audio_loop(
+                microphone_format,
+                config.buffering,
+                microphone_codec,
+                receiver,
+            ))
         }
+        Switch::Enabled(config) => {
+            #[allow(unused_variables)]
+            let (sink, source) = AudioDevice::new_virtual_microphone_pair(
+                Some(settings.audio.linux_backend),
+                config.devices,
+            )?;
+            let receiver = stream_socket.subscribe_to_stream(AUDIO).await?;
+
+            #[cfg(windows)]
+            if let Ok(id) = alvr_audio::get_windows_device_id(&source) {
+                unsafe {
+                    crate::SetOpenvrProperty(
+                        *alvr_common::HEAD_ID,
+                        crate::openvr_props::to_ffi_openvr_prop(
+                            alvr_session::OpenvrPropertyKey::AudioDefaultRecordingDeviceId,
+                            alvr_session::OpenvrPropValue::String(id),
+                        ),
+                    )
+                }
+            }
 
-        Box::pin(alvr_audio::play_audio_loop(
-            sink,
-            1,
-            microphone_sample_rate,
-            config.buffering,
-            receiver,
-        ))
-    } else {
-        Box::pin(future::pending())
+            Box::pin(alvr_audio::play_audio_loop(
+                sink,
+                microphone_format,
+                config.buffering,
+                microphone_codec,
+                false,
+                receiver,
+            ))
+        }
+        Switch::Disabled => Box::pin(future::pending()),
     };
 
     let video_send_loop = {
@@ -693,24 +885,25 @@ async fn connection_pipeline(
 
             while let Some(VideoPacket { header, payload }) = data_receiver.recv().await {
                 socket_sender.send(&header, payload).await.ok();
+
+                if let Some(stats) = &mut *STATISTICS_MANAGER.lock() {
+                    stats.report_frame_sent(header.timestamp);
+                }
             }
 
             Ok(())
         }
     };
 
+    let haptics_manager = Arc::new(Mutex::new(create_haptics_manager(&settings.headset)));
+
     let haptics_send_loop = {
         let mut socket_sender = stream_socket.request_stream(HAPTICS).await?;
-        let controllers_desc = settings.headset.controllers.clone();
+        let haptics_manager = Arc::clone(&haptics_manager);
         async move {
             let (data_sender, mut data_receiver) = tmpsc::unbounded_channel();
             *HAPTICS_SENDER.lock() = Some(data_sender);
 
-            let haptics_manager = controllers_desc
-                .into_option()
-                .and_then(|c| c.haptics.into_option())
-                .map(HapticsManager::new);
-
             while let Some(haptics) = data_receiver.recv().await {
                 if settings.logging.log_haptics {
                     alvr_events::send_event(EventType::Haptics(HapticsEvent {
@@ -724,7 +917,7 @@ async fn connection_pipeline(
                     }))
                 }
 
-                if let Some(manager) = &haptics_manager {
+                if let Some(manager) = &*haptics_manager.lock().await {
                     socket_sender.send(&manager.map(haptics), vec![]).await.ok();
                 }
             }
@@ -751,30 +944,40 @@ async fn connection_pipeline(
 
     let tracking_manager = Arc::new(Mutex::new(TrackingManager::new(&settings.headset)));
 
+    let face_tracking_sink = Arc::new(Mutex::new(
+        if let Switch::Enabled(config) = &settings.headset.face_tracking {
+            Some(FaceTrackingSink::new(
+                config.sink.clone(),
+                settings.connection.osc_local_port,
+            )?)
+        } else {
+            None
+        },
+    ));
+
     let tracking_receive_loop = {
         let mut receiver = stream_socket
             .subscribe_to_stream::<Tracking>(TRACKING)
             .await?;
         let tracking_manager = Arc::clone(&tracking_manager);
+        let face_tracking_sink = Arc::clone(&face_tracking_sink);
         async move {
-            let face_tracking_sink = if let Switch::Enabled(config) = settings.headset.face_tracking
-            {
-                Some(FaceTrackingSink::new(
-                    config.sink,
-                    settings.connection.osc_local_port,
-                )?)
-            } else {
-                None
-            };
-
             let mut track_controllers = 0u32;
             if let Switch::Enabled(config) = settings.headset.controllers {
                 track_controllers = config.tracked.into();
             }
+            let pose_prediction_enabled =
+                matches!(settings.headset.pose_prediction, Switch::Enabled(_));
 
             loop {
                 let tracking = receiver.recv_header_only().await?;
 
+                let prediction = STATISTICS_MANAGER
+                    .lock()
+                    .as_ref()
+                    .map(|stats| stats.pose_prediction_interval())
+                    .unwrap_or_default();
+
                 let mut tracking_manager_lock = tracking_manager.lock().await;
 
                 let motions = tracking_manager_lock.transform_motions(
@@ -783,6 +986,8 @@ async fn connection_pipeline(
                         tracking.hand_skeletons[0].is_some(),
                         tracking.hand_skeletons[1].is_some(),
                     ],
+                    tracking.target_timestamp,
+                    prediction,
                 );
 
                 let left_hand_skeleton = tracking.hand_skeletons[0]
@@ -800,6 +1005,7 @@ async fn connection_pipeline(
 
                 if settings.logging.log_tracking {
                     alvr_events::send_event(EventType::Tracking(Box::new(TrackingEvent {
+                        target_timestamp: tracking.target_timestamp,
                         head_motion: motions
                             .iter()
                             .find(|(id, _)| *id == *HEAD_ID)
@@ -822,7 +1028,7 @@ async fn connection_pipeline(
                     })))
                 }
 
-                if let Some(sink) = &face_tracking_sink {
+                if let Some(sink) = &*face_tracking_sink.lock().await {
                     let mut face_data = tracking.face_data;
                     face_data.eye_gazes = local_eye_gazes;
 
@@ -841,10 +1047,16 @@ async fn connection_pipeline(
                 if let Some(stats) = &mut *STATISTICS_MANAGER.lock() {
                     stats.report_tracking_received(tracking.target_timestamp);
 
+                    let tracker_pose_time_offset = if pose_prediction_enabled {
+                        stats.measured_tracker_pose_time_offset()
+                    } else {
+                        stats.tracker_pose_time_offset()
+                    };
+
                     unsafe {
                         crate::SetTracking(
                             tracking.target_timestamp.as_nanos() as _,
-                            stats.tracker_pose_time_offset().as_secs_f32(),
+                            tracker_pose_time_offset.as_secs_f32(),
                             ffi_motions.as_ptr(),
                             ffi_motions.len() as _,
                             if let Some(skeleton) = &ffi_left_hand_skeleton {
@@ -865,6 +1077,93 @@ async fn connection_pipeline(
         }
     };
 
+    // Apply settings that do not require a SteamVR restart. Settings used by the client are
+    // forwarded and it is up to the client to apply them. Only the subsystems affected by the
+    // change are rebuilt, so their state (bitrate history, motion filters, etc) is kept.
+    let settings_update_loop = {
+        let tracking_manager = Arc::clone(&tracking_manager);
+        let initial_settings = settings.clone();
+        async move {
+            let mut applied_settings = initial_settings.clone();
+            loop {
+                SETTINGS_CHANGE_NOTIFIER.notified().await;
+
+                let (new_settings, session_desc) = {
+                    let data_manager_lock = SERVER_DATA_MANAGER.read();
+                    (
+                        data_manager_lock.settings().clone(),
+                        serde_json::to_string(data_manager_lock.session()).map_err(err!())?,
+                    )
+                };
+                let settings = alvr_session::settings_without_restart_changes(
+                    &initial_settings,
+                    &new_settings,
+                );
+
+                if !changed(&applied_settings, &settings) {
+                    continue;
+                }
+
+                let old = &applied_settings;
+
+                if changed(&old.video.bitrate, &settings.video.bitrate) {
+                    BITRATE_MANAGER
+                        .lock()
+                        .update_config(settings.video.bitrate.clone());
+                }
+
+                if changed(
+                    &haptics_config(&old.headset),
+                    &haptics_config(&settings.headset),
+                ) {
+                    *haptics_manager.lock().await = create_haptics_manager(&settings.headset);
+                }
+
+                if changed(&old.headset.controllers, &settings.headset.controllers)
+                    || changed(
+                        &old.headset.device_motions,
+                        &settings.headset.device_motions,
+                    )
+                    || settings.headset.position_recentering_mode
+                        != old.headset.position_recentering_mode
+                    || settings.headset.rotation_recentering_mode
+                        != old.headset.rotation_recentering_mode
+                {
+                    tracking_manager
+                        .lock()
+                        .await
+                        .update_settings(&settings.headset);
+                }
+
+                if changed(&old.headset.face_tracking, &settings.headset.face_tracking)
+                    || settings.connection.osc_local_port != old.connection.osc_local_port
+                {
+                    let mut sink_lock = face_tracking_sink.lock().await;
+
+                    // Release the OSC port before binding it again
+                    *sink_lock = None;
+                    if let Switch::Enabled(config) = settings.headset.face_tracking.clone() {
+                        match FaceTrackingSink::new(config.sink, settings.connection.osc_local_port)
+                        {
+                            Ok(sink) => *sink_lock = Some(sink),
+                            Err(e) => warn!("Failed to recreate face tracking sink: {e}"),
+                        }
+                    }
+                }
+
+                if let Some(sender) = &*CONTROL_CHANNEL_SENDER.lock() {
+                    sender
+                        .send(ServerControlPacket::UpdateSettings(session_desc))
+                        .ok();
+                }
+
+                applied_settings = settings;
+
+                info!("Settings applied to the running stream");
+            }
+        }
+    };
+
     let statistics_receive_loop = {
         let mut receiver = stream_socket
             .subscribe_to_stream::<ClientStatistics>(STATISTICS)
@@ -972,6 +1271,11 @@ async fn connection_pipeline(
                         stats.report_battery(packet.device_id, packet.gauge_value);
                     }
                 },
+                Ok(ClientControlPacket::Health(health)) => {
+                    if let Some(stats) = &mut *STATISTICS_MANAGER.lock() {
+                        stats.report_client_health(health);
+                    }
+                }
                 Ok(ClientControlPacket::Buttons(entries)) => {
                     if settings.logging.log_button_presses {
                         alvr_events::send_event(EventType::Buttons(
@@ -1041,6 +1345,7 @@ async fn connection_pipeline(
         res = spawn_cancelable(statistics_receive_loop) => res,
         res = spawn_cancelable(haptics_send_loop) => res,
         res = spawn_cancelable(tracking_receive_loop) => res,
+        res = spawn_cancelable(settings_update_loop) => res,
 
         // Leave these loops on the current task
         res = keepalive_loop => res,
@@ -1059,3 +1364,36 @@ async fn connection_pipeline(
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alvr_packets::Haptics;
+    use alvr_session::SessionDesc;
+
+    #[test]
+    fn haptics_changes_rebuild_manager() {
+        let current = SessionDesc::default().to_settings();
+
+        let mut new_json = serde_json::to_value(&current).unwrap();
+        new_json["headset"]["controllers"]["Enabled"]["haptics"]["Enabled"]
+            ["intensity_multiplier"] = serde_json::json!(2.0);
+        let new = serde_json::from_value(new_json).unwrap();
+
+        let applied = alvr_session::settings_without_restart_changes(&current, &new);
+        assert!(changed(
+            &haptics_config(&current.headset),
+            &haptics_config(&applied.headset)
+        ));
+
+        let haptics = create_haptics_manager(&applied.headset)
+            .unwrap()
+            .map(Haptics {
+                device_id: 0,
+                duration: Duration::from_millis(100),
+                frequency: 0.0,
+                amplitude: 1.0,
+            });
+        assert_eq!(haptics.amplitude, 2.0);
+    }
+}
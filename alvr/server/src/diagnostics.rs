@@ -0,0 +1,64 @@
+use crate::{FILESYSTEM_LAYOUT, SERVER_DATA_MANAGER};
+use alvr_common::{once_cell::sync::Lazy, parking_lot::Mutex, prelude::*};
+use alvr_events::{Event, EventType};
+use std::{
+    collections::VecDeque,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+use tokio::sync::broadcast::error::RecvError;
+
+// Statistics older than this are not included in diagnostics bundles
+const STATISTICS_HISTORY_DURATION: Duration = Duration::from_secs(10 * 60);
+
+// Kept across connections, so a bundle created right after a disconnection still has them
+static STATISTICS_HISTORY: Lazy<Mutex<VecDeque<(Instant, Event)>>> =
+    Lazy::new(|| Mutex::new(VecDeque::new()));
+
+pub async fn record_statistics_history() {
+    let mut events_receiver = alvr_events::subscribe();
+
+    loop {
+        let event = match events_receiver.recv().await {
+            Ok(event) => event,
+            Err(RecvError::Lagged(_)) => continue,
+            Err(RecvError::Closed) => return,
+        };
+
+        if matches!(event.event_type, EventType::Statistics(_)) {
+            let now = Instant::now();
+
+            let mut history_lock = STATISTICS_HISTORY.lock();
+            history_lock.push_back((now, event));
+            while history_lock
+                .front()
+                .map(|(instant, _)| *instant + STATISTICS_HISTORY_DURATION < now)
+                .unwrap_or(false)
+            {
+                history_lock.pop_front();
+            }
+        }
+    }
+}
+
+pub fn create_diagnostics_bundle() -> StrResult<Vec<u8>> {
+    let statistics_history = STATISTICS_HISTORY
+        .lock()
+        .iter()
+        .map(|(_, event)| event.clone())
+        .collect::<Vec<_>>();
+
+    alvr_server_io::create_diagnostics_bundle(
+        &SERVER_DATA_MANAGER.read(),
+        &FILESYSTEM_LAYOUT.logs_dir(),
+        &FILESYSTEM_LAYOUT.crash_log(),
+        &statistics_history,
+    )
+}
+
+pub fn save_diagnostics_bundle() -> StrResult<PathBuf> {
+    alvr_server_io::save_diagnostics_bundle(
+        &create_diagnostics_bundle()?,
+        &FILESYSTEM_LAYOUT.diagnostics_bundles_dir(),
+    )
+}
@@ -0,0 +1,169 @@
+use crate::{statistics::HistoryFrame, FILESYSTEM_LAYOUT};
+use alvr_common::prelude::*;
+use alvr_packets::ClientStatistics;
+use chrono::Local;
+use serde_json as json;
+use std::{
+    collections::VecDeque,
+    fs,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+// About 2 minutes at 90 fps. Later frames are ignored
+const MAX_TRACED_FRAMES: usize = 10_000;
+
+const SERVER_PID: u32 = 1;
+const NETWORK_PID: u32 = 2;
+const CLIENT_PID: u32 = 3;
+
+// The clock offset is taken from the frame with the shortest round trip among the latest ones, as
+// done by NTP, since queuing delays are rarely symmetric
+const CLOCK_SAMPLES: usize = 64;
+
+// (pid, tid, name). Every stage gets its own track, since stages of consecutive frames overlap
+const TRACKS: [(u32, u32, &str); 10] = [
+    (SERVER_PID, 0, "Game"),
+    (SERVER_PID, 1, "Compositor"),
+    (SERVER_PID, 2, "Encoder"),
+    (SERVER_PID, 3, "Send"),
+    (NETWORK_PID, 4, "Tracking"),
+    (NETWORK_PID, 5, "Video"),
+    (CLIENT_PID, 6, "Decoder"),
+    (CLIENT_PID, 7, "Decoder queue"),
+    (CLIENT_PID, 8, "Rendering"),
+    (CLIENT_PID, 9, "VSync queue"),
+];
+
+// Records the timeline of every frame on both sides, in the Chrome JSON trace format. The trace
+// can be opened with Perfetto (ui.perfetto.dev) or chrome://tracing
+pub struct FrameTracer {
+    start_instant: Instant,
+    trace_events: Vec<json::Value>,
+    traced_frames: usize,
+    // (round trip, streamer instant at the start of the client clock)
+    clock_samples: VecDeque<(Duration, Instant)>,
+}
+
+impl FrameTracer {
+    pub fn new() -> Self {
+        let mut trace_events = vec![];
+        for (pid, name) in [
+            (SERVER_PID, "Streamer"),
+            (NETWORK_PID, "Network"),
+            (CLIENT_PID, "Client"),
+        ] {
+            trace_events.push(json::json!({
+                "name": "process_name", "ph": "M", "pid": pid, "args": { "name": name }
+            }));
+        }
+        for (pid, tid, name) in TRACKS {
+            trace_events.push(json::json!({
+                "name": "thread_name", "ph": "M", "pid": pid, "tid": tid, "args": { "name": name }
+            }));
+        }
+
+        Self {
+            start_instant: Instant::now(),
+            trace_events,
+            traced_frames: 0,
+            clock_samples: VecDeque::new(),
+        }
+    }
+
+    // Client timestamps are converted to the streamer clock with the NTP estimation: the tracking
+    // travels from the client (input acquired) to the streamer (tracking received) and the video
+    // travels back (video sent, video received), both directions are assumed to take the same time
+    fn client_clock_start(
+        &mut self,
+        tracking_received: Instant,
+        video_sent: Instant,
+        client_stats: &ClientStatistics,
+    ) -> Option<Instant> {
+        // Start of the client clock, if the upstream or the downstream latency were zero
+        let upstream_bound = tracking_received.checked_sub(client_stats.input_acquired)?;
+        let downstream_bound = video_sent.checked_sub(client_stats.video_packet_received)?;
+
+        let round_trip = upstream_bound.saturating_duration_since(downstream_bound);
+        self.clock_samples
+            .push_back((round_trip, downstream_bound + round_trip / 2));
+        if self.clock_samples.len() > CLOCK_SAMPLES {
+            self.clock_samples.pop_front();
+        }
+
+        self.clock_samples
+            .iter()
+            .min_by_key(|(round_trip, _)| *round_trip)
+            .map(|(_, clock_start)| *clock_start)
+    }
+
+    pub fn add_frame(&mut self, frame: &HistoryFrame, client_stats: &ClientStatistics) {
+        if self.traced_frames >= MAX_TRACED_FRAMES {
+            return;
+        }
+
+        let video_sent = Instant::max(frame.frame_sent, frame.frame_encoded);
+
+        let Some(client_clock_start) =
+            self.client_clock_start(frame.tracking_received, video_sent, client_stats)
+        else {
+            return;
+        };
+        self.traced_frames += 1;
+
+        let input_acquired = client_clock_start + client_stats.input_acquired;
+        let video_received = client_clock_start + client_stats.video_packet_received;
+        let decoded = video_received + client_stats.video_decode;
+        let compositor_start = decoded + client_stats.video_decoder_queue;
+        let submitted = compositor_start + client_stats.rendering;
+        let vsync = submitted + client_stats.vsync_queue;
+
+        let spans = [
+            (frame.tracking_received, frame.frame_present),
+            (frame.frame_present, frame.frame_composed),
+            (frame.frame_composed, frame.frame_encoded),
+            (frame.frame_encoded, video_sent),
+            (input_acquired, frame.tracking_received),
+            (video_sent, video_received),
+            (video_received, decoded),
+            (decoded, compositor_start),
+            (compositor_start, submitted),
+            (submitted, vsync),
+        ];
+
+        let target_timestamp_ns = frame.target_timestamp.as_nanos() as u64;
+        for ((pid, tid, name), (begin, end)) in TRACKS.into_iter().zip(spans) {
+            self.trace_events.push(json::json!({
+                "name": name,
+                "ph": "X",
+                "pid": pid,
+                "tid": tid,
+                "ts": self.micros_since_start(begin),
+                "dur": end.saturating_duration_since(begin).as_secs_f64() * 1e6,
+                "args": { "target_timestamp_ns": target_timestamp_ns },
+            }));
+        }
+    }
+
+    fn micros_since_start(&self, instant: Instant) -> f64 {
+        instant
+            .saturating_duration_since(self.start_instant)
+            .as_secs_f64()
+            * 1e6
+    }
+
+    pub fn save(&self) -> StrResult<PathBuf> {
+        let path = FILESYSTEM_LAYOUT.log_dir.join(format!(
+            "frame_trace_{}.json",
+            Local::now().format("%Y-%m-%d_%H-%M-%S")
+        ));
+
+        let trace = json::json!({
+            "traceEvents": self.trace_events,
+            "displayTimeUnit": "ms",
+        });
+        fs::write(&path, json::to_string(&trace).map_err(err!())?).map_err(err!())?;
+
+        Ok(path)
+    }
+}
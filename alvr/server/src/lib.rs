@@ -1,12 +1,19 @@
 mod bitrate;
 mod buttons;
 mod connection;
+mod diagnostics;
 mod face_tracking;
+mod frame_tracing;
 mod haptics;
 mod logging_backend;
+mod metrics;
+mod motion_filter;
 mod openvr_props;
+mod prediction;
+mod rest_api;
 mod sockets;
 mod statistics;
+mod statistics_recorder;
 mod tracking;
 mod web_server;
 
@@ -22,6 +29,7 @@ mod bindings {
 }
 use bindings::*;
 
+use alvr_audio::AudioDeviceChange;
 use alvr_common::{
     glam::Quat,
     log,
@@ -38,7 +46,10 @@ use alvr_packets::{
 use alvr_server_io::ServerDataManager;
 use alvr_session::CodecType;
 use bitrate::BitrateManager;
+use frame_tracing::FrameTracer;
+use metrics::MetricsRecorder;
 use statistics::StatisticsManager;
+use statistics_recorder::StatisticsRecorder;
 use std::{
     collections::HashMap,
     ffi::{c_char, c_void, CStr, CString},
@@ -58,6 +69,8 @@ use tokio::{
     sync::{broadcast, mpsc, Notify},
 };
 
+const SESSION_RELOAD_INTERVAL: Duration = Duration::from_secs(1);
+
 static FILESYSTEM_LAYOUT: Lazy<Layout> = Lazy::new(|| {
     afs::filesystem_layout_from_openvr_driver_root_dir(&alvr_server_io::get_driver_dir().unwrap())
 });
@@ -67,6 +80,9 @@ static WEBSERVER_RUNTIME: Lazy<Mutex<Option<Runtime>>> =
     Lazy::new(|| Mutex::new(Runtime::new().ok()));
 
 static STATISTICS_MANAGER: Lazy<Mutex<Option<StatisticsManager>>> = Lazy::new(|| Mutex::new(None));
+static STATISTICS_RECORDER: Lazy<Mutex<Option<StatisticsRecorder>>> =
+    Lazy::new(|| Mutex::new(None));
+static METRICS: Lazy<Mutex<MetricsRecorder>> = Lazy::new(|| Mutex::new(MetricsRecorder::default()));
 static BITRATE_MANAGER: Lazy<Mutex<BitrateManager>> = Lazy::new(|| {
     let data_lock = SERVER_DATA_MANAGER.read();
     let settings = data_lock.settings();
@@ -91,11 +107,17 @@ static HAPTICS_SENDER: Lazy<Mutex<Option<mpsc::UnboundedSender<Haptics>>>> =
 static VIDEO_MIRROR_SENDER: Lazy<Mutex<Option<broadcast::Sender<Vec<u8>>>>> =
     Lazy::new(|| Mutex::new(None));
 static VIDEO_RECORDING_FILE: Lazy<Mutex<Option<File>>> = Lazy::new(|| Mutex::new(None));
+static FRAME_TRACER: Lazy<Mutex<Option<FrameTracer>>> = Lazy::new(|| Mutex::new(None));
 
 static DISCONNECT_CLIENT_NOTIFIER: Lazy<Notify> = Lazy::new(Notify::new);
 static RESTART_NOTIFIER: Lazy<Notify> = Lazy::new(Notify::new);
 static SHUTDOWN_NOTIFIER: Lazy<Notify> = Lazy::new(Notify::new);
 
+// Audio devices are polled by a single watcher, the changes are shared with the streaming tasks
+static AUDIO_DEVICE_CHANGES: Lazy<broadcast::Sender<Vec<AudioDeviceChange>>> =
+    Lazy::new(|| broadcast::channel(8).0);
+static SETTINGS_CHANGE_NOTIFIER: Lazy<Notify> = Lazy::new(Notify::new);
+
 static FRAME_RENDER_VS_CSO: &[u8] = include_bytes!("../cpp/platform/win32/FrameRenderVS.cso");
 static FRAME_RENDER_PS_CSO: &[u8] = include_bytes!("../cpp/platform/win32/FrameRenderPS.cso");
 static QUAD_SHADER_CSO: &[u8] = include_bytes!("../cpp/platform/win32/QuadVertexShader.cso");
@@ -211,18 +233,62 @@ pub fn notify_restart_driver() {
 }
 
 fn init() {
-    let (events_sender, _) = broadcast::channel(web_server::WS_BROADCAST_CAPACITY);
-    logging_backend::init_logging(events_sender.clone());
+    logging_backend::init_logging();
 
     if let Some(runtime) = WEBSERVER_RUNTIME.lock().as_mut() {
-        runtime.spawn(alvr_common::show_err_async(web_server::web_server(
-            events_sender,
-        )));
+        runtime.spawn(alvr_common::show_err_async(web_server::web_server()));
+
+        // Pick up changes to session.json made by the dashboard
+        runtime.spawn(async {
+            loop {
+                SERVER_DATA_MANAGER.write().reload_if_changed();
+
+                tokio::time::sleep(SESSION_RELOAD_INTERVAL).await;
+            }
+        });
+
+        runtime.spawn(diagnostics::record_statistics_history());
+
+        // Report plugged and unplugged audio devices, so the dashboard lists are up to date
+        runtime.spawn(async {
+            let linux_backend = SERVER_DATA_MANAGER.read().settings().audio.linux_backend;
+            let mut watcher = match alvr_audio::AudioDevicesWatcher::new(Some(linux_backend)) {
+                Ok(watcher) => watcher,
+                Err(e) => {
+                    warn!("Cannot watch audio devices: {e}");
+                    return;
+                }
+            };
+
+            loop {
+                let changes = watcher.changed().await;
+
+                for change in &changes {
+                    match change {
+                        AudioDeviceChange::Added { device, .. } => {
+                            info!("Audio device added: {}", device.name)
+                        }
+                        AudioDeviceChange::Removed { device, .. } => {
+                            info!("Audio device removed: {}", device.name)
+                        }
+                        _ => (),
+                    }
+                }
+
+                alvr_events::send_event(EventType::AudioDevices(watcher.list().clone()));
+
+                // Fails only if there are no streaming tasks listening
+                AUDIO_DEVICE_CHANGES.send(changes).ok();
+            }
+        });
     }
 
     {
         let mut data_manager_lock = SERVER_DATA_MANAGER.write();
 
+        // notify_one() keeps the notification if the loop is busy applying the previous change
+        data_manager_lock.set_settings_change_callback(|| SETTINGS_CHANGE_NOTIFIER.notify_one());
+
         let connections = data_manager_lock.session().client_connections.clone();
         for (hostname, connection) in connections {
             if !connection.trusted {
@@ -441,6 +507,11 @@ pub unsafe extern "C" fn HmdDriverFactory(
     }
 
     extern "C" fn report_present(timestamp_ns: u64, offset_ns: u64) {
+        alvr_audio::report_video_frame_presented(
+            Duration::from_nanos(timestamp_ns),
+            Instant::now() - Duration::from_nanos(offset_ns),
+        );
+
         if let Some(stats) = &mut *STATISTICS_MANAGER.lock() {
             stats.report_frame_present(
                 Duration::from_nanos(timestamp_ns),
@@ -467,7 +538,16 @@ pub unsafe extern "C" fn HmdDriverFactory(
     }
 
     extern "C" fn get_dynamic_encoder_params() -> FfiDynamicEncoderParams {
-        BITRATE_MANAGER.lock().get_encoder_params()
+        let params = BITRATE_MANAGER.lock().get_encoder_params();
+        if params.updated != 0 {
+            METRICS.lock().report_target_bitrate(params.bitrate_bps);
+
+            if let Some(recorder) = &mut *STATISTICS_RECORDER.lock() {
+                recorder.record_target_bitrate(params.bitrate_bps);
+            }
+        }
+
+        params
     }
 
     extern "C" fn wait_for_vsync() {
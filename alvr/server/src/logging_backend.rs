@@ -1,69 +1,201 @@
 use crate::{FILESYSTEM_LAYOUT, SERVER_DATA_MANAGER};
-use alvr_common::{log::LevelFilter, LogEntry, LogSeverity};
+use alvr_common::{log::LevelFilter, once_cell::sync::Lazy, parking_lot::Mutex, prelude::*};
 use alvr_events::{Event, EventType};
+use alvr_session::{LogFileFormat, LogFilesConfig};
 use chrono::Local;
-use fern::Dispatch;
-use std::fs;
-use tokio::sync::broadcast::Sender;
-
-// todo: don't stringify events immediately, use Sender<Event>
-pub fn init_logging(events_sender: Sender<Event>) {
-    let mut log_dispatch = Dispatch::new().format(move |out, message, record| {
-        let maybe_event = format!("{message}");
-        let event_type = if maybe_event.starts_with('{') && maybe_event.ends_with('}') {
-            serde_json::from_str(&maybe_event).unwrap()
-        } else {
-            EventType::Log(LogEntry {
-                severity: LogSeverity::from_log_level(record.level()),
-                content: message.to_string(),
-            })
+use fern::{Dispatch, Output};
+use std::{
+    fs::{self, File},
+    io::{self, Write},
+    thread,
+};
+use tokio::sync::broadcast::{error::RecvError, Receiver};
+
+const STREAMER_LOG_LABEL: &str = "streamer";
+
+// None if logging to disk is disabled
+static LOG_FILE_WRITER: Lazy<Mutex<Option<LogFileWriter>>> = Lazy::new(|| Mutex::new(None));
+
+struct LogFileWriter {
+    config: LogFilesConfig,
+    label: String,
+    file: File,
+    size_bytes: u64,
+}
+
+impl LogFileWriter {
+    // File names start with the creation time, followed by "streamer" or the client hostname
+    fn open(config: LogFilesConfig, label: &str) -> StrResult<Self> {
+        let dir = FILESYSTEM_LAYOUT.logs_dir();
+        fs::create_dir_all(&dir).map_err(err!())?;
+
+        let extension = match config.format {
+            LogFileFormat::Text => "log",
+            LogFileFormat::JsonLines => "jsonl",
         };
-        let event = Event {
-            timestamp: Local::now().format("%H:%M:%S.%f").to_string(),
-            event_type,
+        let time = Local::now().format("%Y-%m-%d_%H-%M-%S");
+
+        // Never reuse a file, it could have been rotated already within the same second
+        let mut counter = 0;
+        let file = loop {
+            let file_name = if counter == 0 {
+                format!("{time}_{label}.{extension}")
+            } else {
+                format!("{time}_{label}_{counter}.{extension}")
+            };
+
+            match fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(dir.join(file_name))
+            {
+                Ok(file) => break file,
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => counter += 1,
+                Err(e) => return Err(e).map_err(err!()),
+            }
         };
-        out.finish(format_args!("{}", serde_json::to_string(&event).unwrap()));
 
-        events_sender.send(event).ok();
-    });
+        // The new file is the newest, so it is never deleted here
+        let files = alvr_server_io::get_log_files(&dir)?;
+        for old_file in files.iter().skip(config.max_file_count as usize) {
+            fs::remove_file(dir.join(&old_file.name)).ok();
+        }
 
-    if cfg!(debug_assertions) {
-        log_dispatch = log_dispatch.level(LevelFilter::Debug)
-    } else {
-        log_dispatch = log_dispatch.level(LevelFilter::Info);
+        Ok(Self {
+            config,
+            label: label.to_owned(),
+            file,
+            size_bytes: 0,
+        })
     }
 
-    if SERVER_DATA_MANAGER.read().settings().logging.log_to_disk {
-        log_dispatch = log_dispatch.chain(
-            fs::OpenOptions::new()
-                .write(true)
-                .create(true)
-                .truncate(true)
-                .open(FILESYSTEM_LAYOUT.session_log())
-                .unwrap(),
-        );
-    } else {
-        // this sink is required to make sure all log gets processed and forwarded to the websocket
-        if cfg!(target_os = "linux") {
-            log_dispatch = log_dispatch.chain(
-                fs::OpenOptions::new()
-                    .write(true)
-                    .open("/dev/null")
-                    .unwrap(),
-            );
-        } else {
-            log_dispatch = log_dispatch.chain(std::io::stdout());
+    fn write_event(&mut self, event: &Event) -> StrResult {
+        let line = match (self.config.format, &event.event_type) {
+            (LogFileFormat::Text, EventType::Log(entry)) => {
+                format!(
+                    "[{} {:?}] {}",
+                    event.timestamp, entry.severity, entry.content
+                )
+            }
+            (LogFileFormat::Text, event_type) => format!(
+                "[{}] {}",
+                event.timestamp,
+                serde_json::to_string(event_type).map_err(err!())?
+            ),
+            (LogFileFormat::JsonLines, _) => serde_json::to_string(event).map_err(err!())?,
+        };
+
+        writeln!(self.file, "{line}").map_err(err!())?;
+        self.size_bytes += line.len() as u64 + 1;
+
+        if self.size_bytes > self.config.max_file_size_mb * 1024 * 1024 {
+            *self = Self::open(self.config.clone(), &self.label)?;
         }
+
+        Ok(())
     }
+}
 
-    log_dispatch
+fn switch_log_file(label: &str) {
+    let mut writer_lock = LOG_FILE_WRITER.lock();
+    if let Some(writer) = &mut *writer_lock {
+        match LogFileWriter::open(writer.config.clone(), label) {
+            Ok(new_writer) => *writer = new_writer,
+            Err(e) => error!("Failed to create log file: {e}"),
+        }
+    }
+}
+
+// Hostnames are chosen by the clients, so they cannot be trusted as part of a file name
+pub fn hostname_file_label(client_hostname: &str) -> String {
+    client_hostname
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '.' || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+// Logs are written to a separate file for the duration of a streaming session
+pub fn start_stream_log(client_hostname: &str) {
+    switch_log_file(&hostname_file_label(client_hostname));
+}
+
+pub fn end_stream_log() {
+    switch_log_file(STREAMER_LOG_LABEL);
+}
+
+fn write_events_to_file(mut events_receiver: Receiver<Event>) {
+    loop {
+        let event = match events_receiver.blocking_recv() {
+            Ok(event) => event,
+            Err(RecvError::Lagged(count)) => Event {
+                timestamp: Local::now().format("%H:%M:%S.%f").to_string(),
+                event_type: EventType::Log(LogEntry {
+                    severity: LogSeverity::Warning,
+                    content: format!("{count} events not logged, file writer lagged"),
+                }),
+            },
+            Err(RecvError::Closed) => return,
+        };
+
+        if let Some(writer) = &mut *LOG_FILE_WRITER.lock() {
+            // Errors cannot be logged here, they would generate more events to write
+            writer.write_event(&event).ok();
+        }
+    }
+}
+
+pub fn init_logging() {
+    let logging_config = SERVER_DATA_MANAGER.read().settings().logging.clone();
+    let mut log_file_result = Ok(());
+    if logging_config.log_to_disk {
+        log_file_result =
+            LogFileWriter::open(logging_config.log_files, STREAMER_LOG_LABEL).map(|writer| {
+                *LOG_FILE_WRITER.lock() = Some(writer);
+
+                // Subscribe before installing the logger so no log is missed
+                let events_receiver = alvr_events::subscribe();
+                thread::spawn(move || write_events_to_file(events_receiver));
+            });
+    }
+
+    // Log messages are forwarded to the event bus as they are, they are never parsed as events
+    let events_output = Output::call(|record| {
+        alvr_events::send_event(EventType::Log(LogEntry {
+            severity: LogSeverity::from_log_level(record.level()),
+            content: record.args().to_string(),
+        }))
+    });
+
+    Dispatch::new()
+        .level(if cfg!(debug_assertions) {
+            LevelFilter::Debug
+        } else {
+            LevelFilter::Info
+        })
+        .chain(events_output)
         .chain(
             Dispatch::new()
                 .level(LevelFilter::Error)
+                .format(|out, message, _| {
+                    out.finish(format_args!(
+                        "[{}] {message}",
+                        Local::now().format("%Y-%m-%d %H:%M:%S.%f")
+                    ))
+                })
                 .chain(fern::log_file(FILESYSTEM_LAYOUT.crash_log()).unwrap()),
         )
         .apply()
         .unwrap();
 
     alvr_common::set_panic_hook();
+
+    if let Err(e) = log_file_result {
+        error!("Failed to create log file: {e}");
+    }
 }
@@ -0,0 +1,274 @@
+use alvr_common::{Histogram, DEVICE_ID_TO_PATH};
+use alvr_events::GraphStatistics;
+use alvr_packets::{ClientHealth, PerformanceLevel};
+use std::{collections::HashMap, fmt::Write, time::Duration};
+
+const LATENCY_BUCKETS_S: [f64; 10] = [
+    0.001, 0.002, 0.005, 0.01, 0.02, 0.035, 0.05, 0.075, 0.1, 0.2,
+];
+
+const LATENCY_STAGES: [&str; 9] = [
+    "total",
+    "game",
+    "server_compositor",
+    "encoder",
+    "network",
+    "decoder",
+    "decoder_queue",
+    "client_compositor",
+    "vsync_queue",
+];
+
+// Note: writing to a String never fails
+fn header(out: &mut String, name: &str, ty: &str, help: &str) {
+    writeln!(out, "# HELP {name} {help}").ok();
+    writeln!(out, "# TYPE {name} {ty}").ok();
+}
+
+// Values are kept across connections, so counters and histograms never go back unless SteamVR is
+// restarted. The output uses the Prometheus text exposition format.
+#[derive(Default)]
+pub struct MetricsRecorder {
+    client_connected: bool,
+    last_latencies_s: [f32; LATENCY_STAGES.len()],
+    latency_histograms: [Histogram; LATENCY_STAGES.len()],
+    client_fps: f32,
+    server_fps: f32,
+    video_packets_total: u64,
+    video_bytes_total: u64,
+    packets_lost_total: u64,
+    target_bitrate_bps: u64,
+    battery_gauges: HashMap<u64, f32>,
+    client_health: ClientHealth,
+}
+
+impl MetricsRecorder {
+    pub fn report_connection_state(&mut self, connected: bool) {
+        self.client_connected = connected;
+    }
+
+    pub fn report_graph_statistics(&mut self, stats: &GraphStatistics) {
+        let latencies = [
+            stats.total_pipeline_latency_s,
+            stats.game_time_s,
+            stats.server_compositor_s,
+            stats.encoder_s,
+            stats.network_s,
+            stats.decoder_s,
+            stats.decoder_queue_s,
+            stats.client_compositor_s,
+            stats.vsync_queue_s,
+        ];
+
+        for (histogram, latency) in self.latency_histograms.iter_mut().zip(latencies) {
+            histogram.submit_sample(Duration::from_secs_f32(latency.max(0.0)));
+        }
+        self.last_latencies_s = latencies;
+
+        self.client_fps = stats.client_fps;
+        self.server_fps = stats.server_fps;
+    }
+
+    pub fn report_video_packet(&mut self, bytes_count: usize) {
+        self.video_packets_total += 1;
+        self.video_bytes_total += bytes_count as u64;
+    }
+
+    pub fn report_packet_loss(&mut self) {
+        self.packets_lost_total += 1;
+    }
+
+    pub fn report_target_bitrate(&mut self, bitrate_bps: u64) {
+        self.target_bitrate_bps = bitrate_bps;
+    }
+
+    pub fn report_battery(&mut self, device_id: u64, gauge_value: f32) {
+        self.battery_gauges.insert(device_id, gauge_value);
+    }
+
+    pub fn report_client_health(&mut self, health: &ClientHealth) {
+        self.client_health = health.clone();
+    }
+
+    pub fn encode(&self) -> String {
+        let mut out = String::new();
+
+        header(
+            &mut out,
+            "alvr_client_connected",
+            "gauge",
+            "1 if a client is streaming",
+        );
+        writeln!(out, "alvr_client_connected {}", self.client_connected as u8).ok();
+
+        header(
+            &mut out,
+            "alvr_latency_last_seconds",
+            "gauge",
+            "Latency of the last frame, by pipeline stage",
+        );
+        for (stage, latency) in LATENCY_STAGES.iter().zip(self.last_latencies_s) {
+            writeln!(
+                out,
+                "alvr_latency_last_seconds{{stage=\"{stage}\"}} {latency}"
+            )
+            .ok();
+        }
+
+        header(
+            &mut out,
+            "alvr_latency_seconds",
+            "histogram",
+            "Frame latency, by pipeline stage",
+        );
+        for (stage, histogram) in LATENCY_STAGES.iter().zip(&self.latency_histograms) {
+            for bound in LATENCY_BUCKETS_S {
+                writeln!(
+                    out,
+                    "alvr_latency_seconds_bucket{{stage=\"{stage}\",le=\"{bound}\"}} {}",
+                    histogram.count_up_to(Duration::from_secs_f64(bound))
+                )
+                .ok();
+            }
+            writeln!(
+                out,
+                "alvr_latency_seconds_bucket{{stage=\"{stage}\",le=\"+Inf\"}} {}",
+                histogram.samples_count()
+            )
+            .ok();
+            writeln!(
+                out,
+                "alvr_latency_seconds_sum{{stage=\"{stage}\"}} {}",
+                histogram.sum().as_secs_f64()
+            )
+            .ok();
+            writeln!(
+                out,
+                "alvr_latency_seconds_count{{stage=\"{stage}\"}} {}",
+                histogram.samples_count()
+            )
+            .ok();
+        }
+
+        header(&mut out, "alvr_fps", "gauge", "Frames per second");
+        writeln!(out, "alvr_fps{{side=\"client\"}} {}", self.client_fps).ok();
+        writeln!(out, "alvr_fps{{side=\"server\"}} {}", self.server_fps).ok();
+
+        header(
+            &mut out,
+            "alvr_video_packets_total",
+            "counter",
+            "Video packets sent",
+        );
+        writeln!(out, "alvr_video_packets_total {}", self.video_packets_total).ok();
+
+        header(
+            &mut out,
+            "alvr_video_bytes_total",
+            "counter",
+            "Video bytes sent",
+        );
+        writeln!(out, "alvr_video_bytes_total {}", self.video_bytes_total).ok();
+
+        header(
+            &mut out,
+            "alvr_packets_lost_total",
+            "counter",
+            "Video packets reported lost by the client",
+        );
+        writeln!(out, "alvr_packets_lost_total {}", self.packets_lost_total).ok();
+
+        header(
+            &mut out,
+            "alvr_encoder_target_bitrate_bits_per_second",
+            "gauge",
+            "Bitrate requested to the encoder",
+        );
+        writeln!(
+            out,
+            "alvr_encoder_target_bitrate_bits_per_second {}",
+            self.target_bitrate_bps
+        )
+        .ok();
+
+        header(
+            &mut out,
+            "alvr_battery_ratio",
+            "gauge",
+            "Battery charge of tracked devices, from 0 to 1",
+        );
+        for (device_id, gauge) in &self.battery_gauges {
+            let device = DEVICE_ID_TO_PATH
+                .get(device_id)
+                .map(|path| (*path).to_owned())
+                .unwrap_or_else(|| format!("{device_id:#x}"));
+            writeln!(out, "alvr_battery_ratio{{device=\"{device}\"}} {gauge}").ok();
+        }
+
+        // Values not supported by the client are omitted
+        let health = &self.client_health;
+        let mut gauge = |name: &str, help: &str, value: Option<String>| {
+            header(&mut out, name, "gauge", help);
+            if let Some(value) = value {
+                writeln!(out, "{name} {value}").ok();
+            }
+        };
+        gauge(
+            "alvr_client_battery_temperature_celsius",
+            "Battery temperature of the headset",
+            health.battery_temperature_c.map(|t| t.to_string()),
+        );
+        gauge(
+            "alvr_client_thermal_throttling",
+            "1 if the headset is thermal throttling",
+            health.thermal_throttling.map(|t| (t as u8).to_string()),
+        );
+        gauge(
+            "alvr_client_wifi_rssi_dbm",
+            "Wi-Fi signal strength of the headset",
+            health.wifi.map(|w| w.rssi_dbm.to_string()),
+        );
+        gauge(
+            "alvr_client_wifi_link_speed_bits_per_second",
+            "Wi-Fi link speed of the headset",
+            health
+                .wifi
+                .map(|w| (w.link_speed_mbps as u64 * 1_000_000).to_string()),
+        );
+        gauge(
+            "alvr_client_wifi_frequency_hertz",
+            "Wi-Fi channel frequency of the headset",
+            health
+                .wifi
+                .map(|w| (w.frequency_mhz as u64 * 1_000_000).to_string()),
+        );
+        gauge(
+            "alvr_client_free_memory_bytes",
+            "Memory available to the headset apps",
+            health.free_memory_bytes.map(|m| m.to_string()),
+        );
+
+        header(
+            &mut out,
+            "alvr_client_performance_level",
+            "gauge",
+            "Headset performance level: 0 normal, 1 warning, 2 impaired",
+        );
+        for (domain, level) in [("cpu", health.cpu_level), ("gpu", health.gpu_level)] {
+            if let Some(level) = level {
+                let value = match level {
+                    PerformanceLevel::Normal => 0,
+                    PerformanceLevel::Warning => 1,
+                    PerformanceLevel::Impaired => 2,
+                };
+                writeln!(
+                    out,
+                    "alvr_client_performance_level{{domain=\"{domain}\"}} {value}"
+                )
+                .ok();
+            }
+        }
+
+        out
+    }
+}
@@ -0,0 +1,299 @@
+use alvr_common::{
+    glam::{Quat, Vec3},
+    DeviceMotion, Pose,
+};
+use alvr_session::MotionSmoothingConfig;
+use std::{f32::consts::PI, time::Duration};
+
+// Samples further apart than this restart the filter, for example after the tracking was lost
+const MAX_SAMPLE_INTERVAL: Duration = Duration::from_millis(200);
+
+// Cutoff for the speeds that drive the One Euro cutoff, as suggested by the paper
+const SPEED_CUTOFF_HZ: f32 = 1.0;
+
+// Weight of the new sample for a first order low-pass filter
+fn smoothing_factor(interval_s: f32, cutoff_hz: f32) -> f32 {
+    let time_constant = 1.0 / (2.0 * PI * cutoff_hz);
+
+    1.0 / (1.0 + time_constant / interval_s)
+}
+
+// Rotation from `from` to `to`, expressed in the world frame, along the shortest path
+fn rotation_difference(from: Quat, to: Quat) -> Vec3 {
+    let difference = to * from.conjugate();
+    if difference.w < 0.0 {
+        (-difference).to_scaled_axis()
+    } else {
+        difference.to_scaled_axis()
+    }
+}
+
+// One Euro filter (Casiez et al., 2012). The speeds are taken from the velocities reported by the
+// device instead of being derived from the poses.
+struct OneEuroState {
+    min_cutoff_hz: f32,
+    beta: f32,
+    pose: Pose,
+    linear_speed: f32,
+    angular_speed: f32,
+}
+
+impl OneEuroState {
+    fn update(&mut self, motion: &DeviceMotion, interval_s: f32) -> Pose {
+        let speed_factor = smoothing_factor(interval_s, SPEED_CUTOFF_HZ);
+        self.linear_speed += (motion.linear_velocity.length() - self.linear_speed) * speed_factor;
+        self.angular_speed +=
+            (motion.angular_velocity.length() - self.angular_speed) * speed_factor;
+
+        let position_factor = smoothing_factor(
+            interval_s,
+            self.min_cutoff_hz + self.beta * self.linear_speed,
+        );
+        let orientation_factor = smoothing_factor(
+            interval_s,
+            self.min_cutoff_hz + self.beta * self.angular_speed,
+        );
+
+        self.pose = Pose {
+            orientation: self
+                .pose
+                .orientation
+                .slerp(motion.pose.orientation, orientation_factor),
+            position: self
+                .pose
+                .position
+                .lerp(motion.pose.position, position_factor),
+        };
+
+        self.pose
+    }
+}
+
+// The position uses a constant velocity model, driven by white noise acceleration. The same
+// covariance is valid for every axis, since the model and the noise are isotropic. The orientation
+// is predicted with the reported angular velocity and corrected with the reported orientation.
+struct KalmanState {
+    acceleration_variance: f32,
+    position_variance: f32,
+    angular_velocity_variance: f32,
+    orientation_variance: f32,
+    position: Vec3,
+    linear_velocity: Vec3,
+    // Covariance of position and velocity, [[pp, pv], [pv, vv]]
+    position_covariance: [f32; 3],
+    orientation: Quat,
+    orientation_covariance: f32,
+}
+
+impl KalmanState {
+    fn update(&mut self, motion: &DeviceMotion, interval_s: f32) -> (Pose, Vec3) {
+        let dt = interval_s;
+
+        // Predict
+        self.position += self.linear_velocity * dt;
+        let [pp, pv, vv] = self.position_covariance;
+        let q = self.acceleration_variance;
+        let pp = pp + 2.0 * dt * pv + dt * dt * vv + q * dt.powi(4) / 4.0;
+        let pv = pv + dt * vv + q * dt.powi(3) / 2.0;
+        let vv = vv + q * dt * dt;
+
+        self.orientation =
+            (Quat::from_scaled_axis(motion.angular_velocity * dt) * self.orientation).normalize();
+        self.orientation_covariance += self.angular_velocity_variance * dt * dt;
+
+        // Correct
+        let innovation_variance = pp + self.position_variance;
+        let position_gain = pp / innovation_variance;
+        let velocity_gain = pv / innovation_variance;
+        let innovation = motion.pose.position - self.position;
+        self.position += innovation * position_gain;
+        self.linear_velocity += innovation * velocity_gain;
+        self.position_covariance = [
+            (1.0 - position_gain) * pp,
+            (1.0 - position_gain) * pv,
+            vv - velocity_gain * pv,
+        ];
+
+        let orientation_gain =
+            self.orientation_covariance / (self.orientation_covariance + self.orientation_variance);
+        let innovation = rotation_difference(self.orientation, motion.pose.orientation);
+        self.orientation =
+            (Quat::from_scaled_axis(innovation * orientation_gain) * self.orientation).normalize();
+        self.orientation_covariance *= 1.0 - orientation_gain;
+
+        (
+            Pose {
+                orientation: self.orientation,
+                position: self.position,
+            },
+            self.linear_velocity,
+        )
+    }
+}
+
+enum FilterState {
+    OneEuro(OneEuroState),
+    Kalman(KalmanState),
+}
+
+pub struct MotionFilter {
+    config: MotionSmoothingConfig,
+    state: Option<FilterState>,
+    last_timestamp: Duration,
+    last_motion: DeviceMotion,
+}
+
+impl MotionFilter {
+    pub fn new(config: MotionSmoothingConfig) -> Self {
+        Self {
+            config,
+            state: None,
+            last_timestamp: Duration::ZERO,
+            last_motion: DeviceMotion::default(),
+        }
+    }
+
+    fn initial_state(&self, motion: &DeviceMotion) -> FilterState {
+        match self.config {
+            MotionSmoothingConfig::OneEuro {
+                min_cutoff_hz,
+                beta,
+            } => FilterState::OneEuro(OneEuroState {
+                min_cutoff_hz,
+                beta,
+                pose: motion.pose,
+                linear_speed: motion.linear_velocity.length(),
+                angular_speed: motion.angular_velocity.length(),
+            }),
+            MotionSmoothingConfig::Kalman {
+                acceleration_noise,
+                position_noise,
+                angular_velocity_noise,
+                orientation_noise,
+            } => {
+                let position_variance = position_noise * position_noise;
+                let orientation_variance = orientation_noise.to_radians().powi(2);
+
+                FilterState::Kalman(KalmanState {
+                    acceleration_variance: acceleration_noise * acceleration_noise,
+                    position_variance,
+                    angular_velocity_variance: angular_velocity_noise.to_radians().powi(2),
+                    orientation_variance,
+                    position: motion.pose.position,
+                    linear_velocity: motion.linear_velocity,
+                    position_covariance: [position_variance, 0.0, position_variance],
+                    orientation: motion.pose.orientation,
+                    orientation_covariance: orientation_variance,
+                })
+            }
+        }
+    }
+
+    // The motion must be in the world frame, with velocities in the world frame too. The timestamp
+    // is the target time of the tracking sample, which increases together with the sample time.
+    pub fn filter(&mut self, mut motion: DeviceMotion, timestamp: Duration) -> DeviceMotion {
+        let is_recent = timestamp + MAX_SAMPLE_INTERVAL > self.last_timestamp
+            && timestamp < self.last_timestamp + MAX_SAMPLE_INTERVAL;
+
+        match &mut self.state {
+            Some(state) if is_recent => {
+                // Repeated or out of order samples are not filtered again
+                if timestamp <= self.last_timestamp {
+                    return self.last_motion;
+                }
+
+                let interval_s = (timestamp - self.last_timestamp).as_secs_f32();
+                match state {
+                    FilterState::OneEuro(state) => {
+                        motion.pose = state.update(&motion, interval_s);
+                    }
+                    FilterState::Kalman(state) => {
+                        (motion.pose, motion.linear_velocity) = state.update(&motion, interval_s);
+                    }
+                }
+            }
+            _ => self.state = Some(self.initial_state(&motion)),
+        }
+
+        self.last_timestamp = timestamp;
+        self.last_motion = motion;
+
+        motion
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_INTERVAL: Duration = Duration::from_millis(10);
+
+    #[test]
+    fn one_euro_converges_to_still_device() {
+        let mut filter = MotionFilter::new(MotionSmoothingConfig::OneEuro {
+            min_cutoff_hz: 1.0,
+            beta: 0.5,
+        });
+        filter.filter(DeviceMotion::default(), Duration::ZERO);
+
+        let target = Pose {
+            orientation: Quat::from_rotation_y(0.5),
+            position: Vec3::new(0.1, 1.5, -0.2),
+        };
+        let mut motion = DeviceMotion::default();
+        for i in 1..=300 {
+            motion = filter.filter(
+                DeviceMotion {
+                    pose: target,
+                    ..Default::default()
+                },
+                SAMPLE_INTERVAL * i,
+            );
+        }
+
+        assert!(motion.pose.position.distance(target.position) < 1e-4);
+        assert!(motion.pose.orientation.angle_between(target.orientation) < 1e-3);
+    }
+
+    #[test]
+    fn kalman_estimates_constant_velocity() {
+        let mut filter = MotionFilter::new(MotionSmoothingConfig::Kalman {
+            acceleration_noise: 10.0,
+            position_noise: 0.001,
+            angular_velocity_noise: 30.0,
+            orientation_noise: 0.2,
+        });
+
+        // The filter estimates the linear velocity from the positions only
+        let linear_velocity = Vec3::new(0.5, 0.0, -0.3);
+        let angular_velocity = Vec3::new(0.0, 1.0, 0.0);
+        let mut motion = DeviceMotion::default();
+        let mut expected_pose = Pose::default();
+        for i in 0..300 {
+            let time_s = (SAMPLE_INTERVAL * i).as_secs_f32();
+            expected_pose = Pose {
+                orientation: Quat::from_scaled_axis(angular_velocity * time_s),
+                position: linear_velocity * time_s,
+            };
+
+            motion = filter.filter(
+                DeviceMotion {
+                    pose: expected_pose,
+                    linear_velocity: Vec3::ZERO,
+                    angular_velocity,
+                },
+                SAMPLE_INTERVAL * i,
+            );
+        }
+
+        assert!(motion.pose.position.distance(expected_pose.position) < 1e-3);
+        assert!(motion.linear_velocity.distance(linear_velocity) < 1e-2);
+        assert!(
+            motion
+                .pose
+                .orientation
+                .angle_between(expected_pose.orientation)
+                < 1e-3
+        );
+    }
+}
@@ -0,0 +1,351 @@
+use alvr_common::{
+    glam::{Quat, Vec3},
+    settings_schema::Switch,
+    DeviceMotion, HEAD_ID,
+};
+use alvr_session::{PosePredictionConfig, PosePredictionStrategy};
+use std::{collections::HashMap, f32::consts::LN_2, time::Duration};
+
+// Velocities further apart than this are not used to estimate the acceleration
+const MAX_SAMPLE_INTERVAL: Duration = Duration::from_millis(200);
+
+// The acceleration is the derivative of the reported velocities, which are already noisy
+const ACCELERATION_SMOOTHING_FACTOR: f32 = 0.3;
+
+// Integrates the motion forward by time_s. Poses and velocities must be in the world frame. For the
+// rotation, the acceleration is assumed to be along the angular velocity axis.
+pub fn extrapolate(
+    motion: &DeviceMotion,
+    linear_acceleration: Vec3,
+    angular_acceleration: Vec3,
+    strategy: PosePredictionStrategy,
+    time_s: f32,
+) -> DeviceMotion {
+    let (translation, rotation, linear_velocity, angular_velocity) = match strategy {
+        PosePredictionStrategy::ConstantVelocity => (
+            motion.linear_velocity * time_s,
+            motion.angular_velocity * time_s,
+            motion.linear_velocity,
+            motion.angular_velocity,
+        ),
+        PosePredictionStrategy::ConstantAcceleration => (
+            motion.linear_velocity * time_s + linear_acceleration * time_s * time_s / 2.0,
+            motion.angular_velocity * time_s + angular_acceleration * time_s * time_s / 2.0,
+            motion.linear_velocity + linear_acceleration * time_s,
+            motion.angular_velocity + angular_acceleration * time_s,
+        ),
+        PosePredictionStrategy::Damped {
+            velocity_half_life_ms,
+        } => {
+            // The velocity decays exponentially, so the displacement converges to v * time_constant
+            let time_constant_s = velocity_half_life_ms / 1000.0 / LN_2;
+            let decay = (-time_s / time_constant_s).exp();
+            let displacement_time_s = time_constant_s * (1.0 - decay);
+
+            (
+                motion.linear_velocity * displacement_time_s,
+                motion.angular_velocity * displacement_time_s,
+                motion.linear_velocity * decay,
+                motion.angular_velocity * decay,
+            )
+        }
+    };
+
+    let mut predicted = *motion;
+    predicted.pose.position += translation;
+    predicted.pose.orientation =
+        (Quat::from_scaled_axis(rotation) * motion.pose.orientation).normalize();
+    predicted.linear_velocity = linear_velocity;
+    predicted.angular_velocity = angular_velocity;
+
+    predicted
+}
+
+struct DeviceState {
+    timestamp: Duration,
+    linear_velocity: Vec3,
+    angular_velocity: Vec3,
+    linear_acceleration: Vec3,
+    angular_acceleration: Vec3,
+}
+
+pub struct PosePredictor {
+    strategy: PosePredictionStrategy,
+    max_prediction: Duration,
+    // None if the head is not predicted
+    head_runtime_prediction: Option<Duration>,
+    devices: HashMap<u64, DeviceState>,
+}
+
+impl PosePredictor {
+    pub fn new(config: &PosePredictionConfig) -> Self {
+        Self {
+            strategy: config.strategy,
+            max_prediction: Duration::from_secs_f32(config.max_prediction_ms / 1000.0),
+            head_runtime_prediction: if let Switch::Enabled(head) = &config.head {
+                Some(Duration::from_secs_f32(head.runtime_prediction_ms / 1000.0))
+            } else {
+                None
+            },
+            devices: HashMap::new(),
+        }
+    }
+
+    fn update_acceleration(&mut self, device_id: u64, motion: &DeviceMotion, timestamp: Duration) {
+        let state = self.devices.entry(device_id).or_insert(DeviceState {
+            timestamp,
+            linear_velocity: motion.linear_velocity,
+            angular_velocity: motion.angular_velocity,
+            linear_acceleration: Vec3::ZERO,
+            angular_acceleration: Vec3::ZERO,
+        });
+
+        match timestamp.checked_sub(state.timestamp) {
+            Some(interval) if !interval.is_zero() && interval <= MAX_SAMPLE_INTERVAL => {
+                let interval_s = interval.as_secs_f32();
+                let linear_acceleration =
+                    (motion.linear_velocity - state.linear_velocity) / interval_s;
+                let angular_acceleration =
+                    (motion.angular_velocity - state.angular_velocity) / interval_s;
+
+                state.linear_acceleration = state
+                    .linear_acceleration
+                    .lerp(linear_acceleration, ACCELERATION_SMOOTHING_FACTOR);
+                state.angular_acceleration = state
+                    .angular_acceleration
+                    .lerp(angular_acceleration, ACCELERATION_SMOOTHING_FACTOR);
+            }
+            // Repeated samples
+            Some(_) if timestamp == state.timestamp => return,
+            Some(_) | None => {
+                state.linear_acceleration = Vec3::ZERO;
+                state.angular_acceleration = Vec3::ZERO;
+            }
+        }
+
+        state.timestamp = timestamp;
+        state.linear_velocity = motion.linear_velocity;
+        state.angular_velocity = motion.angular_velocity;
+    }
+
+    // The timestamp is the target timestamp of the tracking sample, used to estimate the
+    // acceleration. The prediction is clamped to the configured maximum.
+    pub fn predict(
+        &mut self,
+        device_id: u64,
+        motion: DeviceMotion,
+        timestamp: Duration,
+        prediction: Duration,
+    ) -> DeviceMotion {
+        // The head pose is already predicted by the client runtime, at least in part
+        let prediction = if device_id == *HEAD_ID {
+            match self.head_runtime_prediction {
+                Some(runtime_prediction) => prediction.saturating_sub(runtime_prediction),
+                None => return motion,
+            }
+        } else {
+            prediction
+        };
+
+        self.update_acceleration(device_id, &motion, timestamp);
+        let state = &self.devices[&device_id];
+
+        extrapolate(
+            &motion,
+            state.linear_acceleration,
+            state.angular_acceleration,
+            self.strategy,
+            Duration::min(prediction, self.max_prediction).as_secs_f32(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alvr_common::Pose;
+    use alvr_events::{Event, EventType};
+    use alvr_session::HeadPosePredictionConfig;
+
+    const SAMPLE_INTERVAL: Duration = Duration::from_micros(13_889); // 72 Hz
+    const PREDICTION: Duration = Duration::from_millis(50);
+
+    // Analytic trace: controller moving on a horizontal circle while rotating around the vertical
+    // axis, sampled at a constant rate
+    fn circular_trace(samples_count: u32) -> Vec<(Duration, DeviceMotion)> {
+        const RADIUS: f32 = 0.3;
+        const ANGULAR_SPEED: f32 = 4.0;
+
+        (0..samples_count)
+            .map(|i| {
+                let timestamp = SAMPLE_INTERVAL * i;
+                let angle = ANGULAR_SPEED * timestamp.as_secs_f32();
+
+                let motion = DeviceMotion {
+                    pose: Pose {
+                        orientation: Quat::from_rotation_y(angle),
+                        position: Vec3::new(angle.cos(), 1.0, angle.sin()) * RADIUS,
+                    },
+                    linear_velocity: Vec3::new(-angle.sin(), 0.0, angle.cos())
+                        * RADIUS
+                        * ANGULAR_SPEED,
+                    angular_velocity: Vec3::new(0.0, ANGULAR_SPEED, 0.0),
+                };
+
+                (timestamp, motion)
+            })
+            .collect()
+    }
+
+    // Right controller samples of a session log written with log_tracking enabled, in the JSON
+    // lines format
+    fn recorded_trace(jsonl: &str) -> Vec<(Duration, DeviceMotion)> {
+        jsonl
+            .lines()
+            .filter_map(|line| serde_json::from_str::<Event>(line).ok())
+            .filter_map(|event| match event.event_type {
+                EventType::Tracking(tracking) => {
+                    tracking.controller_motions[1].map(|motion| (tracking.target_timestamp, motion))
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    // The first sample at least PREDICTION after the one at index, used as ground truth
+    fn future_sample(
+        trace: &[(Duration, DeviceMotion)],
+        index: usize,
+    ) -> Option<&(Duration, DeviceMotion)> {
+        let target = trace[index].0 + PREDICTION;
+        trace[index..]
+            .iter()
+            .find(|(timestamp, _)| *timestamp >= target)
+    }
+
+    // Replays the trace and returns the average position and orientation errors against the
+    // recorded pose at the predicted time. With no strategy the poses are not predicted.
+    fn replay(
+        trace: &[(Duration, DeviceMotion)],
+        strategy: Option<PosePredictionStrategy>,
+    ) -> (f32, f32) {
+        let mut predictor = strategy.map(|strategy| {
+            PosePredictor::new(&PosePredictionConfig {
+                strategy,
+                max_prediction_ms: 100.0,
+                head: Switch::Disabled,
+            })
+        });
+
+        let mut position_error = 0.0;
+        let mut orientation_error = 0.0;
+        let mut count = 0;
+        for (index, (timestamp, motion)) in trace.iter().enumerate() {
+            let Some((actual_timestamp, actual)) = future_sample(trace, index) else {
+                break;
+            };
+
+            let predicted = match &mut predictor {
+                Some(predictor) => {
+                    predictor.predict(0, *motion, *timestamp, *actual_timestamp - *timestamp)
+                }
+                None => *motion,
+            };
+
+            position_error += predicted.pose.position.distance(actual.pose.position);
+            orientation_error += predicted
+                .pose
+                .orientation
+                .angle_between(actual.pose.orientation);
+            count += 1;
+        }
+
+        (
+            position_error / count as f32,
+            orientation_error / count as f32,
+        )
+    }
+
+    #[test]
+    fn constant_velocity_predicts_rotation() {
+        let (_, orientation_error) = replay(
+            &circular_trace(200),
+            Some(PosePredictionStrategy::ConstantVelocity),
+        );
+
+        assert!(orientation_error < 1e-3);
+    }
+
+    #[test]
+    fn constant_acceleration_follows_curves() {
+        let trace = circular_trace(200);
+        let (velocity_error, _) = replay(&trace, Some(PosePredictionStrategy::ConstantVelocity));
+        let (acceleration_error, _) =
+            replay(&trace, Some(PosePredictionStrategy::ConstantAcceleration));
+
+        assert!(acceleration_error < velocity_error / 2.0);
+    }
+
+    #[test]
+    fn trace_fixture_prediction() {
+        let trace = recorded_trace(include_str!(
+            "../tests/data/simulated_controller_trace.jsonl"
+        ));
+        assert!(trace.len() > 100);
+
+        let (unpredicted_error, _) = replay(&trace, None);
+        for strategy in [
+            PosePredictionStrategy::ConstantVelocity,
+            PosePredictionStrategy::ConstantAcceleration,
+            PosePredictionStrategy::Damped {
+                velocity_half_life_ms: 50.0,
+            },
+        ] {
+            let (position_error, _) = replay(&trace, Some(strategy));
+
+            assert!(position_error < unpredicted_error);
+        }
+    }
+
+    #[test]
+    fn head_prediction_subtracts_runtime_prediction() {
+        let motion = DeviceMotion {
+            linear_velocity: Vec3::X,
+            ..Default::default()
+        };
+        let predictor = |head| {
+            PosePredictor::new(&PosePredictionConfig {
+                strategy: PosePredictionStrategy::ConstantVelocity,
+                max_prediction_ms: 100.0,
+                head,
+            })
+        };
+
+        let predicted =
+            predictor(Switch::Disabled).predict(*HEAD_ID, motion, PREDICTION, PREDICTION);
+        assert_eq!(predicted.pose.position, Vec3::ZERO);
+
+        let predicted = predictor(Switch::Enabled(HeadPosePredictionConfig {
+            runtime_prediction_ms: 20.0,
+        }))
+        .predict(*HEAD_ID, motion, PREDICTION, PREDICTION);
+        assert!((predicted.pose.position.x - 0.03).abs() < 1e-5);
+    }
+
+    #[test]
+    fn damped_prediction_limits_overshoot() {
+        let motion = DeviceMotion {
+            linear_velocity: Vec3::X,
+            ..Default::default()
+        };
+        let strategy = PosePredictionStrategy::Damped {
+            velocity_half_life_ms: 50.0,
+        };
+
+        // After many half lives the displacement converges to velocity * half life / ln(2)
+        let predicted = extrapolate(&motion, Vec3::ZERO, Vec3::ZERO, strategy, 10.0);
+
+        assert!((predicted.pose.position.x - 0.05 / LN_2).abs() < 1e-4);
+        assert!(predicted.linear_velocity.length() < 1e-4);
+    }
+}
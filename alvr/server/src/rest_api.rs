@@ -0,0 +1,236 @@
+use crate::{diagnostics, web_server, FILESYSTEM_LAYOUT, SERVER_DATA_MANAGER, STATISTICS_MANAGER};
+use alvr_common::prelude::*;
+use alvr_packets::{ClientListAction, PathValuePair, SessionChangeOrigin};
+use alvr_session::SessionDesc;
+use hyper::{
+    header::{CONTENT_DISPOSITION, CONTENT_TYPE},
+    Body, Method, Request, Response, StatusCode,
+};
+use serde::Serialize;
+use serde_json as json;
+use std::path::PathBuf;
+
+pub const API_V1_PREFIX: &str = "/api/v1/";
+
+fn reply_json(code: StatusCode, value: &impl Serialize) -> StrResult<Response<Body>> {
+    Response::builder()
+        .status(code)
+        .header(CONTENT_TYPE, "application/json")
+        .body(json::to_string(value).map_err(err!())?.into())
+        .map_err(err!())
+}
+
+fn reply_error(code: StatusCode, message: impl Into<String>) -> StrResult<Response<Body>> {
+    reply_json(code, &json::json!({ "error": message.into() }))
+}
+
+fn reply_drivers_list() -> StrResult<Response<Body>> {
+    match alvr_server_io::get_registered_drivers() {
+        Ok(list) => reply_json(StatusCode::OK, &list),
+        Err(e) => reply_error(StatusCode::INTERNAL_SERVER_ERROR, e),
+    }
+}
+
+// Segments are decoded after splitting the path, so an encoded "/" stays inside its segment
+fn percent_decode(segment: &str) -> StrResult<String> {
+    let bytes = segment.as_bytes();
+
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut idx = 0;
+    while idx < bytes.len() {
+        if bytes[idx] == b'%' {
+            let byte = bytes
+                .get(idx + 1..idx + 3)
+                .filter(|hex| hex.iter().all(u8::is_ascii_hexdigit))
+                .and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok())
+                .ok_or_else(|| format!("Invalid percent encoding in \"{segment}\""))?;
+            decoded.push(byte);
+            idx += 3;
+        } else {
+            decoded.push(bytes[idx]);
+            idx += 1;
+        }
+    }
+
+    String::from_utf8(decoded).map_err(err!())
+}
+
+// Errors caused by the request are returned to the caller with a 4xx status code and a message.
+// Unlike /api/dashboard-request, no data is sent back through events.
+pub async fn handle_request(request: Request<Body>) -> StrResult<Response<Body>> {
+    let method = request.method().clone();
+    let path = request
+        .uri()
+        .path()
+        .trim_start_matches(API_V1_PREFIX)
+        .trim_end_matches('/')
+        .to_owned();
+    let segments = match path
+        .split('/')
+        .map(percent_decode)
+        .collect::<StrResult<Vec<_>>>()
+    {
+        Ok(segments) => segments,
+        Err(e) => return reply_error(StatusCode::BAD_REQUEST, e),
+    };
+    let segments = segments.iter().map(String::as_str).collect::<Vec<_>>();
+
+    match (&method, segments.as_slice()) {
+        (&Method::GET, ["session"]) => {
+            reply_json(StatusCode::OK, SERVER_DATA_MANAGER.read().session())
+        }
+        (&Method::PUT, ["session"]) => {
+            let session = match web_server::from_request_body::<SessionDesc>(request).await {
+                Ok(session) => session,
+                Err(e) => return reply_error(StatusCode::BAD_REQUEST, e),
+            };
+
+            let mut data_manager_lock = SERVER_DATA_MANAGER.write();
+            data_manager_lock.update_session(session, SessionChangeOrigin::HttpApi);
+
+            reply_json(StatusCode::OK, data_manager_lock.session())
+        }
+        (&Method::PATCH, ["session"]) => {
+            let descs = match web_server::from_request_body::<Vec<PathValuePair>>(request).await {
+                Ok(descs) => descs,
+                Err(e) => return reply_error(StatusCode::BAD_REQUEST, e),
+            };
+
+            let mut data_manager_lock = SERVER_DATA_MANAGER.write();
+            if let Err(e) = data_manager_lock.set_values(descs, SessionChangeOrigin::HttpApi) {
+                return reply_error(StatusCode::BAD_REQUEST, e);
+            }
+
+            reply_json(StatusCode::OK, data_manager_lock.session())
+        }
+        (&Method::GET, ["clients"]) => {
+            reply_json(StatusCode::OK, SERVER_DATA_MANAGER.read().client_list())
+        }
+        (&Method::POST, ["clients", hostname]) => {
+            let hostname = hostname.to_string();
+            let action = match web_server::from_request_body::<ClientListAction>(request).await {
+                Ok(action) => action,
+                Err(e) => return reply_error(StatusCode::BAD_REQUEST, e),
+            };
+
+            let mut data_manager_lock = SERVER_DATA_MANAGER.write();
+            data_manager_lock.update_client_list(hostname, action);
+
+            reply_json(StatusCode::OK, data_manager_lock.client_list())
+        }
+        (&Method::DELETE, ["clients", hostname]) => {
+            let mut data_manager_lock = SERVER_DATA_MANAGER.write();
+            if !data_manager_lock.client_list().contains_key(*hostname) {
+                return reply_error(
+                    StatusCode::NOT_FOUND,
+                    format!("Client {hostname} not found"),
+                );
+            }
+
+            data_manager_lock
+                .update_client_list(hostname.to_string(), ClientListAction::RemoveEntry);
+
+            reply_json(StatusCode::OK, data_manager_lock.client_list())
+        }
+        (&Method::GET, ["drivers"]) => reply_drivers_list(),
+        // Register the ALVR driver
+        (&Method::POST, ["drivers"]) => {
+            if let Err(e) = alvr_server_io::driver_registration(
+                &[FILESYSTEM_LAYOUT.openvr_driver_root_dir.clone()],
+                true,
+            ) {
+                return reply_error(StatusCode::INTERNAL_SERVER_ERROR, e);
+            }
+
+            reply_drivers_list()
+        }
+        // The body contains the path of the driver to unregister
+        (&Method::DELETE, ["drivers"]) => {
+            let path = match web_server::from_request_body::<PathBuf>(request).await {
+                Ok(path) => path,
+                Err(e) => return reply_error(StatusCode::BAD_REQUEST, e),
+            };
+
+            if let Err(e) = alvr_server_io::driver_registration(&[path], false) {
+                return reply_error(StatusCode::INTERNAL_SERVER_ERROR, e);
+            }
+
+            reply_drivers_list()
+        }
+        (&Method::GET, ["audio-devices"]) => {
+            match SERVER_DATA_MANAGER.read().get_audio_devices_list() {
+                Ok(list) => reply_json(StatusCode::OK, &list),
+                Err(e) => reply_error(StatusCode::INTERNAL_SERVER_ERROR, e),
+            }
+        }
+        (&Method::GET, ["statistics"]) => {
+            if let Some(stats) = STATISTICS_MANAGER
+                .lock()
+                .as_ref()
+                .and_then(|stats| stats.last_statistics())
+            {
+                reply_json(StatusCode::OK, &stats)
+            } else {
+                reply_error(
+                    StatusCode::NOT_FOUND,
+                    "No statistics, no client is streaming",
+                )
+            }
+        }
+        (&Method::GET, ["logs"]) => {
+            match alvr_server_io::get_log_files(&FILESYSTEM_LAYOUT.logs_dir()) {
+                Ok(list) => reply_json(StatusCode::OK, &list),
+                Err(e) => reply_error(StatusCode::INTERNAL_SERVER_ERROR, e),
+            }
+        }
+        // Download a log file, named as in the logs list
+        (&Method::GET, ["logs", name]) => {
+            match alvr_server_io::read_log_file(&FILESYSTEM_LAYOUT.logs_dir(), name) {
+                Ok(content) => Response::builder()
+                    .status(StatusCode::OK)
+                    .header(CONTENT_TYPE, "text/plain; charset=utf-8")
+                    .header(
+                        CONTENT_DISPOSITION,
+                        format!("attachment; filename=\"{name}\""),
+                    )
+                    .body(content.into())
+                    .map_err(err!()),
+                Err(e) => reply_error(StatusCode::NOT_FOUND, e),
+            }
+        }
+        (&Method::GET, ["diagnostics-bundle"]) => match diagnostics::create_diagnostics_bundle() {
+            Ok(bundle) => Response::builder()
+                .status(StatusCode::OK)
+                .header(CONTENT_TYPE, "application/zip")
+                .header(
+                    CONTENT_DISPOSITION,
+                    "attachment; filename=\"alvr_diagnostics.zip\"",
+                )
+                .body(bundle.into())
+                .map_err(err!()),
+            Err(e) => reply_error(StatusCode::INTERNAL_SERVER_ERROR, e),
+        },
+        (
+            _,
+            ["session" | "clients" | "drivers" | "audio-devices" | "statistics" | "logs"
+            | "diagnostics-bundle", ..],
+        ) => reply_error(
+            StatusCode::METHOD_NOT_ALLOWED,
+            format!("Method {method} not allowed for {path}"),
+        ),
+        _ => reply_error(StatusCode::NOT_FOUND, format!("Unknown endpoint {path}")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn percent_decoding() {
+        assert_eq!(percent_decode("a%20b%2Fc").unwrap(), "a b/c");
+        assert_eq!(percent_decode("%C3%A9").unwrap(), "é");
+        assert!(percent_decode("a%2").is_err());
+        assert!(percent_decode("a%zz").is_err());
+    }
+}
@@ -1,6 +1,12 @@
-use alvr_common::{SlidingWindowAverage, HEAD_ID, LEFT_HAND_ID, RIGHT_HAND_ID};
-use alvr_events::{EventType, GraphStatistics, Statistics};
-use alvr_packets::ClientStatistics;
+use crate::{FRAME_TRACER, METRICS, STATISTICS_RECORDER};
+use alvr_common::{
+    prelude::*, SlidingWindowAverage, SlidingWindowHistogram, HEAD_ID, LEFT_HAND_ID, RIGHT_HAND_ID,
+};
+use alvr_events::{
+    EventType, FrameStatisticsRecord, GraphStatistics, LatencyPercentiles, StageLatencyPercentiles,
+    Statistics, StatisticsRecord, StutterEvent,
+};
+use alvr_packets::{ClientHealth, ClientStatistics, PerformanceLevel};
 use std::{
     collections::{HashMap, VecDeque},
     time::{Duration, Instant},
@@ -8,13 +14,72 @@ use std::{
 
 const FULL_REPORT_INTERVAL: Duration = Duration::from_millis(500);
 
+// Percentiles are calculated over the last 10 to 20 seconds
+const PERCENTILES_WINDOW: Duration = Duration::from_secs(10);
+
+// Wi-Fi bands start at 2.4, 5 and 6 GHz
+const WIFI_5GHZ_BAND_START_MHZ: u32 = 5000;
+
+struct StageHistograms {
+    total: SlidingWindowHistogram,
+    game: SlidingWindowHistogram,
+    server_compositor: SlidingWindowHistogram,
+    encoder: SlidingWindowHistogram,
+    network: SlidingWindowHistogram,
+    decoder: SlidingWindowHistogram,
+    decoder_queue: SlidingWindowHistogram,
+    client_compositor: SlidingWindowHistogram,
+    vsync_queue: SlidingWindowHistogram,
+}
+
+impl StageHistograms {
+    fn new() -> Self {
+        let histogram = || SlidingWindowHistogram::new(PERCENTILES_WINDOW);
+
+        Self {
+            total: histogram(),
+            game: histogram(),
+            server_compositor: histogram(),
+            encoder: histogram(),
+            network: histogram(),
+            decoder: histogram(),
+            decoder_queue: histogram(),
+            client_compositor: histogram(),
+            vsync_queue: histogram(),
+        }
+    }
+
+    fn percentiles(&self) -> StageLatencyPercentiles {
+        fn percentiles(histogram: &SlidingWindowHistogram) -> LatencyPercentiles {
+            LatencyPercentiles {
+                p50_ms: histogram.get_percentile(50.0).as_secs_f32() * 1000.,
+                p95_ms: histogram.get_percentile(95.0).as_secs_f32() * 1000.,
+                p99_ms: histogram.get_percentile(99.0).as_secs_f32() * 1000.,
+            }
+        }
+
+        StageLatencyPercentiles {
+            total: percentiles(&self.total),
+            game: percentiles(&self.game),
+            server_compositor: percentiles(&self.server_compositor),
+            encoder: percentiles(&self.encoder),
+            network: percentiles(&self.network),
+            decoder: percentiles(&self.decoder),
+            decoder_queue: percentiles(&self.decoder_queue),
+            client_compositor: percentiles(&self.client_compositor),
+            vsync_queue: percentiles(&self.vsync_queue),
+        }
+    }
+}
+
 pub struct HistoryFrame {
-    target_timestamp: Duration,
-    tracking_received: Instant,
-    frame_present: Instant,
-    frame_composed: Instant,
-    frame_encoded: Instant,
-    total_pipeline_latency: Duration,
+    pub target_timestamp: Duration,
+    pub tracking_received: Instant,
+    pub frame_present: Instant,
+    pub frame_composed: Instant,
+    pub frame_encoded: Instant,
+    pub frame_sent: Instant,
+    pub total_pipeline_latency: Duration,
 }
 
 impl Default for HistoryFrame {
@@ -26,6 +91,7 @@ impl Default for HistoryFrame {
             frame_present: now,
             frame_composed: now,
             frame_encoded: now,
+            frame_sent: now,
             total_pipeline_latency: Duration::ZERO,
         }
     }
@@ -46,8 +112,17 @@ pub struct StatisticsManager {
     battery_gauges: HashMap<u64, f32>,
     steamvr_pipeline_latency: Duration,
     total_pipeline_latency_average: SlidingWindowAverage<Duration>,
+    // Used only by the server side pose prediction, tracker_pose_time_offset() is not affected
+    pose_prediction_latency_average: SlidingWindowAverage<Duration>,
+    game_time_latency_average: SlidingWindowAverage<Duration>,
     last_vsync_time: Instant,
     frame_interval: Duration,
+    last_statistics: Option<Statistics>,
+    latency_histograms: StageHistograms,
+    last_client_frame_interval: Duration,
+    frame_jitter_s: f32,
+    stutters_total: usize,
+    client_health: ClientHealth,
 }
 
 impl StatisticsManager {
@@ -77,8 +152,19 @@ impl StatisticsManager {
                 Duration::ZERO,
                 max_history_size,
             ),
+            pose_prediction_latency_average: SlidingWindowAverage::new(
+                Duration::ZERO,
+                max_history_size,
+            ),
+            game_time_latency_average: SlidingWindowAverage::new(Duration::ZERO, max_history_size),
             last_vsync_time: Instant::now(),
             frame_interval: nominal_server_frame_interval,
+            last_statistics: None,
+            latency_histograms: StageHistograms::new(),
+            last_client_frame_interval: Duration::ZERO,
+            frame_jitter_s: 0.0,
+            stutters_total: 0,
+            client_health: ClientHealth::default(),
         }
     }
 
@@ -136,20 +222,75 @@ impl StatisticsManager {
         }
     }
 
+    pub fn report_frame_sent(&mut self, target_timestamp: Duration) {
+        if let Some(frame) = self
+            .history_buffer
+            .iter_mut()
+            .find(|frame| frame.target_timestamp == target_timestamp)
+        {
+            frame.frame_sent = Instant::now();
+        }
+    }
+
     pub fn report_video_packet(&mut self, bytes_count: usize) {
         self.video_packets_total += 1;
         self.video_packets_partial_sum += 1;
         self.video_bytes_total += bytes_count;
         self.video_bytes_partial_sum += bytes_count;
+
+        METRICS.lock().report_video_packet(bytes_count);
     }
 
     pub fn report_packet_loss(&mut self) {
         self.packets_lost_total += 1;
         self.packets_lost_partial_sum += 1;
+
+        METRICS.lock().report_packet_loss();
+
+        if let Some(recorder) = &mut *STATISTICS_RECORDER.lock() {
+            let time_s = recorder.time_s();
+            recorder.record(StatisticsRecord::PacketLoss { time_s });
+        }
     }
 
     pub fn report_battery(&mut self, device_id: u64, gauge_value: f32) {
         *self.battery_gauges.entry(device_id).or_default() = gauge_value;
+
+        METRICS.lock().report_battery(device_id, gauge_value);
+    }
+
+    // Notifies only changes, since the client reports its health periodically
+    pub fn report_client_health(&mut self, health: ClientHealth) {
+        let previous = &self.client_health;
+
+        match (previous.thermal_throttling, health.thermal_throttling) {
+            (Some(true), Some(false)) => info!("The headset stopped thermal throttling"),
+            (previous, Some(true)) if previous != Some(true) => {
+                warn!("The headset is thermal throttling")
+            }
+            _ => (),
+        }
+
+        for (name, previous_level, level) in [
+            ("CPU", previous.cpu_level, health.cpu_level),
+            ("GPU", previous.gpu_level, health.gpu_level),
+        ] {
+            if level == Some(PerformanceLevel::Impaired) && previous_level != level {
+                warn!("Headset {name} performance is impaired");
+            }
+        }
+
+        if let (Some(previous_wifi), Some(wifi)) = (previous.wifi, health.wifi) {
+            if previous_wifi.frequency_mhz >= WIFI_5GHZ_BAND_START_MHZ
+                && wifi.frequency_mhz < WIFI_5GHZ_BAND_START_MHZ
+            {
+                warn!("Wi-Fi link dropped to 2.4 GHz");
+            }
+        }
+
+        METRICS.lock().report_client_health(&health);
+
+        self.client_health = health;
     }
 
     // Called every frame. Some statistics are reported once every frame
@@ -162,6 +303,10 @@ impl StatisticsManager {
         {
             frame.total_pipeline_latency = client_stats.total_pipeline_latency;
 
+            if let Some(tracer) = &mut *FRAME_TRACER.lock() {
+                tracer.add_frame(frame, &client_stats);
+            }
+
             let game_time_latency = frame
                 .frame_present
                 .saturating_duration_since(frame.tracking_received);
@@ -201,12 +346,57 @@ impl StatisticsManager {
                     .max(Duration::from_millis(1))
                     .as_secs_f32();
 
+            // A frame stutters if its total display time, from the previous vsync, exceeds the
+            // target interval. Half an interval of tolerance is left for the vsync timing noise,
+            // a frame that missed its vsync is late by a whole interval. The first frame has no
+            // previous vsync.
+            let is_stutter = self.last_client_frame_interval != Duration::ZERO
+                && client_stats.frame_interval > self.frame_interval + self.frame_interval / 2;
+            if is_stutter {
+                self.stutters_total += 1;
+            }
+
+            let histograms = &mut self.latency_histograms;
+            histograms
+                .total
+                .submit_sample(client_stats.total_pipeline_latency);
+            histograms.game.submit_sample(game_time_latency);
+            histograms
+                .server_compositor
+                .submit_sample(server_compositor_latency);
+            histograms.encoder.submit_sample(encoder_latency);
+            histograms.network.submit_sample(network_latency);
+            histograms.decoder.submit_sample(client_stats.video_decode);
+            histograms
+                .decoder_queue
+                .submit_sample(client_stats.video_decoder_queue);
+            histograms
+                .client_compositor
+                .submit_sample(client_stats.rendering);
+            histograms
+                .vsync_queue
+                .submit_sample(client_stats.vsync_queue);
+
+            self.pose_prediction_latency_average
+                .submit_sample(client_stats.total_pipeline_latency);
+            self.game_time_latency_average
+                .submit_sample(game_time_latency);
+
+            // Interarrival jitter estimator from RFC 3550
+            let interval_delta = if client_stats.frame_interval > self.last_client_frame_interval {
+                client_stats.frame_interval - self.last_client_frame_interval
+            } else {
+                self.last_client_frame_interval - client_stats.frame_interval
+            };
+            self.frame_jitter_s += (interval_delta.as_secs_f32() - self.frame_jitter_s) / 16.0;
+            self.last_client_frame_interval = client_stats.frame_interval;
+
             if self.last_full_report_instant + FULL_REPORT_INTERVAL < Instant::now() {
                 self.last_full_report_instant += FULL_REPORT_INTERVAL;
 
                 let interval_secs = FULL_REPORT_INTERVAL.as_secs_f32();
 
-                alvr_events::send_event(EventType::Statistics(Statistics {
+                let statistics = Statistics {
                     video_packets_total: self.video_packets_total,
                     video_packets_per_sec: (self.video_packets_partial_sum as f32 / interval_secs)
                         as _,
@@ -240,7 +430,20 @@ impl StatisticsManager {
                         .cloned()
                         .unwrap_or_default()
                         * 100.) as _,
-                }));
+                    latency_percentiles: self.latency_histograms.percentiles(),
+                    frame_jitter_ms: self.frame_jitter_s * 1000.,
+                    stutters_total: self.stutters_total,
+                    client_health: self.client_health.clone(),
+                    game_audio: client_stats.game_audio,
+                    microphone: alvr_audio::playback_statistics(),
+                };
+
+                alvr_events::send_event(EventType::Statistics(statistics.clone()));
+                self.last_statistics = Some(statistics);
+
+                if let Some(recorder) = &mut *STATISTICS_RECORDER.lock() {
+                    recorder.flush();
+                }
 
                 self.video_packets_partial_sum = 0;
                 self.video_bytes_partial_sum = 0;
@@ -249,7 +452,7 @@ impl StatisticsManager {
 
             // todo: use target timestamp in nanoseconds. the dashboard needs to use the first
             // timestamp as the graph time origin.
-            alvr_events::send_event(EventType::GraphStatistics(GraphStatistics {
+            let graph_statistics = GraphStatistics {
                 total_pipeline_latency_s: client_stats.total_pipeline_latency.as_secs_f32(),
                 game_time_s: game_time_latency.as_secs_f32(),
                 server_compositor_s: server_compositor_latency.as_secs_f32(),
@@ -261,7 +464,38 @@ impl StatisticsManager {
                 vsync_queue_s: client_stats.vsync_queue.as_secs_f32(),
                 client_fps,
                 server_fps,
-            }));
+            };
+
+            METRICS.lock().report_graph_statistics(&graph_statistics);
+
+            if is_stutter {
+                alvr_events::send_event(EventType::Stutter(StutterEvent {
+                    target_interval_s: self.frame_interval.as_secs_f32(),
+                    frame: graph_statistics.clone(),
+                }));
+            }
+
+            if let Some(recorder) = &mut *STATISTICS_RECORDER.lock() {
+                let time_s = recorder.time_s();
+                recorder.record(StatisticsRecord::Frame {
+                    time_s,
+                    frame: FrameStatisticsRecord {
+                        target_timestamp_ns: client_stats.target_timestamp.as_nanos() as u64,
+                        game_time_s: graph_statistics.game_time_s,
+                        server_compositor_s: graph_statistics.server_compositor_s,
+                        encoder_s: graph_statistics.encoder_s,
+                        network_s: graph_statistics.network_s,
+                        decoder_s: graph_statistics.decoder_s,
+                        decoder_queue_s: graph_statistics.decoder_queue_s,
+                        client_compositor_s: graph_statistics.client_compositor_s,
+                        vsync_queue_s: graph_statistics.vsync_queue_s,
+                        total_pipeline_latency_s: graph_statistics.total_pipeline_latency_s,
+                        client_frame_interval_s: client_stats.frame_interval.as_secs_f32(),
+                        server_frame_interval_s: self.last_frame_present_interval.as_secs_f32(),
+                    },
+                });
+            }
+            alvr_events::send_event(EventType::GraphStatistics(graph_statistics));
 
             network_latency
         } else {
@@ -269,12 +503,30 @@ impl StatisticsManager {
         }
     }
 
+    // Latest periodic report, or None if no full report has been made yet
+    pub fn last_statistics(&self) -> Option<Statistics> {
+        self.last_statistics.clone()
+    }
+
     pub fn tracker_pose_time_offset(&self) -> Duration {
         // This is the opposite of the client's StatisticsManager::tracker_prediction_offset().
         self.steamvr_pipeline_latency
             .saturating_sub(self.total_pipeline_latency_average.get_average())
     }
 
+    // Interval between the tracking is received and the frame is displayed on the client, used by
+    // the server side pose prediction
+    pub fn pose_prediction_interval(&self) -> Duration {
+        self.pose_prediction_latency_average.get_average()
+    }
+
+    // Used in place of tracker_pose_time_offset() when the poses are predicted by the server. The
+    // game requests the poses for the time it presents the frame, so SteamVR would extrapolate the
+    // already predicted poses by this interval.
+    pub fn measured_tracker_pose_time_offset(&self) -> Duration {
+        self.game_time_latency_average.get_average()
+    }
+
     // NB: this call is non-blocking, waiting should be done externally
     pub fn duration_until_next_vsync(&mut self) -> Duration {
         let now = Instant::now();
@@ -0,0 +1,82 @@
+use crate::{logging_backend, FILESYSTEM_LAYOUT};
+use alvr_common::{prelude::*, ALVR_VERSION};
+use alvr_events::StatisticsRecord;
+use chrono::Local;
+use std::{
+    fs::{self, File},
+    io::{BufWriter, Write},
+    time::{Duration, Instant},
+};
+
+// Writes a compact binary file per streaming session. Recordings can be converted to CSV and
+// summarized with alvr_cli
+pub struct StatisticsRecorder {
+    writer: BufWriter<File>,
+    start_instant: Instant,
+    failed: bool,
+}
+
+impl StatisticsRecorder {
+    pub fn new(client_hostname: &str, nominal_frame_interval: Duration) -> StrResult<Self> {
+        let dir = FILESYSTEM_LAYOUT.statistics_recordings_dir();
+        fs::create_dir_all(&dir).map_err(err!())?;
+
+        let file_name = format!(
+            "{}_{}.alvrstats",
+            Local::now().format("%Y-%m-%d_%H-%M-%S"),
+            logging_backend::hostname_file_label(client_hostname)
+        );
+        let file = File::create(dir.join(file_name)).map_err(err!())?;
+
+        let mut recorder = Self {
+            writer: BufWriter::new(file),
+            start_instant: Instant::now(),
+            failed: false,
+        };
+        recorder.record(StatisticsRecord::Start {
+            alvr_version: ALVR_VERSION.to_string(),
+            client_hostname: client_hostname.to_owned(),
+            nominal_frame_interval_s: nominal_frame_interval.as_secs_f32(),
+        });
+
+        Ok(recorder)
+    }
+
+    pub fn time_s(&self) -> f64 {
+        self.start_instant.elapsed().as_secs_f64()
+    }
+
+    // After the first error the recording is stopped, so a full disk does not spam the log
+    fn on_error(&mut self, e: impl std::fmt::Display) {
+        warn!("Failed to record statistics, recording stopped: {e}");
+        self.failed = true;
+    }
+
+    pub fn record(&mut self, record: StatisticsRecord) {
+        if self.failed {
+            return;
+        }
+
+        if let Err(e) = bincode::serialize_into(&mut self.writer, &record) {
+            self.on_error(e);
+        }
+    }
+
+    pub fn record_target_bitrate(&mut self, bitrate_bps: u64) {
+        let time_s = self.time_s();
+        self.record(StatisticsRecord::TargetBitrate {
+            time_s,
+            bitrate_bps,
+        });
+    }
+
+    pub fn flush(&mut self) {
+        if self.failed {
+            return;
+        }
+
+        if let Err(e) = self.writer.flush() {
+            self.on_error(e);
+        }
+    }
+}
@@ -1,27 +1,61 @@
-use crate::{to_ffi_quat, FfiDeviceMotion, FfiHandSkeleton};
+use crate::{
+    motion_filter::MotionFilter, prediction::PosePredictor, to_ffi_quat, FfiDeviceMotion,
+    FfiHandSkeleton,
+};
 use alvr_common::{
     glam::{EulerRot, Quat, Vec3},
-    DeviceMotion, Pose, HEAD_ID, LEFT_HAND_ID, RIGHT_HAND_ID,
+    hash_string, DeviceMotion, Pose, HEAD_ID, LEFT_HAND_ID, RIGHT_HAND_ID,
 };
 use alvr_session::{
-    settings_schema::Switch, HeadsetDesc, PositionRecenteringMode, RotationRecenteringMode,
+    settings_schema::Switch, DeviceMotionConfig, HeadsetDesc, PositionRecenteringMode,
+    RotationRecenteringMode,
 };
 use std::{
     collections::HashMap,
     f32::consts::{FRAC_PI_2, PI},
+    time::Duration,
 };
 
-// todo: Move this struct to Settings and use it for every tracked device
+const DEG_TO_RAD: f32 = PI / 180.0;
+
 #[derive(Default)]
 struct MotionConfig {
     // Position offset applied after rotation offset
     pose_offset: Pose,
     linear_velocity_cutoff: f32,
     angular_velocity_cutoff: f32,
+    filter: Option<MotionFilter>,
+}
+
+impl MotionConfig {
+    fn new(config: &DeviceMotionConfig) -> Self {
+        let t = config.position_offset;
+        let r = config.rotation_offset;
+
+        Self {
+            pose_offset: Pose {
+                orientation: Quat::from_euler(
+                    EulerRot::XYZ,
+                    r[0] * DEG_TO_RAD,
+                    r[1] * DEG_TO_RAD,
+                    r[2] * DEG_TO_RAD,
+                ),
+                position: Vec3::new(t[0], t[1], t[2]),
+            },
+            linear_velocity_cutoff: config.linear_velocity_cutoff,
+            angular_velocity_cutoff: config.angular_velocity_cutoff * DEG_TO_RAD,
+            filter: if let Switch::Enabled(smoothing) = config.smoothing {
+                Some(MotionFilter::new(smoothing))
+            } else {
+                None
+            },
+        }
+    }
 }
 
 pub struct TrackingManager {
     device_motion_configs: HashMap<u64, MotionConfig>,
+    pose_predictor: Option<PosePredictor>,
     left_hand_skeleton_offset: Pose,
     right_hand_skeleton_offset: Pose,
     position_recentering_mode: PositionRecenteringMode,
@@ -32,51 +66,25 @@ pub struct TrackingManager {
 
 impl TrackingManager {
     pub fn new(settings: &HeadsetDesc) -> TrackingManager {
+        let controllers_enabled = matches!(settings.controllers, Switch::Enabled(_));
+
+        // The head and the controllers are always tracked, also without an entry
         let mut device_motion_configs = HashMap::new();
         device_motion_configs.insert(*HEAD_ID, MotionConfig::default());
+        if controllers_enabled {
+            device_motion_configs.insert(*LEFT_HAND_ID, MotionConfig::default());
+            device_motion_configs.insert(*RIGHT_HAND_ID, MotionConfig::default());
+        }
+        for (path, config) in &settings.device_motions {
+            let id = hash_string(path);
+            if controllers_enabled || (id != *LEFT_HAND_ID && id != *RIGHT_HAND_ID) {
+                device_motion_configs.insert(id, MotionConfig::new(config));
+            }
+        }
 
         let left_hand_skeleton_offset;
         let right_hand_skeleton_offset;
         if let Switch::Enabled(controllers) = &settings.controllers {
-            const DEG_TO_RAD: f32 = PI / 180.0;
-
-            let t = controllers.left_controller_position_offset;
-            let r = controllers.left_controller_rotation_offset;
-
-            device_motion_configs.insert(
-                *LEFT_HAND_ID,
-                MotionConfig {
-                    pose_offset: Pose {
-                        orientation: Quat::from_euler(
-                            EulerRot::XYZ,
-                            r[0] * DEG_TO_RAD,
-                            r[1] * DEG_TO_RAD,
-                            r[2] * DEG_TO_RAD,
-                        ),
-                        position: Vec3::new(t[0], t[1], t[2]),
-                    },
-                    linear_velocity_cutoff: controllers.linear_velocity_cutoff,
-                    angular_velocity_cutoff: controllers.angular_velocity_cutoff * DEG_TO_RAD,
-                },
-            );
-
-            device_motion_configs.insert(
-                *RIGHT_HAND_ID,
-                MotionConfig {
-                    pose_offset: Pose {
-                        orientation: Quat::from_euler(
-                            EulerRot::XYZ,
-                            r[0] * DEG_TO_RAD,
-                            -r[1] * DEG_TO_RAD,
-                            -r[2] * DEG_TO_RAD,
-                        ),
-                        position: Vec3::new(-t[0], t[1], t[2]),
-                    },
-                    linear_velocity_cutoff: controllers.linear_velocity_cutoff,
-                    angular_velocity_cutoff: controllers.angular_velocity_cutoff * DEG_TO_RAD,
-                },
-            );
-
             let t = controllers.left_hand_tracking_position_offset;
             let r = controllers.left_hand_tracking_rotation_offset;
 
@@ -105,6 +113,11 @@ impl TrackingManager {
 
         TrackingManager {
             device_motion_configs,
+            pose_predictor: if let Switch::Enabled(config) = &settings.pose_prediction {
+                Some(PosePredictor::new(config))
+            } else {
+                None
+            },
             left_hand_skeleton_offset,
             right_hand_skeleton_offset,
             position_recentering_mode: settings.position_recentering_mode,
@@ -114,6 +127,26 @@ impl TrackingManager {
         }
     }
 
+    // Rebuild the device configs from new settings. The recentering origin is kept, unless the
+    // recentering modes changed, in which case it is recomputed from the last head pose.
+    pub fn update_settings(&mut self, settings: &HeadsetDesc) {
+        let recentering_changed = settings.position_recentering_mode
+            != self.position_recentering_mode
+            || settings.rotation_recentering_mode != self.rotation_recentering_mode;
+
+        // The pose prediction requires a SteamVR restart, its state is kept
+        *self = TrackingManager {
+            pose_predictor: self.pose_predictor.take(),
+            last_head_pose: self.last_head_pose,
+            recentering_origin: self.recentering_origin,
+            ..TrackingManager::new(settings)
+        };
+
+        if recentering_changed {
+            self.recenter();
+        }
+    }
+
     pub fn recenter(&mut self) {
         self.recentering_origin.position = match self.position_recentering_mode {
             PositionRecenteringMode::Disabled => Vec3::ZERO,
@@ -153,66 +186,86 @@ impl TrackingManager {
         }
     }
 
-    // Performs all kinds of tracking transformations, driven by settings.
+    // Performs all kinds of tracking transformations, driven by settings. The timestamp is the
+    // target timestamp of the tracking sample.
     pub fn transform_motions(
         &mut self,
         device_motions: &[(u64, DeviceMotion)],
         hand_skeletons_enabled: [bool; 2],
+        timestamp: Duration,
+        prediction: Duration,
     ) -> Vec<(u64, DeviceMotion)> {
+        // Trackers without a device_motions entry are recentered and predicted, without offsets
+        let mut unconfigured = MotionConfig::default();
+
         let mut transformed_motions = vec![];
         for &(device_id, mut motion) in device_motions {
             if device_id == *HEAD_ID {
                 self.last_head_pose = motion.pose;
             }
 
-            if let Some(config) = self.device_motion_configs.get(&device_id) {
-                // Recenter
-                motion.pose = self.recenter_pose(motion.pose);
+            // Recenter
+            let recentered_pose = self.recenter_pose(motion.pose);
+            let inverse_origin_orientation = self.recentering_origin.orientation.conjugate();
 
-                let inverse_origin_orientation = self.recentering_origin.orientation.conjugate();
-                motion.linear_velocity = inverse_origin_orientation * motion.linear_velocity;
-                motion.angular_velocity = inverse_origin_orientation * motion.angular_velocity;
+            let config = match self.device_motion_configs.get_mut(&device_id) {
+                Some(config) => config,
+                // The controllers have always an entry, unless they are disabled
+                None if device_id == *LEFT_HAND_ID || device_id == *RIGHT_HAND_ID => continue,
+                None => &mut unconfigured,
+            };
 
-                // Apply custom transform
-                let pose_offset = if device_id == *LEFT_HAND_ID && hand_skeletons_enabled[0] {
-                    self.left_hand_skeleton_offset
-                } else if device_id == *RIGHT_HAND_ID && hand_skeletons_enabled[1] {
-                    self.right_hand_skeleton_offset
-                } else {
-                    config.pose_offset
-                };
-                motion.pose.orientation *= pose_offset.orientation;
-                motion.pose.position += motion.pose.orientation * pose_offset.position;
+            motion.pose = recentered_pose;
+            motion.linear_velocity = inverse_origin_orientation * motion.linear_velocity;
+            motion.angular_velocity = inverse_origin_orientation * motion.angular_velocity;
 
-                motion.linear_velocity += motion
-                    .angular_velocity
-                    .cross(motion.pose.orientation * pose_offset.position);
-                motion.angular_velocity =
-                    motion.pose.orientation.conjugate() * motion.angular_velocity;
-
-                fn cutoff(v: Vec3, threshold: f32) -> Vec3 {
-                    if v.length_squared() > threshold * threshold {
-                        v
-                    } else {
-                        Vec3::ZERO
-                    }
-                }
+            // Smooth before the offset, so the offset does not amplify the rotation jitter
+            if let Some(filter) = &mut config.filter {
+                motion = filter.filter(motion, timestamp);
+            }
+
+            if let Some(predictor) = &mut self.pose_predictor {
+                motion = predictor.predict(device_id, motion, timestamp, prediction);
+            }
+
+            // Apply custom transform
+            let pose_offset = if device_id == *LEFT_HAND_ID && hand_skeletons_enabled[0] {
+                self.left_hand_skeleton_offset
+            } else if device_id == *RIGHT_HAND_ID && hand_skeletons_enabled[1] {
+                self.right_hand_skeleton_offset
+            } else {
+                config.pose_offset
+            };
+            motion.pose.orientation *= pose_offset.orientation;
+            motion.pose.position += motion.pose.orientation * pose_offset.position;
 
-                if (device_id == *LEFT_HAND_ID && hand_skeletons_enabled[0])
-                    || (device_id == *RIGHT_HAND_ID && hand_skeletons_enabled[1])
-                {
-                    // On hand tracking, velocities seem to make hands overly jittery
-                    motion.linear_velocity = Vec3::ZERO;
-                    motion.angular_velocity = Vec3::ZERO;
+            motion.linear_velocity += motion
+                .angular_velocity
+                .cross(motion.pose.orientation * pose_offset.position);
+            motion.angular_velocity = motion.pose.orientation.conjugate() * motion.angular_velocity;
+
+            fn cutoff(v: Vec3, threshold: f32) -> Vec3 {
+                if v.length_squared() > threshold * threshold {
+                    v
                 } else {
-                    motion.linear_velocity =
-                        cutoff(motion.linear_velocity, config.linear_velocity_cutoff);
-                    motion.angular_velocity =
-                        cutoff(motion.angular_velocity, config.angular_velocity_cutoff);
+                    Vec3::ZERO
                 }
+            }
 
-                transformed_motions.push((device_id, motion));
+            if (device_id == *LEFT_HAND_ID && hand_skeletons_enabled[0])
+                || (device_id == *RIGHT_HAND_ID && hand_skeletons_enabled[1])
+            {
+                // On hand tracking, velocities seem to make hands overly jittery
+                motion.linear_velocity = Vec3::ZERO;
+                motion.angular_velocity = Vec3::ZERO;
+            } else {
+                motion.linear_velocity =
+                    cutoff(motion.linear_velocity, config.linear_velocity_cutoff);
+                motion.angular_velocity =
+                    cutoff(motion.angular_velocity, config.angular_velocity_cutoff);
             }
+
+            transformed_motions.push((device_id, motion));
         }
 
         transformed_motions
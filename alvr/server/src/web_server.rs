@@ -1,20 +1,28 @@
 use crate::{
-    DECODER_CONFIG, FILESYSTEM_LAYOUT, SERVER_DATA_MANAGER, VIDEO_MIRROR_SENDER,
-    VIDEO_RECORDING_FILE,
+    diagnostics, frame_tracing::FrameTracer, rest_api, DECODER_CONFIG, FILESYSTEM_LAYOUT,
+    FRAME_TRACER, METRICS, SERVER_DATA_MANAGER, VIDEO_MIRROR_SENDER, VIDEO_RECORDING_FILE,
 };
 use alvr_common::{log, prelude::*};
-use alvr_events::{Event, EventType};
-use alvr_packets::ServerRequest;
+use alvr_events::{Event, EventFilter, EventType};
+use alvr_packets::{ServerRequest, SessionChangeOrigin, SESSION_CHANGE_ORIGIN_HEADER};
 use bytes::Buf;
-use futures::SinkExt;
+use chrono::Local;
+use futures::{Future, SinkExt, StreamExt};
 use headers::HeaderMapExt;
 use hyper::{
-    header::{HeaderValue, ACCESS_CONTROL_ALLOW_ORIGIN, CACHE_CONTROL, CONTENT_TYPE},
-    service, Body, Request, Response, StatusCode,
+    header::{HeaderValue, AUTHORIZATION, CACHE_CONTROL, CONTENT_TYPE, HOST, ORIGIN},
+    service,
+    upgrade::Upgraded,
+    Body, Request, Response, StatusCode,
 };
 use serde::de::DeserializeOwned;
 use serde_json as json;
-use std::net::SocketAddr;
+use std::{
+    collections::HashMap,
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+    time::Instant,
+};
 use tokio::sync::broadcast::{self, error::RecvError};
 use tokio_tungstenite::{tungstenite::protocol, WebSocketStream};
 use tokio_util::codec::{BytesCodec, FramedRead};
@@ -28,7 +36,7 @@ fn reply(code: StatusCode) -> StrResult<Response<Body>> {
         .map_err(err!())
 }
 
-async fn from_request_body<T: DeserializeOwned>(request: Request<Body>) -> StrResult<T> {
+pub async fn from_request_body<T: DeserializeOwned>(request: Request<Body>) -> StrResult<T> {
     json::from_reader(
         hyper::body::aggregate(request)
             .await
@@ -38,39 +46,163 @@ async fn from_request_body<T: DeserializeOwned>(request: Request<Body>) -> StrRe
     .map_err(err!())
 }
 
-async fn websocket<T: Clone + Send + 'static>(
+// The token can be passed as "Authorization: Bearer <token>" or, since browsers cannot set headers
+// when opening a websocket, as the "token" query parameter.
+fn is_authorized(request: &Request<Body>, token: &str) -> bool {
+    let header_token = request
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    let query_token = request.uri().query().and_then(|query| {
+        query
+            .split('&')
+            .find_map(|pair| pair.strip_prefix("token="))
+    });
+
+    // An empty token would accept any request
+    !token.is_empty()
+        && (header_token.map_or(false, |t| constant_time_eq(t, token))
+            || query_token.map_or(false, |t| constant_time_eq(t, token)))
+}
+
+// Compares all bytes regardless of where the first difference is, so the response time does not
+// leak the token
+fn constant_time_eq(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0, |acc, (a, b)| acc | (a ^ b))
+            == 0
+}
+
+// Browsers always send the Origin header for cross-origin requests. Only the pages served by this
+// web server are allowed. Requests from non-browser clients usually have no Origin header.
+fn is_same_origin(request: &Request<Body>) -> bool {
+    let headers = request.headers();
+    match (headers.get(ORIGIN), headers.get(HOST)) {
+        (None, _) => true,
+        (Some(origin), Some(host)) => {
+            origin
+                .to_str()
+                .ok()
+                .and_then(|origin| origin.strip_prefix("http://"))
+                == host.to_str().ok()
+        }
+        (Some(_), None) => false,
+    }
+}
+
+// Forward all data from a broadcast channel
+async fn broadcast_to_websocket<T: Clone>(
+    mut ws: WebSocketStream<Upgraded>,
+    mut data_receiver: broadcast::Receiver<T>,
+    message_builder: impl Fn(T) -> protocol::Message,
+) {
+    loop {
+        match data_receiver.recv().await {
+            Ok(data) => {
+                if let Err(e) = ws.send(message_builder(data)).await {
+                    info!("Failed to send data with websocket: {e}");
+                    break;
+                }
+
+                ws.flush().await.ok();
+            }
+            Err(RecvError::Lagged(_)) => {
+                warn!("Some data has been lost because the buffer is full");
+            }
+            Err(RecvError::Closed) => break,
+        }
+    }
+
+    ws.close(None).await.ok();
+}
+
+// Forward events that pass the subscriber's filter. The filter is received as a text message and
+// can be replaced at any time. Instead of logging a warning, which would generate more events, a
+// lag notice is sent only to the subscriber that is lagging behind.
+async fn events_to_websocket(
+    mut ws: WebSocketStream<Upgraded>,
+    mut events_receiver: broadcast::Receiver<Event>,
+) {
+    let mut filter = EventFilter::default();
+    let mut last_sent_instants = HashMap::new();
+
+    loop {
+        // The lag notice is always sent, regardless of the filter and the rate limits
+        let (event, is_lag_notice) = tokio::select! {
+            message = ws.next() => match message {
+                Some(Ok(protocol::Message::Text(text))) => {
+                    match json::from_str(&text) {
+                        Ok(new_filter) => filter = new_filter,
+                        Err(e) => info!("Invalid event filter: {e}"),
+                    }
+
+                    continue;
+                }
+                Some(Ok(protocol::Message::Close(_)) | Err(_)) | None => break,
+                Some(Ok(_)) => continue,
+            },
+            res = events_receiver.recv() => match res {
+                Ok(event) => (event, false),
+                Err(RecvError::Lagged(count)) => {
+                    let notice = Event {
+                        timestamp: Local::now().format("%H:%M:%S.%f").to_string(),
+                        event_type: EventType::Log(LogEntry {
+                            severity: LogSeverity::Warning,
+                            content: format!(
+                                "{count} events have been dropped because the subscriber is too \
+                                slow"
+                            ),
+                        }),
+                    };
+
+                    (notice, true)
+                }
+                Err(RecvError::Closed) => break,
+            },
+        };
+
+        if !is_lag_notice {
+            if !filter.matches(&event.event_type) {
+                continue;
+            }
+
+            if let Some(min_interval) = filter.min_interval(&event.event_type) {
+                let now = Instant::now();
+                let id = event.event_type.id();
+
+                if matches!(last_sent_instants.get(id), Some(last) if now < *last + min_interval) {
+                    continue;
+                }
+                last_sent_instants.insert(id, now);
+            }
+        }
+
+        let message = protocol::Message::Text(json::to_string(&event).unwrap());
+        if let Err(e) = ws.send(message).await {
+            info!("Failed to send event with websocket: {e}");
+            break;
+        }
+    }
+
+    ws.close(None).await.ok();
+}
+
+async fn websocket<F: Future<Output = ()> + Send + 'static>(
     request: Request<Body>,
-    sender: broadcast::Sender<T>,
-    message_builder: impl Fn(T) -> protocol::Message + Send + Sync + 'static,
+    handler: impl FnOnce(WebSocketStream<Upgraded>) -> F + Send + 'static,
 ) -> StrResult<Response<Body>> {
     if let Some(key) = request.headers().typed_get::<headers::SecWebsocketKey>() {
         tokio::spawn(async move {
             match hyper::upgrade::on(request).await {
                 Ok(upgraded) => {
-                    let mut data_receiver = sender.subscribe();
-
-                    let mut ws =
+                    let ws =
                         WebSocketStream::from_raw_socket(upgraded, protocol::Role::Server, None)
                             .await;
 
-                    loop {
-                        match data_receiver.recv().await {
-                            Ok(data) => {
-                                if let Err(e) = ws.send(message_builder(data)).await {
-                                    info!("Failed to send log with websocket: {e}");
-                                    break;
-                                }
-
-                                ws.flush().await.ok();
-                            }
-                            Err(RecvError::Lagged(_)) => {
-                                warn!("Some log lines have been lost because the buffer is full");
-                            }
-                            Err(RecvError::Closed) => break,
-                        }
-                    }
-
-                    ws.close(None).await.ok();
+                    handler(ws).await;
                 }
                 Err(e) => error!("{e}"),
             }
@@ -92,13 +224,31 @@ async fn websocket<T: Clone + Send + 'static>(
     }
 }
 
-async fn http_api(
-    request: Request<Body>,
-    events_sender: broadcast::Sender<Event>,
-) -> StrResult<Response<Body>> {
+async fn http_api(request: Request<Body>, token: &str) -> StrResult<Response<Body>> {
+    if !is_same_origin(&request) {
+        return reply(StatusCode::FORBIDDEN);
+    }
+
+    let path = request.uri().path();
+    if path.starts_with("/api/") && path != "/api/ping" && !is_authorized(&request, token) {
+        return reply(StatusCode::UNAUTHORIZED);
+    }
+
     let mut response = match request.uri().path() {
-        // New unified requests
+        // Used by the dashboard and the CLI. Data is sent back through events. Prefer the /api/v1/
+        // endpoints
         "/api/dashboard-request" => {
+            // Callers can only identify as the dashboard or the CLI
+            let origin = match request
+                .headers()
+                .get(SESSION_CHANGE_ORIGIN_HEADER)
+                .and_then(|value| value.to_str().ok())
+            {
+                Some("Dashboard") => SessionChangeOrigin::Dashboard,
+                Some("Cli") => SessionChangeOrigin::Cli,
+                _ => SessionChangeOrigin::HttpApi,
+            };
+
             if let Ok(request) = from_request_body::<ServerRequest>(request).await {
                 match request {
                     ServerRequest::Log(event) => {
@@ -111,10 +261,25 @@ async fn http_api(
                         )));
                     }
                     ServerRequest::UpdateSession(session) => {
-                        *SERVER_DATA_MANAGER.write().session_mut() = *session
+                        SERVER_DATA_MANAGER.write().update_session(*session, origin)
                     }
                     ServerRequest::SetValues(descs) => {
-                        SERVER_DATA_MANAGER.write().set_values(descs).ok();
+                        SERVER_DATA_MANAGER.write().set_values(descs, origin).ok();
+                    }
+                    ServerRequest::GetSessionHistory => {
+                        alvr_events::send_event(EventType::SessionHistory(
+                            SERVER_DATA_MANAGER.read().session_history(),
+                        ));
+                    }
+                    ServerRequest::UndoSessionChange => {
+                        if let Err(e) = SERVER_DATA_MANAGER.write().undo_session_change() {
+                            warn!("Failed to undo session change: {e}");
+                        }
+                    }
+                    ServerRequest::RestoreSessionSnapshot(id) => {
+                        if let Err(e) = SERVER_DATA_MANAGER.write().restore_session_snapshot(id) {
+                            error!("Failed to restore session snapshot: {e}");
+                        }
                     }
                     ServerRequest::UpdateClientList { hostname, action } => SERVER_DATA_MANAGER
                         .write()
@@ -128,6 +293,17 @@ async fn http_api(
                     ServerRequest::InsertIdr => unsafe { crate::RequestIDR() },
                     ServerRequest::StartRecording => crate::create_recording_file(),
                     ServerRequest::StopRecording => *VIDEO_RECORDING_FILE.lock() = None,
+                    ServerRequest::StartFrameTracing => {
+                        *FRAME_TRACER.lock() = Some(FrameTracer::new())
+                    }
+                    ServerRequest::StopFrameTracing => {
+                        if let Some(tracer) = FRAME_TRACER.lock().take() {
+                            match tracer.save() {
+                                Ok(path) => info!("Frame trace saved to {}", path.display()),
+                                Err(e) => error!("Failed to save frame trace: {e}"),
+                            }
+                        }
+                    }
                     ServerRequest::FirewallRules(action) => {
                         if alvr_server_io::firewall_rules(action).is_ok() {
                             info!("Setting firewall rules succeeded!");
@@ -158,6 +334,18 @@ async fn http_api(
                             alvr_events::send_event(EventType::DriversList(list));
                         }
                     }
+                    ServerRequest::GetLogFiles => {
+                        match alvr_server_io::get_log_files(&FILESYSTEM_LAYOUT.logs_dir()) {
+                            Ok(list) => alvr_events::send_event(EventType::LogFiles(list)),
+                            Err(e) => error!("Failed to list log files: {e}"),
+                        }
+                    }
+                    ServerRequest::CreateDiagnosticsBundle => {
+                        match diagnostics::save_diagnostics_bundle() {
+                            Ok(path) => info!("Diagnostics bundle saved to {}", path.display()),
+                            Err(e) => error!("Failed to create diagnostics bundle: {e}"),
+                        }
+                    }
                     ServerRequest::RestartSteamvr => crate::notify_restart_driver(),
                     ServerRequest::ShutdownSteamvr => crate::notify_shutdown_driver(),
                 }
@@ -167,9 +355,12 @@ async fn http_api(
                 reply(StatusCode::BAD_REQUEST)?
             }
         }
+        uri if uri.starts_with(rest_api::API_V1_PREFIX) => {
+            rest_api::handle_request(request).await?
+        }
         "/api/events" => {
-            websocket(request, events_sender, |e| {
-                protocol::Message::Text(json::to_string(&e).unwrap())
+            websocket(request, move |ws| {
+                events_to_websocket(ws, alvr_events::subscribe())
             })
             .await?
         }
@@ -190,13 +381,21 @@ async fn http_api(
                 sender.send(config.config_buffer.clone()).ok();
             }
 
-            let res = websocket(request, sender, protocol::Message::Binary).await?;
+            let res = websocket(request, move |ws| {
+                broadcast_to_websocket(ws, sender.subscribe(), protocol::Message::Binary)
+            })
+            .await?;
 
             unsafe { crate::RequestIDR() };
 
             res
         }
         "/api/ping" => reply(StatusCode::OK)?,
+        // Read only. Not under /api/ so it can be scraped without the token
+        "/metrics" => Response::builder()
+            .header(CONTENT_TYPE, "text/plain; version=0.0.4")
+            .body(METRICS.lock().encode().into())
+            .map_err(err!())?,
         other_uri => {
             if other_uri.contains("..") {
                 // Attempted tree traversal
@@ -232,31 +431,40 @@ async fn http_api(
         }
     };
 
+    // Note: Access-Control-Allow-Origin is not set, so browsers deny cross-origin reads
     response.headers_mut().insert(
         CACHE_CONTROL,
         HeaderValue::from_str("no-cache, no-store, must-revalidate").map_err(err!())?,
     );
-    response
-        .headers_mut()
-        .insert(ACCESS_CONTROL_ALLOW_ORIGIN, HeaderValue::from_static("*"));
 
     Ok(response)
 }
 
-pub async fn web_server(events_sender: broadcast::Sender<Event>) -> StrResult {
-    let web_server_port = SERVER_DATA_MANAGER
-        .read()
-        .settings()
-        .connection
-        .web_server_port;
+pub async fn web_server() -> StrResult {
+    let (web_server_port, bind_address) = {
+        let data_manager_lock = SERVER_DATA_MANAGER.read();
+        let connection = &data_manager_lock.settings().connection;
+
+        (
+            connection.web_server_port,
+            connection.web_server_bind_address.clone(),
+        )
+    };
+    let bind_address = bind_address
+        .parse::<IpAddr>()
+        .map_err(|e| format!("Invalid web server bind address \"{bind_address}\": {e}"))?;
+
+    let token = Arc::new(alvr_server_io::load_or_create_web_server_token(
+        &FILESYSTEM_LAYOUT.web_server_token(),
+    )?);
 
     let service = service::make_service_fn(|_| {
-        let events_sender = events_sender.clone();
+        let token = Arc::clone(&token);
         async move {
             StrResult::Ok(service::service_fn(move |request| {
-                let events_sender = events_sender.clone();
+                let token = Arc::clone(&token);
                 async move {
-                    let res = http_api(request, events_sender).await;
+                    let res = http_api(request, &token).await;
                     if let Err(e) = &res {
                         alvr_common::show_e(e);
                     }
@@ -267,11 +475,8 @@ pub async fn web_server(events_sender: broadcast::Sender<Event>) -> StrResult {
         }
     });
 
-    hyper::Server::bind(&SocketAddr::new(
-        "0.0.0.0".parse().unwrap(),
-        web_server_port,
-    ))
-    .serve(service)
-    .await
-    .map_err(err!())
+    hyper::Server::bind(&SocketAddr::new(bind_address, web_server_port))
+        .serve(service)
+        .await
+        .map_err(err!())
 }
@@ -0,0 +1,194 @@
+use crate::ServerDataManager;
+use alvr_common::{prelude::*, ALVR_VERSION};
+use alvr_events::Event;
+use alvr_session::SessionDesc;
+use chrono::Local;
+use regex::Regex;
+use serde_json as json;
+use std::{
+    fs,
+    io::{Cursor, Write},
+    path::{Path, PathBuf},
+};
+use zip::{write::FileOptions, CompressionMethod, ZipWriter};
+
+// Only the most recent log files are included, to keep the bundle small
+const MAX_BUNDLE_LOG_FILES: usize = 3;
+
+const REDACTED: &str = "<redacted>";
+
+// Client hostnames and IPs are replaced in every file of the bundle. Hostnames get a stable
+// placeholder so they can still be matched between the session and the logs
+struct Redactor {
+    ipv4_regex: Regex,
+    // Only the full and the "::" compressed forms, so that times like 12:34:56 are not matched
+    ipv6_regex: Regex,
+    replacements: Vec<(String, String)>,
+}
+
+impl Redactor {
+    fn new(session: &SessionDesc) -> Self {
+        let mut replacements = vec![];
+        for (index, (hostname, connection)) in session.client_connections.iter().enumerate() {
+            replacements.push((hostname.clone(), format!("<client {}>", index + 1)));
+            for ip in connection.current_ip.iter().chain(&connection.manual_ips) {
+                replacements.push((ip.to_string(), REDACTED.into()));
+            }
+        }
+        // Longer strings first, in case a hostname contains another one
+        replacements.sort_by_key(|(from, _)| std::cmp::Reverse(from.len()));
+
+        Self {
+            ipv4_regex: Regex::new(r"\b(?:\d{1,3}\.){3}\d{1,3}\b").unwrap(),
+            ipv6_regex: Regex::new(
+                r"(?i)\b(?:[0-9a-f]{1,4}:){7}[0-9a-f]{1,4}\b|\b(?:[0-9a-f]{1,4}:){1,6}(?::[0-9a-f]{1,4}){1,6}\b",
+            )
+            .unwrap(),
+            replacements,
+        }
+    }
+
+    fn redact(&self, text: &str) -> String {
+        let mut text = text.to_owned();
+        for (from, to) in &self.replacements {
+            text = text.replace(from, to);
+        }
+
+        let text = self.ipv4_regex.replace_all(&text, REDACTED);
+
+        self.ipv6_regex.replace_all(&text, REDACTED).into_owned()
+    }
+}
+
+// The session is otherwise left untouched, so it can be loaded to reproduce the issue
+fn sanitized_session_json(session: &SessionDesc, redactor: &Redactor) -> StrResult<String> {
+    let session_json = json::to_string_pretty(session).map_err(err!())?;
+
+    Ok(redactor.redact(&session_json))
+}
+
+fn system_info_json(data_manager: &ServerDataManager) -> StrResult<String> {
+    // Failures are recorded in the bundle, since they can be relevant too
+    let registered_drivers = match crate::get_registered_drivers() {
+        Ok(list) => json::to_value(list).map_err(err!())?,
+        Err(e) => json::Value::String(format!("Error: {e}")),
+    };
+    let audio_devices = match data_manager.get_audio_devices_list() {
+        Ok(list) => json::to_value(list).map_err(err!())?,
+        Err(e) => json::Value::String(format!("Error: {e}")),
+    };
+
+    json::to_string_pretty(&json::json!({
+        "version": ALVR_VERSION.to_string(),
+        "protocol_id": format!("{:#x}", alvr_common::protocol_id()),
+        "os": std::env::consts::OS,
+        "gpu_names": data_manager.get_gpu_names(),
+        "gpu_vendors": data_manager.get_gpu_vendors(),
+        "registered_drivers": registered_drivers,
+        "audio_devices": audio_devices,
+    }))
+    .map_err(err!())
+}
+
+// Returns the zip file content. statistics_history is empty if the streamer is not running
+pub fn create_diagnostics_bundle(
+    data_manager: &ServerDataManager,
+    logs_dir: &Path,
+    crash_log_path: &Path,
+    statistics_history: &[Event],
+) -> StrResult<Vec<u8>> {
+    let mut zip = ZipWriter::new(Cursor::new(vec![]));
+    let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+
+    let mut add_file = |name: &str, content: &[u8]| -> StrResult {
+        zip.start_file(name, options).map_err(err!())?;
+        zip.write_all(content).map_err(err!())
+    };
+
+    let redactor = Redactor::new(data_manager.session());
+
+    add_file(
+        "session.json",
+        sanitized_session_json(data_manager.session(), &redactor)?.as_bytes(),
+    )?;
+    add_file(
+        "system_info.json",
+        system_info_json(data_manager)?.as_bytes(),
+    )?;
+    add_file(
+        "statistics_history.json",
+        json::to_string_pretty(statistics_history)
+            .map_err(err!())?
+            .as_bytes(),
+    )?;
+
+    let log_files = match crate::get_log_files(logs_dir) {
+        Ok(log_files) => log_files,
+        Err(e) => {
+            add_file(
+                "logs/error.txt",
+                format!("Failed to list log files: {e}").as_bytes(),
+            )?;
+            vec![]
+        }
+    };
+    for log_file in log_files.iter().take(MAX_BUNDLE_LOG_FILES) {
+        // An unreadable file should not prevent exporting the rest of the bundle
+        let content = match crate::read_log_file(logs_dir, &log_file.name) {
+            Ok(content) => redactor.redact(&String::from_utf8_lossy(&content)),
+            Err(e) => format!("Failed to read log file: {e}"),
+        };
+        add_file(&format!("logs/{}", log_file.name), content.as_bytes())?;
+    }
+
+    if crash_log_path.exists() {
+        let content = match fs::read(crash_log_path) {
+            Ok(content) => redactor.redact(&String::from_utf8_lossy(&content)),
+            Err(e) => format!("Failed to read crash log: {e}"),
+        };
+        add_file("crash_log.txt", content.as_bytes())?;
+    }
+
+    Ok(zip.finish().map_err(err!())?.into_inner())
+}
+
+pub fn save_diagnostics_bundle(bundle: &[u8], dir: &Path) -> StrResult<PathBuf> {
+    fs::create_dir_all(dir).map_err(err!())?;
+
+    let path = dir.join(format!(
+        "alvr_diagnostics_{}.zip",
+        Local::now().format("%Y-%m-%d_%H-%M-%S")
+    ));
+    fs::write(&path, bundle).map_err(err!())?;
+
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alvr_session::ClientConnectionDesc;
+
+    #[test]
+    fn redact_clients_and_ips() {
+        let mut session = SessionDesc::default();
+        session.client_connections.insert(
+            "1234.client.alvr".into(),
+            ClientConnectionDesc {
+                display_name: "Headset".into(),
+                current_ip: Some("192.168.1.5".parse().unwrap()),
+                manual_ips: Default::default(),
+                trusted: true,
+            },
+        );
+        let redactor = Redactor::new(&session);
+
+        assert_eq!(
+            redactor.redact(
+                "12:34:56 1234.client.alvr at 192.168.1.5, 10.0.0.2, fe80::1c2:3d4 and \
+                2001:DB8:0:0:8:800:200C:417A"
+            ),
+            "12:34:56 <client 1> at <redacted>, <redacted>, <redacted> and <redacted>"
+        );
+    }
+}
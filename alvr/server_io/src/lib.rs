@@ -1,17 +1,29 @@
+mod diagnostics;
 mod firewall;
+mod log_files;
 mod openvr_drivers;
 mod openvrpaths;
+mod session_file;
+mod session_history;
+mod web_server_token;
 
+pub use diagnostics::*;
 pub use firewall::*;
+pub use log_files::*;
 pub use openvr_drivers::*;
 pub use openvrpaths::*;
+pub use web_server_token::*;
 
 use alvr_common::prelude::*;
 use alvr_events::EventType;
-use alvr_packets::{AudioDevicesList, ClientListAction, GpuVendor, PathSegment, PathValuePair};
+use alvr_packets::{
+    AudioDevicesList, ClientListAction, GpuVendor, PathSegment, PathValuePair, SessionChangeOrigin,
+    SessionHistoryEntry,
+};
 use alvr_session::{ClientConnectionDesc, SessionDesc, Settings};
-use cpal::traits::{DeviceTrait, HostTrait};
 use serde_json as json;
+use session_file::{FileStamp, SessionFileLock};
+use session_history::SessionHistory;
 use std::{
     collections::{hash_map::Entry, HashMap},
     fs,
@@ -20,55 +32,60 @@ use std::{
 };
 use wgpu::AdapterInfo;
 
-fn save_session(session: &SessionDesc, path: &Path) -> StrResult {
-    fs::write(path, json::to_string_pretty(session).map_err(err!())?).map_err(err!())
-}
-
 // SessionDesc wrapper that saves settings.json and session.json on destruction.
 pub struct SessionLock<'a> {
-    session_desc: &'a mut SessionDesc,
-    session_path: &'a Path,
-    settings: &'a mut Settings,
+    data_manager: &'a mut ServerDataManager,
+    origin: SessionChangeOrigin,
+    _file_lock: Option<SessionFileLock>,
 }
 
 impl Deref for SessionLock<'_> {
     type Target = SessionDesc;
     fn deref(&self) -> &SessionDesc {
-        self.session_desc
+        &self.data_manager.session
     }
 }
 
 impl DerefMut for SessionLock<'_> {
     fn deref_mut(&mut self) -> &mut SessionDesc {
-        self.session_desc
+        &mut self.data_manager.session
     }
 }
 
 impl Drop for SessionLock<'_> {
     fn drop(&mut self) {
-        save_session(self.session_desc, self.session_path).unwrap();
-        *self.settings = self.session_desc.to_settings();
-        alvr_events::send_event(EventType::Session(Box::new(self.session_desc.clone())));
+        self.data_manager.commit_session(self.origin);
     }
 }
 
 // Correct usage:
 // SessionManager should be used behind a Mutex. Each write of the session should be preceded by a
 // read, within the same lock.
-// fixme: the dashboard is doing this wrong because it is holding its own session state. If read and
-// write need to happen on separate threads, a critical region should be implemented.
+// The dashboard and the driver can each hold a ServerDataManager for the same session file. Writes
+// are serialized with an advisory file lock and are always applied on top of the latest session on
+// disk. Call reload_if_changed() periodically to pick up changes made by the other process.
 pub struct ServerDataManager {
     session: SessionDesc,
     settings: Settings,
     session_path: PathBuf,
+    session_file_stamp: Option<FileStamp>,
     gpu_infos: Vec<AdapterInfo>,
+    history: SessionHistory,
+    settings_change_callback: Option<Box<dyn Fn() + Send + Sync>>,
 }
 
 impl ServerDataManager {
     pub fn new(session_path: &Path) -> Self {
         let config_dir = session_path.parent().unwrap();
         fs::create_dir_all(config_dir).ok();
-        let session_desc = Self::load_session(session_path, config_dir);
+
+        // The session could be migrated and written back
+        let file_lock = SessionFileLock::acquire(session_path)
+            .map_err(|e| error!("Failed to lock session file: {e}"))
+            .ok();
+        let (session_desc, load_origin) = Self::load_session(session_path, config_dir);
+        let session_file_stamp = session_file::file_stamp(session_path);
+        drop(file_lock);
 
         let vk_adapters: Vec<wgpu::Adapter> = wgpu::Instance::new(wgpu::InstanceDescriptor {
             backends: wgpu::Backends::VULKAN,
@@ -86,15 +103,19 @@ impl ServerDataManager {
             session: session_desc.clone(),
             settings: session_desc.to_settings(),
             session_path: session_path.to_owned(),
+            session_file_stamp,
             gpu_infos,
+            history: SessionHistory::new(&session_desc.session_settings, load_origin),
+            settings_change_callback: None,
         }
     }
 
-    fn load_session(session_path: &Path, config_dir: &Path) -> SessionDesc {
+    // The returned origin is used for the first session history entry
+    fn load_session(session_path: &Path, config_dir: &Path) -> (SessionDesc, SessionChangeOrigin) {
         let session_string = fs::read_to_string(session_path).unwrap_or_default();
 
         if session_string.is_empty() {
-            return SessionDesc::default();
+            return (SessionDesc::default(), SessionChangeOrigin::Internal);
         }
 
         let session_json = json::from_str::<json::Value>(&session_string)
@@ -111,10 +132,12 @@ impl ServerDataManager {
 
         if session_json.is_null() {
             fs::write(config_dir.join("session_invalid.json"), &session_string).ok();
-            return SessionDesc::default();
+            return (SessionDesc::default(), SessionChangeOrigin::Migration);
         }
 
-        json::from_value(session_json.clone()).unwrap_or_else(|_| {
+        if let Ok(session_desc) = json::from_value(session_json.clone()) {
+            (session_desc, SessionChangeOrigin::Internal)
+        } else {
             fs::write(config_dir.join("session_old.json"), &session_string).ok();
             let mut session_desc = SessionDesc::default();
             match session_desc.merge_from_json(&session_json) {
@@ -131,10 +154,113 @@ impl ServerDataManager {
                 ),
             }
             // not essential, but useful to avoid duplicated errors
-            save_session(&session_desc, session_path).ok();
+            session_file::save_session(&session_desc, session_path).ok();
 
-            session_desc
-        })
+            (session_desc, SessionChangeOrigin::Migration)
+        }
+    }
+
+    // Acquire the inter-process lock and make sure the in-memory session is up to date before it
+    // gets modified. If the lock cannot be acquired the write is still performed, but changes made
+    // by the other process at the same time could be lost.
+    fn lock_session_file(&mut self) -> Option<SessionFileLock> {
+        match SessionFileLock::acquire(&self.session_path) {
+            Ok(lock) => {
+                self.reload_locked();
+
+                Some(lock)
+            }
+            Err(e) => {
+                error!("Failed to lock session file: {e}");
+
+                None
+            }
+        }
+    }
+
+    fn save_session(&mut self) {
+        if let Err(e) = session_file::save_session(&self.session, &self.session_path) {
+            error!("Failed to save session: {e}");
+        }
+        self.session_file_stamp = session_file::file_stamp(&self.session_path);
+    }
+
+    fn commit_session(&mut self, origin: SessionChangeOrigin) {
+        self.settings = self.session.to_settings();
+        self.save_session();
+
+        alvr_events::send_event(EventType::Session(Box::new(self.session.clone())));
+
+        if self.history.record(&self.session.session_settings, origin) {
+            alvr_events::send_event(EventType::SessionHistory(self.history.entries()));
+            self.notify_settings_change();
+        }
+    }
+
+    fn notify_settings_change(&self) {
+        if let Some(callback) = &self.settings_change_callback {
+            callback();
+        }
+    }
+
+    // The callback is invoked every time session_settings change, after settings() has been
+    // updated. It is called with the data manager still locked, so it must not access it.
+    pub fn set_settings_change_callback(&mut self, callback: impl Fn() + Send + Sync + 'static) {
+        self.settings_change_callback = Some(Box::new(callback));
+    }
+
+    // Returns true if session.json has been modified by another process and has been reloaded
+    pub fn reload_if_changed(&mut self) -> bool {
+        // Cheap check first, the file is locked and read only if it changed
+        if !session_file::changed_since(&self.session_path, self.session_file_stamp) {
+            return false;
+        }
+
+        match SessionFileLock::acquire(&self.session_path) {
+            Ok(_lock) => self.reload_locked(),
+            Err(e) => {
+                warn!("Failed to lock session file, it will be reloaded later: {e}");
+
+                false
+            }
+        }
+    }
+
+    // Must be called with the session file locked, so the file is not partially written. The file
+    // is never written here. If it is invalid, the current session is kept and will overwrite the
+    // file at the next change.
+    fn reload_locked(&mut self) -> bool {
+        let Some((session_string, stamp)) = session_file::read_with_stamp(&self.session_path)
+        else {
+            return false;
+        };
+        if Some(stamp) == self.session_file_stamp {
+            return false;
+        }
+        self.session_file_stamp = Some(stamp);
+
+        let session_desc = match json::from_str::<SessionDesc>(&session_string) {
+            Ok(session_desc) => session_desc,
+            Err(e) => {
+                error!("Failed to reload session.json, keeping the current session: {e}");
+
+                return false;
+            }
+        };
+
+        self.session = session_desc;
+        self.settings = self.session.to_settings();
+
+        alvr_events::send_event(EventType::Session(Box::new(self.session.clone())));
+        if self.history.record(
+            &self.session.session_settings,
+            SessionChangeOrigin::External,
+        ) {
+            alvr_events::send_event(EventType::SessionHistory(self.history.entries()));
+            self.notify_settings_change();
+        }
+
+        true
     }
 
     // prefer settings()
@@ -143,19 +269,34 @@ impl ServerDataManager {
     }
 
     pub fn session_mut(&mut self) -> SessionLock {
+        let file_lock = self.lock_session_file();
+
         SessionLock {
-            session_desc: &mut self.session,
-            session_path: &self.session_path,
-            settings: &mut self.settings,
+            data_manager: self,
+            origin: SessionChangeOrigin::Internal,
+            _file_lock: file_lock,
         }
     }
 
+    pub fn update_session(&mut self, session: SessionDesc, origin: SessionChangeOrigin) {
+        let _file_lock = self.lock_session_file();
+
+        self.session = session;
+        self.commit_session(origin);
+    }
+
     pub fn settings(&self) -> &Settings {
         &self.settings
     }
 
     // Note: "value" can be any session subtree, in json format.
-    pub fn set_values(&mut self, descs: Vec<PathValuePair>) -> StrResult {
+    pub fn set_values(
+        &mut self,
+        descs: Vec<PathValuePair>,
+        origin: SessionChangeOrigin,
+    ) -> StrResult {
+        let _file_lock = self.lock_session_file();
+
         let mut session_json = serde_json::to_value(self.session.clone()).unwrap();
 
         for desc in descs {
@@ -191,10 +332,36 @@ impl ServerDataManager {
 
         // session_json has been updated
         self.session = serde_json::from_value(session_json).map_err(err!())?;
-        self.settings = self.session.to_settings();
+        self.commit_session(origin);
 
-        save_session(&self.session, &self.session_path).unwrap();
-        alvr_events::send_event(EventType::Session(Box::new(self.session.clone())));
+        Ok(())
+    }
+
+    pub fn session_history(&self) -> Vec<SessionHistoryEntry> {
+        self.history.entries()
+    }
+
+    // Revert the latest change to the session settings. The reverted entry is discarded.
+    pub fn undo_session_change(&mut self) -> StrResult {
+        let _file_lock = self.lock_session_file();
+
+        self.session.session_settings = self.history.undo()?;
+        self.commit_session(SessionChangeOrigin::Rollback);
+
+        // The history has only shrunk, so commit_session() did not report it
+        alvr_events::send_event(EventType::SessionHistory(self.history.entries()));
+        self.notify_settings_change();
+
+        Ok(())
+    }
+
+    // Roll back the session settings to a previous snapshot. This is recorded as a new change, so
+    // it can be undone.
+    pub fn restore_session_snapshot(&mut self, id: u64) -> StrResult {
+        let _file_lock = self.lock_session_file();
+
+        self.session.session_settings = self.history.snapshot(id)?;
+        self.commit_session(SessionChangeOrigin::Rollback);
 
         Ok(())
     }
@@ -204,6 +371,8 @@ impl ServerDataManager {
     }
 
     pub fn update_client_list(&mut self, hostname: String, action: ClientListAction) {
+        let _file_lock = self.lock_session_file();
+
         let mut client_connections = self.session.client_connections.clone();
 
         let maybe_client_entry = client_connections.entry(hostname);
@@ -268,7 +437,7 @@ impl ServerDataManager {
         if updated {
             self.session.client_connections = client_connections;
 
-            save_session(&self.session, &self.session_path).unwrap();
+            self.save_session();
             alvr_events::send_event(EventType::Session(Box::new(self.session.clone())));
         }
     }
@@ -293,28 +462,7 @@ impl ServerDataManager {
             .collect();
     }
 
-    #[cfg_attr(not(target_os = "linux"), allow(unused_variables))]
     pub fn get_audio_devices_list(&self) -> StrResult<AudioDevicesList> {
-        #[cfg(target_os = "linux")]
-        let host = match self.session.to_settings().audio.linux_backend {
-            alvr_session::LinuxAudioBackend::Alsa => cpal::host_from_id(cpal::HostId::Alsa),
-            alvr_session::LinuxAudioBackend::Jack => cpal::host_from_id(cpal::HostId::Jack),
-        }
-        .map_err(err!())?;
-        #[cfg(not(target_os = "linux"))]
-        let host = cpal::default_host();
-
-        let output = host
-            .output_devices()
-            .map_err(err!())?
-            .filter_map(|d| d.name().ok())
-            .collect::<Vec<_>>();
-        let input = host
-            .input_devices()
-            .map_err(err!())?
-            .filter_map(|d| d.name().ok())
-            .collect::<Vec<_>>();
-
-        Ok(AudioDevicesList { output, input })
+        alvr_audio_devices::devices_list(Some(self.session.to_settings().audio.linux_backend))
     }
 }
@@ -0,0 +1,46 @@
+use alvr_common::prelude::*;
+use alvr_packets::LogFileInfo;
+use std::{fs, path::Path, time::UNIX_EPOCH};
+
+// Sorted from the newest to the oldest. A missing directory means there are no logs yet
+pub fn get_log_files(dir: &Path) -> StrResult<Vec<LogFileInfo>> {
+    if !dir.exists() {
+        return Ok(vec![]);
+    }
+
+    let mut files = vec![];
+    for entry in fs::read_dir(dir).map_err(err!())? {
+        let entry = entry.map_err(err!())?;
+        let metadata = entry.metadata().map_err(err!())?;
+        if !metadata.is_file() {
+            continue;
+        }
+
+        let modified_unix_secs = metadata
+            .modified()
+            .ok()
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .map(|duration| duration.as_secs())
+            .unwrap_or(0);
+
+        files.push(LogFileInfo {
+            name: entry.file_name().to_string_lossy().into_owned(),
+            size_bytes: metadata.len(),
+            modified_unix_secs,
+        });
+    }
+
+    // File names start with the creation time, so they break ties
+    files.sort_by(|a, b| (b.modified_unix_secs, &b.name).cmp(&(a.modified_unix_secs, &a.name)));
+
+    Ok(files)
+}
+
+// Only files listed by get_log_files() can be read, so the name cannot point outside of dir
+pub fn read_log_file(dir: &Path, name: &str) -> StrResult<Vec<u8>> {
+    if !get_log_files(dir)?.iter().any(|file| file.name == name) {
+        return fmt_e!("Log file {name} not found");
+    }
+
+    fs::read(dir.join(name)).map_err(err!())
+}
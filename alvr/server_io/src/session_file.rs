@@ -0,0 +1,141 @@
+use alvr_common::prelude::*;
+use alvr_session::SessionDesc;
+use fs2::FileExt;
+use serde_json as json;
+use std::{
+    fs::{self, File, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+fn sibling_path(path: &Path, extension: &str) -> PathBuf {
+    let mut file_name = path.file_name().unwrap_or_default().to_owned();
+    file_name.push(extension);
+
+    path.with_file_name(file_name)
+}
+
+// Write to a temporary file first, then replace session.json with a rename. This way a crash
+// mid-write never leaves a truncated session file behind.
+pub fn save_session(session: &SessionDesc, path: &Path) -> StrResult {
+    let temp_path = sibling_path(path, ".tmp");
+
+    {
+        let mut file = File::create(&temp_path).map_err(err!())?;
+        file.write_all(json::to_string_pretty(session).map_err(err!())?.as_bytes())
+            .map_err(err!())?;
+        file.sync_all().map_err(err!())?;
+    }
+
+    fs::rename(&temp_path, path).map_err(err!())?;
+
+    // Persist the rename itself. Directories cannot be opened as files on Windows, where
+    // MoveFileEx already flushes the metadata.
+    #[cfg(unix)]
+    if let Some(dir) = path.parent() {
+        File::open(dir).and_then(|dir| dir.sync_all()).ok();
+    }
+
+    Ok(())
+}
+
+// Advisory inter-process lock, held by the dashboard or the driver for the duration of a
+// read-modify-write of session.json. The lock is released on drop.
+pub struct SessionFileLock(File);
+
+impl SessionFileLock {
+    pub fn acquire(session_path: &Path) -> StrResult<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .open(sibling_path(session_path, ".lock"))
+            .map_err(err!())?;
+        file.lock_exclusive().map_err(err!())?;
+
+        Ok(Self(file))
+    }
+}
+
+impl Drop for SessionFileLock {
+    fn drop(&mut self) {
+        self.0.unlock().ok();
+    }
+}
+
+// Coarsest mtime resolution of the common filesystems (FAT)
+const MTIME_RESOLUTION: Duration = Duration::from_secs(2);
+
+// Used to detect when session.json has been written by another process. The content hash catches
+// edits that keep the same size within the mtime resolution.
+#[derive(PartialEq, Eq, Clone, Copy)]
+pub struct FileStamp {
+    modified: SystemTime,
+    len: u64,
+    hash: u64,
+}
+
+pub fn read_with_stamp(path: &Path) -> Option<(String, FileStamp)> {
+    let metadata = fs::metadata(path).ok()?;
+    let content = fs::read_to_string(path).ok()?;
+
+    let stamp = FileStamp {
+        modified: metadata.modified().ok()?,
+        len: metadata.len(),
+        hash: alvr_common::hash_string(&content),
+    };
+
+    Some((content, stamp))
+}
+
+pub fn file_stamp(path: &Path) -> Option<FileStamp> {
+    read_with_stamp(path).map(|(_, stamp)| stamp)
+}
+
+// Returns true if the file could differ from the stamp. Only the metadata is checked, unless the
+// file has been modified within the mtime resolution, when another write could keep the same
+// metadata. Returns false if the file does not exist.
+pub fn changed_since(path: &Path, stamp: Option<FileStamp>) -> bool {
+    let Some((modified, len)) = fs::metadata(path)
+        .ok()
+        .and_then(|metadata| Some((metadata.modified().ok()?, metadata.len())))
+    else {
+        return false;
+    };
+    let Some(stamp) = stamp else {
+        return true;
+    };
+
+    if modified != stamp.modified || len != stamp.len {
+        return true;
+    }
+
+    let recently_modified = SystemTime::now()
+        .duration_since(modified)
+        .map_or(true, |age| age < MTIME_RESOLUTION);
+
+    recently_modified
+        && read_with_stamp(path).map_or(false, |(_, new_stamp)| new_stamp.hash != stamp.hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn same_size_write_is_detected() {
+        let path = std::env::temp_dir().join(format!("alvr_stamp_test_{}", std::process::id()));
+
+        fs::write(&path, "{\"a\": 1}").unwrap();
+        let stamp = file_stamp(&path);
+        assert!(!changed_since(&path, stamp));
+
+        // Within the mtime resolution, so the metadata could be the same
+        fs::write(&path, "{\"a\": 2}").unwrap();
+        assert!(changed_since(&path, stamp));
+
+        fs::remove_file(&path).ok();
+        assert!(!changed_since(&path, stamp));
+    }
+}
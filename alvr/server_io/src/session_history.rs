@@ -0,0 +1,192 @@
+use alvr_common::prelude::*;
+use alvr_packets::{SessionChangeOrigin, SessionHistoryEntry};
+use alvr_session::SessionSettings;
+use serde_json as json;
+use std::{collections::VecDeque, time::SystemTime};
+
+const MAX_HISTORY_SIZE: usize = 64;
+
+// Bounded journal of session_settings snapshots. Each entry holds the settings as they were after
+// the change it describes. The first entry is the baseline state loaded from disk. Client
+// connections and the OpenVR config are not tracked, as they are managed by the streamer itself.
+pub struct SessionHistory {
+    entries: VecDeque<(SessionHistoryEntry, json::Value)>,
+    next_id: u64,
+}
+
+impl SessionHistory {
+    pub fn new(session_settings: &SessionSettings, origin: SessionChangeOrigin) -> Self {
+        let mut history = Self {
+            entries: VecDeque::new(),
+            next_id: 0,
+        };
+        history.push(json::to_value(session_settings).unwrap(), origin, vec![]);
+
+        history
+    }
+
+    fn push(&mut self, snapshot: json::Value, origin: SessionChangeOrigin, paths: Vec<String>) {
+        if self.entries.len() >= MAX_HISTORY_SIZE {
+            self.entries.pop_front();
+        }
+
+        self.entries.push_back((
+            SessionHistoryEntry {
+                id: self.next_id,
+                timestamp: SystemTime::now(),
+                origin,
+                changed_paths: paths,
+            },
+            snapshot,
+        ));
+
+        self.next_id += 1;
+    }
+
+    // Returns true if the settings differ from the last snapshot and a new entry has been added
+    pub fn record(
+        &mut self,
+        session_settings: &SessionSettings,
+        origin: SessionChangeOrigin,
+    ) -> bool {
+        let snapshot = json::to_value(session_settings).unwrap();
+
+        let mut changed_paths = vec![];
+        if let Some((_, last_snapshot)) = self.entries.back() {
+            diff_paths(last_snapshot, &snapshot, "", &mut changed_paths);
+        }
+
+        if !changed_paths.is_empty() {
+            self.push(snapshot, origin, changed_paths);
+
+            true
+        } else {
+            false
+        }
+    }
+
+    // Drop the latest entry and return the settings that preceded it
+    pub fn undo(&mut self) -> StrResult<SessionSettings> {
+        if self.entries.len() < 2 {
+            return fmt_e!("No session change to undo");
+        }
+
+        self.entries.pop_back();
+        let (_, snapshot) = self.entries.back().unwrap();
+
+        json::from_value(snapshot.clone()).map_err(err!())
+    }
+
+    pub fn snapshot(&self, id: u64) -> StrResult<SessionSettings> {
+        let (_, snapshot) = self
+            .entries
+            .iter()
+            .find(|(entry, _)| entry.id == id)
+            .ok_or_else(|| format!("Session snapshot {id} not found"))?;
+
+        json::from_value(snapshot.clone()).map_err(err!())
+    }
+
+    pub fn entries(&self) -> Vec<SessionHistoryEntry> {
+        self.entries
+            .iter()
+            .map(|(entry, _)| entry.clone())
+            .collect()
+    }
+}
+
+fn diff_paths(old: &json::Value, new: &json::Value, prefix: &str, paths: &mut Vec<String>) {
+    let join = |segment: &str| {
+        if prefix.is_empty() {
+            segment.to_owned()
+        } else {
+            format!("{prefix}.{segment}")
+        }
+    };
+
+    match (old, new) {
+        (json::Value::Object(old_map), json::Value::Object(new_map)) => {
+            for (name, new_value) in new_map {
+                if let Some(old_value) = old_map.get(name) {
+                    diff_paths(old_value, new_value, &join(name), paths);
+                } else {
+                    paths.push(join(name));
+                }
+            }
+            for name in old_map.keys() {
+                if !new_map.contains_key(name) {
+                    paths.push(join(name));
+                }
+            }
+        }
+        (json::Value::Array(old_vec), json::Value::Array(new_vec))
+            if old_vec.len() == new_vec.len() =>
+        {
+            for (idx, (old_value, new_value)) in old_vec.iter().zip(new_vec).enumerate() {
+                diff_paths(old_value, new_value, &format!("{prefix}[{idx}]"), paths);
+            }
+        }
+        (old, new) => {
+            if old != new {
+                paths.push(prefix.to_owned());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alvr_session::session_settings_default;
+
+    #[test]
+    fn changed_paths() {
+        let old = json::json!({ "a": { "b": 1, "c": [1, 2] }, "d": true, "removed": 0 });
+        let new = json::json!({ "a": { "b": 2, "c": [1, 3] }, "d": true, "added": 0 });
+
+        let mut paths = vec![];
+        diff_paths(&old, &new, "", &mut paths);
+        paths.sort();
+
+        assert_eq!(paths, ["a.b", "a.c[1]", "added", "removed"]);
+    }
+
+    #[test]
+    fn record_and_undo() {
+        let mut settings = session_settings_default();
+        let mut history = SessionHistory::new(&settings, SessionChangeOrigin::Internal);
+
+        assert!(!history.record(&settings, SessionChangeOrigin::Dashboard));
+        assert!(history.undo().is_err());
+
+        settings.video.preferred_fps = 90.0;
+        assert!(history.record(&settings, SessionChangeOrigin::Dashboard));
+
+        let entries = history.entries();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[1].origin, SessionChangeOrigin::Dashboard);
+        assert_eq!(entries[1].changed_paths, ["video.preferred_fps"]);
+
+        assert_eq!(history.undo().unwrap().video.preferred_fps, 72.0);
+        assert_eq!(history.entries().len(), 1);
+    }
+
+    #[test]
+    fn snapshot_restore() {
+        let mut settings = session_settings_default();
+        let mut history = SessionHistory::new(&settings, SessionChangeOrigin::Internal);
+        let baseline_id = history.entries()[0].id;
+
+        settings.video.preferred_fps = 90.0;
+        history.record(&settings, SessionChangeOrigin::HttpApi);
+
+        let restored = history.snapshot(baseline_id).unwrap();
+        assert_eq!(restored.video.preferred_fps, 72.0);
+
+        // Restoring is recorded as a new change, which can be undone
+        assert!(history.record(&restored, SessionChangeOrigin::Rollback));
+        assert_eq!(history.undo().unwrap().video.preferred_fps, 90.0);
+
+        assert!(history.snapshot(100).is_err());
+    }
+}
@@ -0,0 +1,85 @@
+use alvr_common::prelude::*;
+use rand::{distributions::Alphanumeric, Rng};
+use std::{
+    fs::{self, OpenOptions},
+    io::{ErrorKind, Write},
+    path::{Path, PathBuf},
+    thread,
+    time::Duration,
+};
+
+const TOKEN_LENGTH: usize = 32;
+
+// Another process could be creating the token at the same time
+const READ_RETRIES: usize = 10;
+const READ_RETRY_INTERVAL: Duration = Duration::from_millis(50);
+
+fn read_token(path: &Path) -> StrResult<String> {
+    let token = fs::read_to_string(path).map_err(err!())?;
+    let token = token.trim();
+
+    if token.len() >= TOKEN_LENGTH && token.chars().all(|c| c.is_ascii_alphanumeric()) {
+        Ok(token.to_owned())
+    } else {
+        fmt_e!("Invalid web server token in {}", path.display())
+    }
+}
+
+fn write_temp_token(path: &Path, token: &str) -> StrResult<PathBuf> {
+    let temp_path = path.with_extension(format!("tmp{}", std::process::id()));
+
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+
+    let mut file = options.open(&temp_path).map_err(err!())?;
+    file.write_all(token.as_bytes()).map_err(err!())?;
+    file.sync_all().map_err(err!())?;
+
+    Ok(temp_path)
+}
+
+// The token is required to use the web server API. It is stored in the config dir, so it can be
+// read only by processes running as the same user, like the dashboard. The token file is written
+// to a temporary file and then linked into place, so it is never read partially written.
+pub fn load_or_create_web_server_token(path: &Path) -> StrResult<String> {
+    if !path.exists() {
+        let token = rand::thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(TOKEN_LENGTH)
+            .map(char::from)
+            .collect::<String>();
+
+        let temp_path = write_temp_token(path, &token)?;
+        // Unlike a rename, this fails if the dashboard or the streamer created the token in the
+        // meantime
+        let res = fs::hard_link(&temp_path, path);
+        fs::remove_file(&temp_path).ok();
+
+        match res {
+            Ok(()) => return Ok(token),
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => (),
+            Err(e) => return fmt_e!("Failed to create web server token: {e}"),
+        }
+    }
+
+    load_web_server_token(path)
+}
+
+// Unlike load_or_create_web_server_token(), this never creates the token file
+pub fn load_web_server_token(path: &Path) -> StrResult<String> {
+    let mut res = read_token(path);
+    for _ in 0..READ_RETRIES {
+        if res.is_ok() {
+            break;
+        }
+        thread::sleep(READ_RETRY_INTERVAL);
+        res = read_token(path);
+    }
+
+    res
+}
@@ -3,7 +3,7 @@ mod settings;
 pub use settings::*;
 pub use settings_schema;
 
-use alvr_common::{prelude::*, semver::Version, ALVR_VERSION};
+use alvr_common::{prelude::*, semver::Version, ALVR_VERSION, LEFT_HAND_PATH, RIGHT_HAND_PATH};
 use serde::{Deserialize, Serialize};
 use serde_json as json;
 use settings_schema::{NumberType, SchemaNode};
@@ -151,6 +151,10 @@ impl SessionDesc {
     pub fn merge_from_json(&mut self, json_value: &json::Value) -> StrResult {
         const SESSION_SETTINGS_STR: &str = "session_settings";
 
+        let mut json_value = json_value.clone();
+        migrate_controller_motions(&mut json_value);
+        let json_value = &json_value;
+
         if let Ok(session_desc) = json::from_value(json_value.clone()) {
             *self = session_desc;
             return Ok(());
@@ -213,6 +217,148 @@ impl SessionDesc {
     }
 }
 
+// The controller offsets and velocity cutoffs used to be shared by both controllers, with the right
+// one mirrored. They are now in the per-device motion configs, which are missing from old sessions.
+fn migrate_controller_motions(session_json: &mut json::Value) {
+    let Some(headset) = session_json
+        .get_mut("session_settings")
+        .and_then(|settings| settings.get_mut("headset"))
+        .and_then(|headset| headset.as_object_mut())
+    else {
+        return;
+    };
+    if headset.contains_key("device_motions") {
+        return;
+    }
+    let Some(controllers) = headset
+        .get("controllers")
+        .and_then(|controllers| controllers.get("content"))
+    else {
+        return;
+    };
+
+    let vec3 = |name: &str| json::from_value::<[f32; 3]>(controllers.get(name)?.clone()).ok();
+    let scalar = |name: &str| controllers.get(name)?.as_f64();
+    let (Some(t), Some(r), Some(linear_cutoff), Some(angular_cutoff)) = (
+        vec3("left_controller_position_offset"),
+        vec3("left_controller_rotation_offset"),
+        scalar("linear_velocity_cutoff"),
+        scalar("angular_velocity_cutoff"),
+    ) else {
+        return;
+    };
+
+    let mut device_motions = json::to_value(settings::session_settings_default()).unwrap()
+        ["headset"]["device_motions"]
+        .take();
+    if let Some(entries) = device_motions["content"].as_array_mut() {
+        for entry in entries {
+            let (position_offset, rotation_offset) = if entry[0] == LEFT_HAND_PATH {
+                (t, r)
+            } else if entry[0] == RIGHT_HAND_PATH {
+                ([-t[0], t[1], t[2]], [r[0], -r[1], -r[2]])
+            } else {
+                continue;
+            };
+
+            let config = &mut entry[1];
+            config["position_offset"] = json::json!(position_offset);
+            config["rotation_offset"] = json::json!(rotation_offset);
+            config["linear_velocity_cutoff"] = json::json!(linear_cutoff);
+            config["angular_velocity_cutoff"] = json::json!(angular_cutoff);
+        }
+    }
+
+    headset.insert("device_motions".into(), device_motions);
+}
+
+// Reverts the fields with the "steamvr-restart" flag to their value in `current`. The result
+// contains only the changes that can be applied to a running stream.
+pub fn settings_without_restart_changes(current: &Settings, new: &Settings) -> Settings {
+    let schema = Settings::schema(settings::session_settings_default());
+    let current_json = json::to_value(current).unwrap();
+    let mut new_json = json::to_value(new).unwrap();
+
+    revert_restart_fields(&schema, &current_json, &mut new_json);
+
+    json::from_value(new_json).unwrap()
+}
+
+// Only the content of sections, and of switches and choices that kept the same state, is visited
+fn revert_restart_fields(schema: &SchemaNode, current: &json::Value, new: &mut json::Value) {
+    match schema {
+        SchemaNode::Section(entries) => {
+            for entry in entries {
+                let (Some(current_field), Some(new_field)) =
+                    (current.get(&entry.name), new.get_mut(&entry.name))
+                else {
+                    continue;
+                };
+
+                if entry.flags.contains("steamvr-restart") {
+                    *new_field = current_field.clone();
+                } else {
+                    revert_restart_fields(&entry.content, current_field, new_field);
+                }
+            }
+        }
+        SchemaNode::Choice { variants, .. } => {
+            for entry in variants {
+                if let (Some(content), Some(current_content), Some(new_content)) = (
+                    &entry.content,
+                    current.get(&entry.name),
+                    new.get_mut(&entry.name),
+                ) {
+                    revert_restart_fields(content, current_content, new_content);
+                }
+            }
+        }
+        SchemaNode::Switch { content, .. } => {
+            match (current.get("Enabled"), new.get_mut("Enabled")) {
+                (Some(current_content), Some(new_content)) => {
+                    revert_restart_fields(content, current_content, new_content)
+                }
+                (None, None) => (),
+                // Toggling the switch enables or disables the restart fields of its content too
+                _ => {
+                    if contains_restart_fields(content) {
+                        *new = current.clone();
+                    }
+                }
+            }
+        }
+        SchemaNode::Optional { content, .. } => {
+            if !current.is_null() && !new.is_null() {
+                revert_restart_fields(content, current, new);
+            }
+        }
+        _ => (),
+    }
+}
+
+fn contains_restart_fields(schema: &SchemaNode) -> bool {
+    match schema {
+        SchemaNode::Section(entries) => entries.iter().any(|entry| {
+            entry.flags.contains("steamvr-restart") || contains_restart_fields(&entry.content)
+        }),
+        SchemaNode::Choice { variants, .. } => variants.iter().any(|entry| {
+            entry
+                .content
+                .as_ref()
+                .map_or(false, contains_restart_fields)
+        }),
+        SchemaNode::Switch { content, .. } | SchemaNode::Optional { content, .. } => {
+            contains_restart_fields(content)
+        }
+        SchemaNode::Array(entries) => entries.iter().any(contains_restart_fields),
+        SchemaNode::Vector {
+            default_element, ..
+        } => contains_restart_fields(default_element),
+        SchemaNode::Dictionary { default_value, .. } => contains_restart_fields(default_value),
+        _ => false,
+    }
+}
+
 // Current data extrapolation strategy: match both field name and value type exactly.
 // Integer bounds are not validated, if they do not match the schema, deserialization will fail and
 // all data is lost.
@@ -557,6 +703,78 @@ mod tests {
         let _settings = SessionDesc::default().to_settings();
     }
 
+    #[test]
+    fn test_settings_without_restart_changes() {
+        let current = SessionDesc::default().to_settings();
+
+        let mut new_json = json::to_value(&current).unwrap();
+        new_json["headset"]["controllers"] = json::json!("Disabled");
+        new_json["headset"]["rotation_recentering_mode"] = json::json!("Tilted");
+        let new = json::from_value(new_json).unwrap();
+
+        let applied = json::to_value(settings_without_restart_changes(&current, &new)).unwrap();
+        let current = json::to_value(current).unwrap();
+
+        assert_eq!(
+            applied["headset"]["controllers"],
+            current["headset"]["controllers"]
+        );
+        assert_eq!(
+            applied["headset"]["rotation_recentering_mode"],
+            json::json!("Tilted")
+        );
+    }
+
+    #[test]
+    fn test_haptics_changes_without_restart() {
+        let current = SessionDesc::default().to_settings();
+
+        let mut new_json = json::to_value(&current).unwrap();
+        let controllers = &mut new_json["headset"]["controllers"]["Enabled"];
+        controllers["haptics"]["Enabled"]["intensity_multiplier"] = json::json!(2.0);
+        controllers["emulation_mode"] = json::json!("ValveIndex");
+        let new = json::from_value(new_json).unwrap();
+
+        let applied = json::to_value(settings_without_restart_changes(&current, &new)).unwrap();
+        let current = json::to_value(current).unwrap();
+
+        let applied_controllers = &applied["headset"]["controllers"]["Enabled"];
+        assert_eq!(
+            applied_controllers["haptics"]["Enabled"]["intensity_multiplier"],
+            json::json!(2.0)
+        );
+        assert_eq!(
+            applied_controllers["emulation_mode"],
+            current["headset"]["controllers"]["Enabled"]["emulation_mode"]
+        );
+    }
+
+    #[test]
+    fn test_controller_motions_migration() {
+        let mut session_json = json::to_value(SessionDesc::default()).unwrap();
+        let headset = &mut session_json["session_settings"]["headset"];
+        headset.as_object_mut().unwrap().remove("device_motions");
+        let controllers = &mut headset["controllers"]["content"];
+        controllers["left_controller_position_offset"] = json::json!([0.01, 0.02, -0.1]);
+        controllers["left_controller_rotation_offset"] = json::json!([-10.0, 5.0, 15.0]);
+        controllers["linear_velocity_cutoff"] = json::json!(0.1);
+        controllers["angular_velocity_cutoff"] = json::json!(20.0);
+
+        let mut session = SessionDesc::default();
+        session.merge_from_json(&session_json).unwrap();
+        let device_motions = session.to_settings().headset.device_motions;
+
+        let right = &device_motions
+            .iter()
+            .find(|(path, _)| path == RIGHT_HAND_PATH)
+            .unwrap()
+            .1;
+        assert_eq!(right.position_offset, [-0.01, 0.02, -0.1]);
+        assert_eq!(right.rotation_offset, [-10.0, -5.0, -15.0]);
+        assert_eq!(right.linear_velocity_cutoff, 0.1);
+        assert_eq!(right.angular_velocity_cutoff, 20.0);
+    }
+
     #[test]
     fn test_session_extrapolation_trivial() {
         SessionDesc::default()
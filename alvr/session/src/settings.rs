@@ -1,4 +1,7 @@
-use alvr_common::{LogSeverity, LogSeverityDefault, LogSeverityDefaultVariant};
+use alvr_common::{
+    LogSeverity, LogSeverityDefault, LogSeverityDefaultVariant, HEAD_PATH, LEFT_HAND_PATH,
+    RIGHT_HAND_PATH,
+};
 use bytemuck::{Pod, Zeroable};
 use serde::{Deserialize, Serialize};
 use settings_schema::{
@@ -428,6 +431,9 @@ pub enum LinuxAudioBackend {
     Alsa,
 
     Jack,
+
+    #[schema(strings(display_name = "PipeWire"))]
+    PipeWire,
 }
 
 #[derive(SettingsSchema, Serialize, Deserialize, Clone)]
@@ -435,13 +441,24 @@ pub enum LinuxAudioBackend {
 pub enum CustomAudioDeviceConfig {
     #[schema(strings(display_name = "By name (substring)"))]
     NameSubstring(String),
-    #[schema(strings(display_name = "By index"))]
+    #[schema(strings(
+        display_name = "By index",
+        help = "The index changes when other devices are added or removed"
+    ))]
     Index(usize),
+    #[schema(strings(
+        display_name = "By ID",
+        help = "Stable when other devices are added or removed. The name is used if no device has this ID, for example after a driver update. On Windows this is the endpoint ID and on Linux the ALSA PCM name. On macOS it is the device name, which is not guaranteed to be unique"
+    ))]
+    Id { id: String, name: String },
 }
 
 #[derive(SettingsSchema, Serialize, Deserialize, Clone)]
 pub struct AudioBufferingConfig {
-    #[schema(strings(display_name = "Average buffering"))]
+    #[schema(strings(
+        display_name = "Average buffering",
+        help = "Minimum buffering. It is increased automatically when the network jitter is high"
+    ))]
     #[schema(gui(slider(min = 0, max = 200)), suffix = "ms")]
     pub average_buffering_ms: u64,
 
@@ -450,11 +467,44 @@ pub struct AudioBufferingConfig {
     pub batch_ms: u64,
 }
 
+#[derive(SettingsSchema, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum AudioCodecConfig {
+    #[schema(strings(display_name = "PCM (uncompressed)"))]
+    Pcm,
+    Opus {
+        #[schema(gui(slider(min = 16, max = 256, step = 8)), suffix = "kbps")]
+        bitrate_kbps: u64,
+        #[schema(strings(
+            display_name = "In-band FEC",
+            help = "Adds redundancy to recover single lost packets. Effective only at lower bitrates"
+        ))]
+        in_band_fec: bool,
+    },
+}
+
+#[derive(SettingsSchema, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[schema(gui = "button_group")]
+pub enum SurroundAudioMode {
+    StereoDownmix,
+    BinauralDownmix,
+    Passthrough,
+}
+
 #[derive(SettingsSchema, Serialize, Deserialize, Clone)]
 pub struct GameAudioConfig {
     pub device: Option<CustomAudioDeviceConfig>,
     pub mute_when_streaming: bool,
     pub buffering: AudioBufferingConfig,
+    #[schema(strings(
+        help = "With Opus, audio is resampled to 48 kHz if the device sample rate is not supported"
+    ))]
+    pub codec: AudioCodecConfig,
+    #[schema(
+        strings(help = r#"Used when the game audio device has more than 2 channels.
+Binaural downmix: virtual speakers rendered with a head model, for headphones.
+Passthrough: requires the PCM codec and a client that can play the channel layout, otherwise binaural downmix is used."#)
+    )]
+    pub surround: SurroundAudioMode,
 }
 
 #[derive(SettingsSchema, Serialize, Deserialize, Clone)]
@@ -476,17 +526,40 @@ pub enum MicrophoneDevicesConfig {
     },
 }
 
+#[derive(SettingsSchema, Serialize, Deserialize, Clone)]
+pub struct AutomaticGainControlConfig {
+    #[schema(gui(slider(min = -40.0, max = -6.0, step = 1.0)), suffix = "dBFS")]
+    pub target_level_dbfs: f32,
+    #[schema(gui(slider(min = 0.0, max = 40.0, step = 1.0)), suffix = "dB")]
+    pub max_gain_db: f32,
+}
+
+// The processing is done on the client, where the played game audio is available as echo reference
+#[derive(SettingsSchema, Serialize, Deserialize, Clone)]
+pub struct MicrophoneProcessingConfig {
+    #[schema(strings(help = "Removes background noise like fans, using a neural network"))]
+    pub noise_suppression: bool,
+    #[schema(strings(help = "Keeps the voice at a constant level"))]
+    pub automatic_gain_control: Switch<AutomaticGainControlConfig>,
+    #[schema(strings(help = "Removes the game audio played by the headset speakers"))]
+    pub echo_cancellation: bool,
+}
+
 // Note: sample rate is a free parameter for microphone, because both server and client supports
 // resampling. In contrary, for game audio, the server does not support resampling.
 #[derive(SettingsSchema, Serialize, Deserialize, Clone)]
 pub struct MicrophoneConfig {
     pub devices: MicrophoneDevicesConfig,
     pub buffering: AudioBufferingConfig,
+    pub codec: AudioCodecConfig,
+    pub processing: MicrophoneProcessingConfig,
 }
 
 #[derive(SettingsSchema, Serialize, Deserialize, Clone)]
 pub struct AudioConfig {
-    #[schema(strings(help = "ALSA is recommended for most PulseAudio or PipeWire-based setups"))]
+    #[schema(strings(
+        help = "ALSA is recommended for most PulseAudio or PipeWire-based setups. PipeWire creates the virtual devices \"ALVR Audio\" and \"ALVR Microphone\" while streaming and ignores custom devices. It requires a running PipeWire daemon and a streamer built with PipeWire support, otherwise ALSA is used"
+    ))]
     pub linux_backend: LinuxAudioBackend,
 
     pub game_audio: Switch<GameAudioConfig>,
@@ -593,21 +666,6 @@ Currently this cannot be reliably estimated automatically. The correct value sho
     #[schema(gui(slider(min = 1.0, max = 10.0, logarithmic)), suffix = "frames")]
     pub steamvr_pipeline_frames: f32,
 
-    // note: logarithmic scale seems to be glitchy for this control
-    #[schema(gui(slider(min = 0.0, max = 1.0, step = 0.01)), suffix = "m/s")]
-    pub linear_velocity_cutoff: f32,
-
-    // note: logarithmic scale seems to be glitchy for this control
-    #[schema(gui(slider(min = 0.0, max = 100.0, step = 1.0)), suffix = "°/s")]
-    pub angular_velocity_cutoff: f32,
-
-    // note: logarithmic scale seems to be glitchy for this control
-    #[schema(gui(slider(min = -0.5, max = 0.5, step = 0.001)), suffix = "m")]
-    pub left_controller_position_offset: [f32; 3],
-
-    #[schema(gui(slider(min = -180.0, max = 180.0, step = 1.0)), suffix = "°")]
-    pub left_controller_rotation_offset: [f32; 3],
-
     // note: logarithmic scale seems to be glitchy for this control
     #[schema(gui(slider(min = -0.5, max = 0.5, step = 0.001)), suffix = "m")]
     pub left_hand_tracking_position_offset: [f32; 3],
@@ -627,6 +685,99 @@ Currently this cannot be reliably estimated automatically. The correct value sho
 }
 
 #[derive(SettingsSchema, Serialize, Deserialize, Clone, Copy)]
+pub enum MotionSmoothingConfig {
+    #[schema(strings(
+        display_name = "One Euro",
+        help = "Removes the jitter when the device is still and lets fast movements through"
+    ))]
+    OneEuro {
+        #[schema(strings(
+            help = "Lower values remove more jitter, but add lag to slow movements"
+        ))]
+        #[schema(gui(slider(min = 0.1, max = 10.0, step = 0.1)), suffix = "Hz")]
+        min_cutoff_hz: f32,
+        #[schema(strings(help = "Higher values reduce the lag of fast movements"))]
+        #[schema(gui(slider(min = 0.0, max = 10.0, step = 0.1)))]
+        beta: f32,
+    },
+    #[schema(strings(
+        help = "Constant velocity model. Smoother than One Euro, but it overshoots on sudden stops"
+    ))]
+    Kalman {
+        #[schema(strings(
+            help = "Expected changes of velocity. Higher values follow the measurements more closely"
+        ))]
+        #[schema(gui(slider(min = 0.1, max = 100.0, logarithmic)), suffix = "m/s²")]
+        acceleration_noise: f32,
+        #[schema(gui(slider(min = 0.0001, max = 0.01, logarithmic)), suffix = "m")]
+        position_noise: f32,
+        #[schema(strings(help = "Expected error of the reported angular velocity"))]
+        #[schema(gui(slider(min = 1.0, max = 360.0, logarithmic)), suffix = "°/s")]
+        angular_velocity_noise: f32,
+        #[schema(gui(slider(min = 0.01, max = 5.0, logarithmic)), suffix = "°")]
+        orientation_noise: f32,
+    },
+}
+
+#[derive(SettingsSchema, Serialize, Deserialize, Clone)]
+pub struct DeviceMotionConfig {
+    // note: logarithmic scale seems to be glitchy for this control
+    #[schema(gui(slider(min = -0.5, max = 0.5, step = 0.001)), suffix = "m")]
+    pub position_offset: [f32; 3],
+
+    #[schema(strings(help = "Applied before the position offset"))]
+    #[schema(gui(slider(min = -180.0, max = 180.0, step = 1.0)), suffix = "°")]
+    pub rotation_offset: [f32; 3],
+
+    // note: logarithmic scale seems to be glitchy for this control
+    #[schema(gui(slider(min = 0.0, max = 1.0, step = 0.01)), suffix = "m/s")]
+    pub linear_velocity_cutoff: f32,
+
+    // note: logarithmic scale seems to be glitchy for this control
+    #[schema(gui(slider(min = 0.0, max = 100.0, step = 1.0)), suffix = "°/s")]
+    pub angular_velocity_cutoff: f32,
+
+    pub smoothing: Switch<MotionSmoothingConfig>,
+}
+
+#[derive(SettingsSchema, Serialize, Deserialize, Clone, Copy)]
+pub enum PosePredictionStrategy {
+    ConstantVelocity,
+    #[schema(strings(help = "The acceleration is estimated from the last velocities"))]
+    ConstantAcceleration,
+    #[schema(strings(help = "The velocity decays over time, which limits the overshoot"))]
+    Damped {
+        #[schema(gui(slider(min = 5.0, max = 200.0, step = 5.0)), suffix = "ms")]
+        velocity_half_life_ms: f32,
+    },
+}
+
+#[derive(SettingsSchema, Serialize, Deserialize, Clone)]
+pub struct HeadPosePredictionConfig {
+    #[schema(strings(
+        help = "Part of the latency already covered by the prediction of the client runtime. It is subtracted from the measured latency"
+    ))]
+    #[schema(gui(slider(min = 0.0, max = 200.0, step = 1.0)), suffix = "ms")]
+    pub runtime_prediction_ms: f32,
+}
+
+#[derive(SettingsSchema, Serialize, Deserialize, Clone)]
+pub struct PosePredictionConfig {
+    pub strategy: PosePredictionStrategy,
+
+    #[schema(strings(
+        help = "The measured latency is clamped to this value, since long predictions overshoot"
+    ))]
+    #[schema(gui(slider(min = 0.0, max = 200.0, step = 1.0)), suffix = "ms")]
+    pub max_prediction_ms: f32,
+
+    #[schema(strings(
+        help = "The client runtime already predicts the head pose to the time it requested it for. Enable this to extrapolate the head too, if it lags behind"
+    ))]
+    pub head: Switch<HeadPosePredictionConfig>,
+}
+
+#[derive(SettingsSchema, Serialize, Deserialize, Clone, Copy, PartialEq)]
 pub enum PositionRecenteringMode {
     Disabled,
     LocalFloor,
@@ -636,7 +787,7 @@ pub enum PositionRecenteringMode {
     },
 }
 
-#[derive(SettingsSchema, Serialize, Deserialize, Clone, Copy)]
+#[derive(SettingsSchema, Serialize, Deserialize, Clone, Copy, PartialEq)]
 pub enum RotationRecenteringMode {
     Disabled,
     Yaw,
@@ -659,9 +810,25 @@ pub struct HeadsetDesc {
 
     pub face_tracking: Switch<FaceTrackingConfig>,
 
-    #[schema(flag = "steamvr-restart")]
+    #[schema(strings(
+        help = "Enabling or disabling the controllers is applied after a SteamVR restart. Fields marked as such inside this section need a restart too, the others are applied while streaming"
+    ))]
     pub controllers: Switch<ControllersDesc>,
 
+    #[schema(strings(
+        help = r#"Offsets, velocity cutoffs and smoothing of each tracked device, by path: /user/head, /user/hand/left, /user/hand/right.
+The hand entries are not used if controllers are disabled. The offsets are not used with hand tracking.
+The controller offsets and velocity cutoffs were previously in the Controllers section, they are migrated here with the right controller mirrored."#
+    ))]
+    pub device_motions: Vec<(String, DeviceMotionConfig)>,
+
+    #[schema(strings(
+        help = r#"Controllers and trackers poses are extrapolated by the streamer to the display time, using the measured latency, instead of by the client and SteamVR. The controllers Prediction setting is not used.
+The head pose is predicted by the client runtime, it is extrapolated by the streamer only if Head is enabled."#
+    ))]
+    #[schema(flag = "steamvr-restart")]
+    pub pose_prediction: Switch<PosePredictionConfig>,
+
     #[schema(strings(
         help = r#"Disabled: the playspace origin is determined by the room-scale guardian setup.
 Local floor: the origin is on the floor and resets when long pressing the oculus button.
@@ -713,6 +880,13 @@ TCP: Slower than UDP, but more stable. Pick this if you experience video or audi
 
     pub stream_port: u16,
     pub web_server_port: u16,
+
+    #[schema(strings(
+        help = "Address the web server listens on. Set 0.0.0.0 to allow access from other devices on the network. Requests always require the token found in the config directory."
+    ))]
+    #[schema(flag = "steamvr-restart")]
+    pub web_server_bind_address: String,
+
     pub osc_local_port: u16,
 
     #[schema(strings(display_name = "Streamer send buffer size"))]
@@ -762,10 +936,41 @@ For now works only on Windows+Nvidia"#
     pub statistics_history_size: u64,
 }
 
+#[derive(SettingsSchema, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub enum LogFileFormat {
+    Text,
+    #[schema(strings(display_name = "JSON lines"))]
+    JsonLines,
+}
+
+#[derive(SettingsSchema, Serialize, Deserialize, Clone)]
+pub struct LogFilesConfig {
+    pub format: LogFileFormat,
+
+    #[schema(strings(help = "A new file is started when the current one exceeds this size"))]
+    #[schema(gui(slider(min = 1, max = 100)), suffix = "MB")]
+    pub max_file_size_mb: u64,
+
+    #[schema(strings(help = "The oldest log files are deleted when there are more than this"))]
+    #[schema(gui(slider(min = 1, max = 100)))]
+    pub max_file_count: u64,
+}
+
 #[derive(SettingsSchema, Serialize, Deserialize, Clone)]
 pub struct LoggingConfig {
-    #[schema(strings(help = "Write logs into the session_log.txt file."))]
+    #[schema(strings(
+        help = "Write logs into the logs folder. A new file is started for every streaming session, named after the client hostname."
+    ))]
     pub log_to_disk: bool,
+
+    #[schema(flag = "steamvr-restart")]
+    pub log_files: LogFilesConfig,
+
+    #[schema(strings(
+        help = "Record the timings of every frame of each streaming session into the statistics folder. Use alvr_cli to summarize recordings or convert them to CSV."
+    ))]
+    pub record_statistics: bool,
+
     pub log_tracking: bool,
     pub log_button_presses: bool,
     pub log_haptics: bool,
@@ -838,6 +1043,10 @@ pub fn session_settings_default() -> SettingsDefault {
     let default_custom_audio_device = CustomAudioDeviceConfigDefault {
         NameSubstring: "".into(),
         Index: 0,
+        Id: CustomAudioDeviceConfigIdDefault {
+            id: "".into(),
+            name: "".into(),
+        },
         variant: CustomAudioDeviceConfigDefaultVariant::NameSubstring,
     };
     let default_custom_openvr_props = VectorDefault {
@@ -1028,6 +1237,16 @@ pub fn session_settings_default() -> SettingsDefault {
                         average_buffering_ms: 50,
                         batch_ms: 10,
                     },
+                    codec: AudioCodecConfigDefault {
+                        Opus: AudioCodecConfigOpusDefault {
+                            bitrate_kbps: 128,
+                            in_band_fec: true,
+                        },
+                        variant: AudioCodecConfigDefaultVariant::Pcm,
+                    },
+                    surround: SurroundAudioModeDefault {
+                        variant: SurroundAudioModeDefaultVariant::StereoDownmix,
+                    },
                 },
             },
             microphone: SwitchDefault {
@@ -1044,6 +1263,24 @@ pub fn session_settings_default() -> SettingsDefault {
                         average_buffering_ms: 50,
                         batch_ms: 10,
                     },
+                    codec: AudioCodecConfigDefault {
+                        Opus: AudioCodecConfigOpusDefault {
+                            bitrate_kbps: 32,
+                            in_band_fec: true,
+                        },
+                        variant: AudioCodecConfigDefaultVariant::Pcm,
+                    },
+                    processing: MicrophoneProcessingConfigDefault {
+                        noise_suppression: false,
+                        automatic_gain_control: SwitchDefault {
+                            enabled: false,
+                            content: AutomaticGainControlConfigDefault {
+                                target_level_dbfs: -18.0,
+                                max_gain_db: 20.0,
+                            },
+                        },
+                        echo_cancellation: false,
+                    },
                 },
             },
         },
@@ -1085,10 +1322,6 @@ pub fn session_settings_default() -> SettingsDefault {
                     tracked: true,
                     extra_openvr_props: default_custom_openvr_props,
                     steamvr_pipeline_frames: 3.0,
-                    linear_velocity_cutoff: 0.05,
-                    angular_velocity_cutoff: 10.0,
-                    left_controller_position_offset: [0.0, 0.0, -0.11],
-                    left_controller_rotation_offset: [-20.0, 0.0, 0.0],
                     left_hand_tracking_position_offset: [0.04, -0.02, -0.13],
                     left_hand_tracking_rotation_offset: [0.0, -45.0, -90.0],
                     trigger_threshold_override: SwitchDefault {
@@ -1109,6 +1342,70 @@ pub fn session_settings_default() -> SettingsDefault {
                     },
                 },
             },
+            device_motions: {
+                fn motion_default(
+                    position_offset: [f32; 3],
+                    rotation_offset: [f32; 3],
+                    linear_velocity_cutoff: f32,
+                    angular_velocity_cutoff: f32,
+                ) -> DeviceMotionConfigDefault {
+                    DeviceMotionConfigDefault {
+                        position_offset,
+                        rotation_offset,
+                        linear_velocity_cutoff,
+                        angular_velocity_cutoff,
+                        smoothing: SwitchDefault {
+                            enabled: false,
+                            content: MotionSmoothingConfigDefault {
+                                OneEuro: MotionSmoothingConfigOneEuroDefault {
+                                    min_cutoff_hz: 1.0,
+                                    beta: 0.5,
+                                },
+                                Kalman: MotionSmoothingConfigKalmanDefault {
+                                    acceleration_noise: 10.0,
+                                    position_noise: 0.001,
+                                    angular_velocity_noise: 30.0,
+                                    orientation_noise: 0.2,
+                                },
+                                variant: MotionSmoothingConfigDefaultVariant::OneEuro,
+                            },
+                        },
+                    }
+                }
+                let controller_default =
+                    || motion_default([0.0, 0.0, -0.11], [-20.0, 0.0, 0.0], 0.05, 10.0);
+
+                DictionaryDefault {
+                    key: "".into(),
+                    value: motion_default([0.0; 3], [0.0; 3], 0.0, 0.0),
+                    content: vec![
+                        (
+                            HEAD_PATH.into(),
+                            motion_default([0.0; 3], [0.0; 3], 0.0, 0.0),
+                        ),
+                        (LEFT_HAND_PATH.into(), controller_default()),
+                        (RIGHT_HAND_PATH.into(), controller_default()),
+                    ],
+                }
+            },
+            pose_prediction: SwitchDefault {
+                enabled: false,
+                content: PosePredictionConfigDefault {
+                    strategy: PosePredictionStrategyDefault {
+                        Damped: PosePredictionStrategyDampedDefault {
+                            velocity_half_life_ms: 50.0,
+                        },
+                        variant: PosePredictionStrategyDefaultVariant::ConstantVelocity,
+                    },
+                    max_prediction_ms: 100.0,
+                    head: SwitchDefault {
+                        enabled: false,
+                        content: HeadPosePredictionConfigDefault {
+                            runtime_prediction_ms: 0.0,
+                        },
+                    },
+                },
+            },
             position_recentering_mode: PositionRecenteringModeDefault {
                 Local: PositionRecenteringModeLocalDefault { view_height: 1.5 },
                 variant: PositionRecenteringModeDefaultVariant::LocalFloor,
@@ -1128,6 +1425,7 @@ pub fn session_settings_default() -> SettingsDefault {
                 },
             },
             web_server_port: 8082,
+            web_server_bind_address: "127.0.0.1".into(),
             stream_port: 9944,
             osc_local_port: 9942,
             server_send_buffer_bytes: socket_buffer.clone(),
@@ -1144,6 +1442,14 @@ pub fn session_settings_default() -> SettingsDefault {
         },
         logging: LoggingConfigDefault {
             log_to_disk: cfg!(debug_assertions),
+            log_files: LogFilesConfigDefault {
+                format: LogFileFormatDefault {
+                    variant: LogFileFormatDefaultVariant::Text,
+                },
+                max_file_size_mb: 20,
+                max_file_count: 20,
+            },
+            record_statistics: false,
             log_button_presses: false,
             log_tracking: false,
             log_haptics: false,
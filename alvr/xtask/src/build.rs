@@ -29,6 +29,7 @@ impl Display for Profile {
 pub fn build_streamer(
     profile: Profile,
     gpl: bool,
+    pipewire: bool,
     root: Option<String>,
     reproducible: bool,
     keep_config: bool,
@@ -76,11 +77,17 @@ pub fn build_streamer(
     // build server
     {
         let gpl_flag = gpl.then(|| vec!["--features", "gpl"]).unwrap_or_default();
+        let pipewire_flag = pipewire
+            .then(|| vec!["--features", "pipewire"])
+            .unwrap_or_default();
 
         let _push_guard = sh.push_dir(afs::crate_dir("server"));
-        cmd!(sh, "cargo build {common_flags_ref...} {gpl_flag...}")
-            .run()
-            .unwrap();
+        cmd!(
+            sh,
+            "cargo build {common_flags_ref...} {gpl_flag...} {pipewire_flag...}"
+        )
+        .run()
+        .unwrap();
 
         sh.copy_file(
             artifacts_dir.join(afs::dynlib_fname("alvr_server")),
@@ -111,6 +118,18 @@ pub fn build_streamer(
         .unwrap();
     }
 
+    // Build CLI
+    {
+        let _push_guard = sh.push_dir(afs::crate_dir("cli"));
+        cmd!(sh, "cargo build {common_flags_ref...}").run().unwrap();
+
+        sh.copy_file(
+            artifacts_dir.join(afs::exec_fname("alvr_cli")),
+            build_layout.cli_exe(),
+        )
+        .unwrap();
+    }
+
     // copy dependencies
     if cfg!(windows) {
         command::copy_recursive(
@@ -37,6 +37,7 @@ FLAGS:
     --no-nvidia         Disables nVidia support on Linux. For prepare-deps subcommand
     --release           Optimized build with less debug checks. For build subcommands
     --gpl               Bundle GPL libraries (FFmpeg). Only for Windows
+    --pipewire          Build the native PipeWire audio backend. Only for Linux
     --appimage          Package as AppImage. For package-streamer subcommand
     --zsync             For --appimage, create .zsync update file and build AppImage with embedded update information. For package-streamer subcommand
     --nightly           Append nightly tag to versions. For bump subcommand
@@ -145,6 +146,7 @@ fn main() {
             Profile::Debug
         };
         let gpl = args.contains("--gpl");
+        let pipewire = args.contains("--pipewire");
         let is_nightly = args.contains("--nightly");
         let no_rebuild = args.contains("--no-rebuild");
         let for_ci = args.contains("--ci");
@@ -177,12 +179,14 @@ fn main() {
                         dependencies::build_android_deps(for_ci);
                     }
                 }
-                "build-streamer" => build::build_streamer(profile, gpl, None, false, keep_config),
+                "build-streamer" => {
+                    build::build_streamer(profile, gpl, pipewire, None, false, keep_config)
+                }
                 "build-client" => build::build_android_client(profile),
                 "build-client-lib" => build::build_client_lib(profile, link_stdcpp),
                 "run-streamer" => {
                     if !no_rebuild {
-                        build::build_streamer(profile, gpl, None, false, keep_config);
+                        build::build_streamer(profile, gpl, pipewire, None, false, keep_config);
                     }
                     run_streamer();
                 }
@@ -50,7 +50,7 @@ fn build_windows_installer() {
 pub fn package_streamer(gpl: bool, root: Option<String>, appimage: bool, zsync: bool) {
     let sh = Shell::new().unwrap();
 
-    build::build_streamer(Profile::Distribution, gpl, root, true, false);
+    build::build_streamer(Profile::Distribution, gpl, false, root, true, false);
 
     // Add licenses
     let licenses_dir = afs::streamer_build_dir().join("licenses");